@@ -2,22 +2,152 @@ use std::env;
 use std::fs;
 use std::path::Path;
 
-use isa_gen::emitter::strategies::latency::LatencyOptimizedCodeEmitter;
+use isa_gen::emitter::options::{Options, ENV, FREQUENCIES_ENV};
+use isa_gen::emitter::semantics::{self, Library, Report};
+use isa_gen::emitter::strategies::latency::{LatencyOptimizedCodeEmitter, Tree};
+use isa_gen::emitter::strategies::latency::graph;
+use isa_gen::emitter::strategies::pext::{self, PextCodeEmitter};
+use isa_gen::emitter::strategies::portable::PortableCodeEmitter;
 use isa_gen::emitter::traits::CodeEmitter;
+use isa_gen::fetcher::arm::{FetchError, SpecSource, SPEC_A64_ENV, SPEC_ENV};
+use isa_gen::ir::Isa;
 
-fn main() {
-    let out_dir = env::var_os("OUT_DIR").unwrap();
-
-    let dest_path = Path::new(&out_dir).join("a32.rs");
-    let tokens = LatencyOptimizedCodeEmitter::emit();
-
+fn write_tokens(path: &Path, tokens: proc_macro2::TokenStream) {
     let syntax_tree = syn::parse2(tokens)
         .expect("Generated code is not valid Rust");
 
     fs::write(
-        &dest_path,
+        path,
         prettyplease::unparse(&syntax_tree)
     ).unwrap();
+}
+
+fn track_source(var: &str, source: Result<SpecSource, FetchError>) {
+    println!("cargo::rerun-if-env-changed={var}");
+    if let Ok(source) = source {
+        match source.local_path() {
+            Some(path) => println!("cargo::rerun-if-changed={}", path.display()),
+            None if source.pin().is_none() => {
+                println!("cargo::warning=The ISA specification release is not pinned in isa-gen/spec.lock");
+            }
+            None => {}
+        }
+    }
+}
+
+fn main() {
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    let a64 = env::var_os("CARGO_FEATURE_A64").is_some();
+    let with_semantics = env::var_os("CARGO_FEATURE_SEMANTICS").is_some();
+    let pext = env::var_os("CARGO_FEATURE_PEXT").is_some();
+    let portable = env::var_os("CARGO_FEATURE_PORTABLE").is_some();
+    let artifacts = env::var_os("CARGO_FEATURE_ARTIFACTS").is_some();
+    // The tables are walked with AVX-512/BMI2 code, anywhere else the tree is plain Rust.
+    let x86 = env::var("CARGO_CFG_TARGET_ARCH").unwrap() == "x86_64";
+
+    for var in ENV {
+        println!("cargo::rerun-if-env-changed={var}");
+    }
+    if let Some(path) = env::var_os(FREQUENCIES_ENV).filter(|path| !path.is_empty()) {
+        println!("cargo::rerun-if-changed={}", Path::new(&path).display());
+    }
+    let options = Options {
+        // PEXT nodes aren't bound to the 16 ways of an `Entry`.
+        budget: if x86 && pext { pext::BUDGET } else { graph::BUDGET },
+        ..Options::from_env().unwrap_or_else(|e| panic!("{e}"))
+    };
+
+    track_source(SPEC_ENV, SpecSource::aarch32());
+    if a64 {
+        track_source(SPEC_A64_ENV, SpecSource::a64());
+    }
+
+    let out_dir = Path::new(&out_dir);
+    let mut warnings = vec![];
+    let mut depths = vec![];
+    // Every strategy emits the same `InstructionView`, only the tables and their walk differ.
+    let emit = match (x86, pext) {
+        (false, _) => PortableCodeEmitter::emit,
+        (true, true) => PextCodeEmitter::emit,
+        (true, false) => LatencyOptimizedCodeEmitter::emit,
+    };
+    // A release is parsed once, every pass and the semantics of its ISAs take the same encodings.
+    let mut releases = vec![(Isa::A32, vec![(Isa::A32, "a32"), (Isa::T32, "t32")])];
+    if a64 {
+        releases.push((Isa::A64, vec![(Isa::A64, "a64")]));
+    }
+    let loaded = releases
+        .iter()
+        .map(|(release, _)| Options { isa: *release, ..options.clone() }.load(&mut warnings))
+        .collect::<Vec<_>>();
+    for ((_, isas), instructions) in releases.iter().zip(&loaded) {
+        for &(isa, name) in isas {
+            let options = Options { isa, ..options.clone() };
+            // Built once, the portable code and the artifact walk the same tree as the tables.
+            let tree = Tree::build(&options, instructions);
+            let mut report = tree.depths();
+            write_tokens(&out_dir.join(format!("{name}.rs")), emit(&options, &tree, &mut report));
+            if portable && x86 {
+                let tokens = PortableCodeEmitter::emit(&options, &tree, &mut report);
+                write_tokens(&out_dir.join(format!("{name}_portable.rs")), tokens);
+            }
+            if artifacts && options.budget == graph::BUDGET {
+                // The `Entry` tables whatever the strategy, that's what `decoder::tables` walks.
+                let bytes = LatencyOptimizedCodeEmitter::emit_artifact(&options, &tree, &mut report);
+                fs::write(out_dir.join(format!("{name}.xdt")), bytes).unwrap();
+            }
+            depths.push(report);
+            if artifacts && options.budget != graph::BUDGET {
+                // PEXT lookups are wider than an `Entry` takes, the artifact has a tree of its own.
+                let options = Options { budget: graph::BUDGET, ..options.clone() };
+                let tree = Tree::build(&options, instructions);
+                let mut report = tree.depths();
+                let bytes = LatencyOptimizedCodeEmitter::emit_artifact(&options, &tree, &mut report);
+                fs::write(out_dir.join(format!("{name}.xdt")), bytes).unwrap();
+                depths.push(report);
+            }
+        }
+    }
+    let depths = depths.iter().map(|d| format!("{d}\n")).collect::<String>();
+    fs::write(out_dir.join("decode_depth_report.txt"), &depths).unwrap();
+    // Only worth the noise when asked to balance on a histogram.
+    if !options.frequencies.is_empty() {
+        for line in depths.lines() {
+            println!("cargo::warning={line}");
+        }
+    }
+    // Encodings the parser couldn't make sense of are left out, the rest still decodes.
+    for warning in &warnings {
+        println!("cargo::warning={warning}");
+    }
+
+    if with_semantics {
+        let mut report = Report::default();
+
+        let aarch32 = Library::load(&Options { isa: Isa::A32, ..options.clone() }, &mut report);
+        write_tokens(&out_dir.join("aarch32_library.rs"), aarch32.emit(&mut report));
+        for (isa, file) in [(Isa::A32, "a32_execute.rs"), (Isa::T32, "t32_execute.rs")] {
+            let tokens = semantics::emit_isa(&loaded[0], isa, options.classes, &options.profile, &aarch32, "crate::semantics::aarch32", &mut report);
+            write_tokens(&out_dir.join(file), tokens);
+        }
+        if a64 {
+            let aarch64 = Library::load(&Options { isa: Isa::A64, ..options.clone() }, &mut report);
+            write_tokens(&out_dir.join("a64_library.rs"), aarch64.emit(&mut report));
+            let tokens = semantics::emit_isa(&loaded[1], Isa::A64, options.classes, &options.profile, &aarch64, "crate::semantics::aarch64", &mut report);
+            write_tokens(&out_dir.join("a64_execute.rs"), tokens);
+        }
+
+        let report_path = out_dir.join("semantics_report.txt");
+        fs::write(&report_path, report.to_string()).unwrap();
+        if !report.unsupported.is_empty() {
+            println!(
+                "cargo::warning={} of {} generated functions use unsupported pseudocode, see {}",
+                report.unsupported.len(),
+                report.generated,
+                report_path.display()
+            );
+        }
+    }
 
     println!("cargo::rerun-if-changed=build.rs");
 }
@@ -93,7 +93,7 @@ fn start_test() {
     let iterations: usize = 1_000_000_000 / 1;
 
     for _ in 0..iterations {
-        let v = black_box(unsafe { scalar_decode(0b00000010110000000000000000000000) } );
+        let v = black_box(unsafe { scalar_decode(0b00000010110000000000000000000000) }.unwrap_or_else(DecodeError::leaf));
         let b: InstructionView = unsafe { core::mem::transmute(v) };
         println!("{b:?}");
         //black_box(unsafe { semi_vectorized_decode(words_512, x_512) } );
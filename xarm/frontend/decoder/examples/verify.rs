@@ -0,0 +1,58 @@
+//! Checks the generated tables word by word against the encodings of the specification.
+//!
+//! `cargo run --release --example verify -- [a32|t32|a64] [all | sample <per stratum> [seed]]`
+//!
+//! Run it with the same `XARM_ISA_*` variables as the build, or the leaves won't line up.
+
+use decoder::DecodeError;
+use isa_gen::emitter::options::Options;
+use isa_gen::emitter::strategies::latency;
+use isa_gen::ir::Isa;
+use isa_gen::verify::{self, Reference, Words};
+
+fn usage() -> ! {
+    eprintln!("usage: verify [a32|t32|a64] [all | sample <per stratum> [seed]]");
+    std::process::exit(2)
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let isa = match args.first().map(String::as_str) {
+        None | Some("a32") => Isa::A32,
+        Some("t32") => Isa::T32,
+        Some("a64") => Isa::A64,
+        Some(_) => usage(),
+    };
+    let words = match args.get(1).map(String::as_str) {
+        None | Some("all") => Words::All,
+        Some("sample") => {
+            let per_stratum = args.get(2).and_then(|n| n.parse().ok()).unwrap_or_else(|| usage());
+            let seed = args.get(3).map_or(Some(1), |s| s.parse().ok()).unwrap_or_else(|| usage());
+            Words::Sampled { per_stratum, seed }
+        }
+        Some(_) => usage(),
+    };
+
+    let options = Options { isa, ..Options::from_env().unwrap_or_else(|e| panic!("{e}")) };
+    let instructions = options.load(&mut vec![]);
+    let (all, implemented) = latency::select(&instructions, isa, options.classes, &options.profile);
+    let reference = Reference::new(&all, &implemented);
+
+    // The reference checks leaves, errors go back to the reserved leaf they were decoded from.
+    let leaf = |decoded: Result<u16, DecodeError>| decoded.unwrap_or_else(DecodeError::leaf);
+    let report = match isa {
+        Isa::A32 => verify::verify(&reference, words, |word| leaf(unsafe { decoder::scalar_decode(word) })),
+        Isa::T32 => verify::verify(&reference, words, |word| unsafe {
+            leaf(decoder::t32::scalar_decode((word >> 16) as u16, word as u16).0)
+        }),
+        #[cfg(feature = "a64")]
+        Isa::A64 => verify::verify(&reference, words, |word| leaf(unsafe { decoder::a64::scalar_decode(word) })),
+        #[cfg(not(feature = "a64"))]
+        Isa::A64 => panic!("the A64 table needs the `a64` feature"),
+    };
+
+    println!("{report}");
+    if !report.disagreements.is_empty() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,149 @@
+//! Layout of the table artifacts, the `Entry` pool of an instruction set written out to be
+//! loaded at runtime instead of compiled in.
+//!
+//! Everything is little endian:
+//!
+//! | offset            | size                 |                                                |
+//! |-------------------|----------------------|------------------------------------------------|
+//! | 0                 | [`HEADER_LEN`]       | [`Header`], zero padded                        |
+//! | [`HEADER_LEN`]    | `entries * Entry::LEN` | the `Entry` pool, as laid out in memory      |
+//! | after the pool    | `names_len`          | the encoding name of every leaf from `Descriptor::RESERVED`, each terminated by a NUL |
+
+use crate::{Entry, Word};
+
+pub const MAGIC: [u8; 4] = *b"XDT\0";
+/// Bumped whenever the layout changes, loaders reject any other. 2 reserves leaves 1 and 2 for
+/// UNPREDICTABLE words, 3 records the geometry of the entries.
+pub const VERSION: u16 = 3;
+/// The header is padded to a cache line so the pool is as aligned as the artifact.
+pub const HEADER_LEN: usize = 64;
+/// Bytes of the default `Entry`.
+pub const ENTRY_LEN: usize = Entry::<4, 16, u16>::LEN;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Isa {
+    A32 = 0,
+    T32 = 1,
+    A64 = 2
+}
+
+impl Isa {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::A32),
+            1 => Some(Self::T32),
+            2 => Some(Self::A64),
+            _ => None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    /// Raw, see [`Isa::from_u8`].
+    pub isa: u8,
+    /// Masks of an `Entry`, it has `1 << masks` ways.
+    pub masks: u8,
+    /// Bytes of a `Descriptor`.
+    pub descriptor: u8,
+    pub root: u32,
+    pub entries: u32,
+    /// Leaves besides the reserved ones, one name each.
+    pub leaves: u32,
+    pub names_len: u32
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6] = self.isa;
+        bytes[7] = self.masks;
+        bytes[8..12].copy_from_slice(&self.root.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.entries.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.leaves.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.names_len.to_le_bytes());
+        bytes[24] = self.descriptor;
+        bytes
+    }
+
+    /// The header of `bytes`, `None` without the magic. Nothing else is checked.
+    pub fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Option<Self> {
+        if bytes[0..4] != MAGIC {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(Self {
+            version: u16_at(4),
+            isa: bytes[6],
+            masks: bytes[7],
+            descriptor: bytes[24],
+            root: u32_at(8),
+            entries: u32_at(12),
+            leaves: u32_at(16),
+            names_len: u32_at(20)
+        })
+    }
+
+    /// The header of a pool of `Entry<MASKS, WAYS, W>`, the counts left at zero.
+    pub fn for_entry<const MASKS: usize, const WAYS: usize, W: Word>(isa: Isa) -> Self {
+        Self {
+            version: VERSION,
+            isa: isa as u8,
+            masks: MASKS as u8,
+            descriptor: size_of::<W>() as u8,
+            root: 0,
+            entries: 0,
+            leaves: 0,
+            names_len: 0
+        }
+    }
+
+    /// Whether the pool is made of `Entry<MASKS, WAYS, W>`.
+    pub fn is_entry<const MASKS: usize, const WAYS: usize, W: Word>(&self) -> bool {
+        self.masks as usize == MASKS && self.descriptor as usize == size_of::<W>()
+    }
+
+    /// Bytes of an `Entry`, padded to the cache line it is aligned to.
+    pub fn entry_len(&self) -> usize {
+        let masks = self.masks as usize;
+        (masks * 8 + ((self.descriptor as usize) << masks)).next_multiple_of(64)
+    }
+
+    /// Bytes of the whole artifact.
+    pub fn artifact_len(&self) -> usize {
+        HEADER_LEN + self.entries as usize * self.entry_len() + self.names_len as usize
+    }
+}
+
+impl<const MASKS: usize, const WAYS: usize, W: Word> Entry<MASKS, WAYS, W> {
+    /// Writes the entry into the first [`Self::LEN`] bytes, masks, expected values and
+    /// descriptors in order and zero padded.
+    pub fn write_le_bytes(&self, bytes: &mut [u8]) {
+        let bytes = &mut bytes[..Self::LEN];
+        bytes.fill(0);
+        for i in 0..MASKS {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&self.bitmasks[i].to_le_bytes());
+            bytes[(MASKS + i) * 4..(MASKS + i) * 4 + 4].copy_from_slice(&self.expected[i].to_le_bytes());
+        }
+        let width = size_of::<W>();
+        for (i, descriptor) in self.entries.iter().enumerate() {
+            descriptor.0.write_le(&mut bytes[MASKS * 8 + i * width..]);
+        }
+    }
+
+    /// Reads the entry [`Self::write_le_bytes`] wrote at the start of `bytes`.
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let width = size_of::<W>();
+        Self {
+            bitmasks: core::array::from_fn(|i| u32_at(i * 4)),
+            expected: core::array::from_fn(|i| u32_at((MASKS + i) * 4)),
+            entries: core::array::from_fn(|i| crate::Descriptor(W::read_le(&bytes[MASKS * 8 + i * width..])))
+        }
+    }
+}
@@ -1,8 +1,92 @@
 #![no_std]
 
-#[derive(Copy, Clone, Debug)]
+#[cfg(feature = "semantics")]
+extern crate alloc;
+
+#[cfg(feature = "semantics")]
+pub mod semantics;
+pub mod artifact;
+
+/// The integer a [`Descriptor`] is stored in, `u16` by default. The top bit tags an entry, the
+/// others hold the index of the entry or the leaf.
+pub trait Word:
+    Copy + Eq + Default + core::hash::Hash + core::fmt::Debug
+    + core::ops::BitAnd<Output = Self> + core::ops::BitOr<Output = Self>
+{
+    const TAG_ENTRY: Self;
+    const MASK_DATA: Self;
+
+    /// `None` when `value` doesn't fit below the tag.
+    fn from_usize(value: usize) -> Option<Self>;
+    fn to_usize(self) -> usize;
+    /// Stores `self` little endian at the start of `bytes`.
+    fn write_le(self, bytes: &mut [u8]);
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($ty:ty),*) => {$(
+        impl Word for $ty {
+            const TAG_ENTRY: Self = 1 << (<$ty>::BITS - 1);
+            const MASK_DATA: Self = !Self::TAG_ENTRY;
+
+            fn from_usize(value: usize) -> Option<Self> {
+                Self::try_from(value).ok().filter(|value| value & Self::TAG_ENTRY == 0)
+            }
+
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+
+            fn write_le(self, bytes: &mut [u8]) {
+                bytes[..size_of::<Self>()].copy_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                Self::from_le_bytes(bytes[..size_of::<Self>()].try_into().unwrap())
+            }
+        }
+    )*};
+}
+
+impl_word!(u16, u32);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[derive(Default)]
-pub struct Descriptor(pub u16);
+pub struct Descriptor<W = u16>(pub W);
+
+impl<W: Word> Descriptor<W> {
+    pub fn new_entry(offset: W) -> Self {
+        debug_assert!(offset & W::MASK_DATA == offset);
+        Self(W::TAG_ENTRY | (offset & W::MASK_DATA))
+    }
+
+    pub fn new_leaf(id: W) -> Self {
+        // ZII - Zero is a leaf.
+        debug_assert!(id & W::MASK_DATA == id);
+        Self(id & W::MASK_DATA)
+    }
+
+    pub fn new_invalid() -> Self {
+        Self(W::default())
+    }
+
+    pub fn is_entry(self) -> bool {
+        self.0 & W::TAG_ENTRY == W::TAG_ENTRY
+    }
+
+    /// The index of the entry or the leaf.
+    pub fn data(self) -> usize {
+        (self.0 & W::MASK_DATA).to_usize()
+    }
+
+    /// The same descriptor stored in a `V`, `None` when the index doesn't fit.
+    pub fn to_width<V: Word>(self) -> Option<Descriptor<V>> {
+        let data = V::from_usize(self.data())?;
+        Some(if self.is_entry() { Descriptor::new_entry(data) } else { Descriptor::new_leaf(data) })
+    }
+}
 
 // TODO: Descriptor entries are either an entry or not.
 // And naming is bad.
@@ -12,26 +96,115 @@ impl Descriptor {
 
     pub const MASK_DATA: u16 = 0x7FFF;
 
-    pub fn new_entry(offset: u16) -> Self {
-        debug_assert!(offset <= Self::MASK_DATA);
-        Self(Self::TAG_ENTRY | (offset & Self::MASK_DATA))
+    /// Leaf 0, the `Undefined` member every generated `InstructionView` starts with.
+    pub const UNDEFINED: u16 = 0;
+    /// Leaf 1, words the decode pseudocode of their encoding calls UNPREDICTABLE.
+    pub const UNPREDICTABLE: u16 = 1;
+    /// Leaf 2, UNPREDICTABLE words whose behaviour the architecture constrains to a few choices.
+    pub const CONSTRAINED_UNPREDICTABLE: u16 = 2;
+    /// Leaves below are reserved, leaf `RESERVED + n` is the `n`-th implemented encoding.
+    pub const RESERVED: u16 = 3;
+
+    /// How the specification spells a reserved leaf, `None` for encodings.
+    pub const fn reserved_name(leaf: u16) -> Option<&'static str> {
+        match leaf {
+            Self::UNDEFINED => Some("UNDEFINED"),
+            Self::UNPREDICTABLE => Some("UNPREDICTABLE"),
+            Self::CONSTRAINED_UNPREDICTABLE => Some("CONSTRAINED UNPREDICTABLE"),
+            _ => None
+        }
     }
+}
 
-    pub fn new_leaf(id: u16) -> Self {
-        // ZII - Zero is a leaf.
-        debug_assert!(id <= Self::MASK_DATA);
-        Self(id & Self::MASK_DATA)
+/// A node of the tables, `MASKS` compares of the word picking one of `WAYS` descriptors: the
+/// `i`-th sets bit `i` of the index when the word has `expected[i]` under `bitmasks[i]`.
+///
+/// `WAYS` has to be `1 << MASKS`. The default is the 64-byte cache line of 4 masks and 16 `u16`
+/// descriptors, see [`Self::CACHE_LINE`], wider ones take up to 8 masks and 256 ways or `u32`
+/// descriptors for pools beyond `0x7FFF` entries.
+#[repr(C, align(64))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entry<const MASKS: usize = 4, const WAYS: usize = 16, W = u16> {
+    pub bitmasks: [u32; MASKS],
+    pub expected: [u32; MASKS],
+    pub entries: [Descriptor<W>; WAYS]
+}
+
+impl<const MASKS: usize, const WAYS: usize, W: Word> Default for Entry<MASKS, WAYS, W> {
+    fn default() -> Self {
+        Self { bitmasks: [0; MASKS], expected: [0; MASKS], entries: [Descriptor::default(); WAYS] }
+    }
+}
+
+impl<const MASKS: usize, const WAYS: usize, W: Word> Entry<MASKS, WAYS, W> {
+    const GEOMETRY: () = assert!(MASKS <= 8 && WAYS == 1 << MASKS, "an `Entry` has `1 << MASKS` ways");
+
+    /// Bytes of an entry, in memory as in an artifact.
+    pub const LEN: usize = size_of::<Self>();
+    /// Whether this is the entry of 4 masks and 16 `u16` descriptors filling a cache line, the
+    /// one the SIMD walks of `decoder` load whole.
+    pub const CACHE_LINE: bool = MASKS == 4 && WAYS == 16 && size_of::<W>() == 2;
+
+    /// The slot `word` takes, without a branch.
+    #[inline(always)]
+    pub fn index(&self, word: u32) -> usize {
+        let () = Self::GEOMETRY;
+        let mut index = 0;
+        for i in 0..MASKS {
+            index |= ((self.bitmasks[i] & word == self.expected[i]) as usize) << i;
+        }
+        index
     }
 
-    pub fn new_invalid() -> Self {
-        Self(0)
+    /// The leaf `word` ends at from the entry at `root` of `pool`.
+    pub fn walk(pool: &[Self], root: W, word: u32) -> W {
+        let mut entry = &pool[root.to_usize()];
+        loop {
+            let descriptor = entry.entries[entry.index(word)];
+            if !descriptor.is_entry() {
+                return descriptor.0;
+            }
+            entry = &pool[descriptor.data()];
+        }
+    }
+
+    /// [`Self::walk`] without bounds checks.
+    ///
+    /// # Safety
+    ///
+    /// `root` and every entry descriptor reachable from it have to be inside `pool`, and no walk
+    /// may cycle.
+    #[inline(always)]
+    pub unsafe fn walk_unchecked(pool: &[Self], root: W, word: u32) -> W {
+        unsafe {
+            let mut entry = pool.get_unchecked(root.to_usize());
+            loop {
+                let descriptor = *entry.entries.get_unchecked(entry.index(word));
+                if !descriptor.is_entry() {
+                    return descriptor.0;
+                }
+                entry = pool.get_unchecked(descriptor.data());
+            }
+        }
+    }
+
+    /// The same entry with `V` descriptors, `None` when one of them doesn't fit.
+    pub fn to_width<V: Word>(&self) -> Option<Entry<MASKS, WAYS, V>> {
+        let mut entries = [Descriptor::<V>::default(); WAYS];
+        for (to, from) in entries.iter_mut().zip(&self.entries) {
+            *to = from.to_width()?;
+        }
+        Some(Entry { bitmasks: self.bitmasks, expected: self.expected, entries })
     }
 }
 
-#[repr(C, align(64))]
-#[derive(Default)]
-pub struct Entry {
-    pub bitmasks: [u32; 4],
-    pub expected: [u32; 4],
-    pub entries: [Descriptor; 16]
+/// A node of the PEXT tables. The bits of the word under `mask` index the children starting at
+/// `base`, or with `compare` set, whether the word has `value` under `mask` picks one of two.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PextNode {
+    pub mask: u32,
+    pub value: u32,
+    pub base: u32,
+    pub compare: bool
 }
@@ -0,0 +1,461 @@
+//! Runtime for the execute functions generated from the ASL pseudocode.
+//!
+//! ASL is typed but the generator doesn't infer types, every value is a [`Value`] and the
+//! operations check their operands when they run.
+
+use alloc::vec::Vec;
+
+/// Functions implemented here instead of being generated from the shared pseudocode.
+pub const BUILTINS: &[&str] = &[
+    "UInt", "SInt", "Int", "ZeroExtend", "SignExtend", "Zeros", "Ones", "Replicate",
+    "IsZero", "IsZeroBit", "IsOnes", "IsOnesBit", "Align", "NOT",
+    "LSL", "LSR", "ASR", "ROR", "LSL_C", "LSR_C", "ASR_C", "ROR_C",
+    "Min", "Max", "Abs", "BitCount", "CountLeadingZeroBits", "HighestSetBit", "LowestSetBit",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bits {
+    value: u128,
+    width: u32
+}
+
+impl Bits {
+    pub const MAX_WIDTH: u32 = 128;
+
+    pub const fn new(value: u128, width: u32) -> Self {
+        Bits { value: value & Self::mask(width), width }
+    }
+
+    pub const fn value(self) -> u128 {
+        self.value
+    }
+
+    pub const fn width(self) -> u32 {
+        self.width
+    }
+
+    const fn mask(width: u32) -> u128 {
+        if width >= 128 { u128::MAX } else { (1 << width) - 1 }
+    }
+
+    fn signed(self) -> i128 {
+        if self.width == 0 {
+            return 0;
+        }
+        let shift = 128 - self.width;
+        ((self.value << shift) as i128) >> shift
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Declared but never assigned, or the result of a procedure.
+    Unset,
+    Bool(bool),
+    Int(i128),
+    Bits(Bits),
+    Real(f64),
+    Str(&'static str),
+    /// A constant of an ASL enumeration, e.g. `BranchType_DIR`.
+    Enum(&'static str),
+    Tuple(Vec<Value>)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Exception {
+    Undefined,
+    Unpredictable,
+    /// The encoding belongs to another instruction, decode it as that one.
+    See(&'static str),
+    AssertionFailed,
+    /// The pseudocode uses something the generator or this runtime doesn't support.
+    Unsupported(&'static str),
+    /// An operation got operands of the wrong type.
+    Type(&'static str),
+    /// Raised by a [`Cpu`] implementation, aborts, exceptions taken and so on.
+    Cpu(&'static str)
+}
+
+pub type Result<T> = core::result::Result<T, Exception>;
+
+/// CPU state the generated code runs against. Registers, memory and system registers of the
+/// pseudocode are all reached through it: `R[n]` is `read_array("R", [n])`, `PSTATE.C` is
+/// `read_field("PSTATE", "C")`, and functions that are neither builtins nor generated from the
+/// shared pseudocode end up in `call`.
+pub trait Cpu {
+    fn read(&mut self, name: &str) -> Result<Value>;
+    fn write(&mut self, name: &str, value: Value) -> Result<()>;
+    fn read_field(&mut self, name: &str, field: &str) -> Result<Value>;
+    fn write_field(&mut self, name: &str, field: &str, value: Value) -> Result<()>;
+    fn read_array(&mut self, name: &str, index: &[Value]) -> Result<Value>;
+    fn write_array(&mut self, name: &str, index: &[Value], value: Value) -> Result<()>;
+    fn call(&mut self, name: &str, args: &[Value]) -> Result<Value>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add, Sub, Mul, Div, IntDiv, Mod, Pow, Shl, Shr,
+    And, Or, Eor, BoolAnd, BoolOr,
+    Eq, Ne, Lt, Le, Gt, Ge,
+    Concat
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    BoolNot,
+    Not
+}
+
+impl Value {
+    pub const fn bits(value: u128, width: u32) -> Value {
+        Value::Bits(Bits::new(value, width))
+    }
+
+    pub fn tuple<const N: usize>(values: [Value; N]) -> Value {
+        Value::Tuple(Vec::from(values))
+    }
+
+    pub fn truthy(&self) -> Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(Exception::Type("expected a boolean"))
+        }
+    }
+
+    pub fn int(&self) -> Result<i128> {
+        match self {
+            Value::Int(i) => Ok(*i),
+            _ => Err(Exception::Type("expected an integer"))
+        }
+    }
+
+    pub fn as_bits(&self) -> Result<Bits> {
+        match self {
+            Value::Bits(b) => Ok(*b),
+            _ => Err(Exception::Type("expected a bitvector"))
+        }
+    }
+
+    pub fn width(&self) -> Result<i128> {
+        Ok(self.as_bits()?.width as i128)
+    }
+
+    pub fn into_tuple(self, len: usize) -> Result<Vec<Value>> {
+        match self {
+            Value::Tuple(values) if values.len() == len => Ok(values),
+            _ => Err(Exception::Type("expected a tuple"))
+        }
+    }
+}
+
+fn width(value: &Value) -> Result<u32> {
+    let width = value.int()?;
+    if !(0..=Bits::MAX_WIDTH as i128).contains(&width) {
+        return Err(Exception::Unsupported("bitvector wider than 128 bits"));
+    }
+    Ok(width as u32)
+}
+
+fn floor_div(a: i128, b: i128) -> Result<i128> {
+    if b == 0 {
+        return Err(Exception::Type("division by zero"));
+    }
+    let q = a / b;
+    Ok(if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q })
+}
+
+pub fn binary(op: BinOp, a: Value, b: Value) -> Result<Value> {
+    use Value::*;
+
+    Ok(match (op, a, b) {
+        (BinOp::Eq, a, b) => Bool(equals(&a, &b)?),
+        (BinOp::Ne, a, b) => Bool(!equals(&a, &b)?),
+        (BinOp::BoolAnd, a, b) => Bool(a.truthy()? && b.truthy()?),
+        (BinOp::BoolOr, a, b) => Bool(a.truthy()? || b.truthy()?),
+
+        (BinOp::Add, Int(a), Int(b)) => Int(a.wrapping_add(b)),
+        (BinOp::Sub, Int(a), Int(b)) => Int(a.wrapping_sub(b)),
+        (BinOp::Mul, Int(a), Int(b)) => Int(a.wrapping_mul(b)),
+        (BinOp::Div, Int(a), Int(b)) => {
+            if b == 0 || a % b != 0 {
+                return Err(Exception::Type("inexact integer division"));
+            }
+            Int(a / b)
+        }
+        (BinOp::IntDiv, Int(a), Int(b)) => Int(floor_div(a, b)?),
+        (BinOp::Mod, Int(a), Int(b)) => Int(a - b * floor_div(a, b)?),
+        (BinOp::Pow, Int(a), Int(b)) => Int(a.wrapping_pow(u32::try_from(b).map_err(|_| Exception::Type("negative exponent"))?)),
+        (BinOp::Shl | BinOp::Shr, Int(_), Int(b)) if !(0..128).contains(&b) => return Err(Exception::Type("invalid shift")),
+        (BinOp::Shl, Int(a), Int(b)) => Int(a << b),
+        (BinOp::Shr, Int(a), Int(b)) => Int(a >> b),
+        (BinOp::Lt, Int(a), Int(b)) => Bool(a < b),
+        (BinOp::Le, Int(a), Int(b)) => Bool(a <= b),
+        (BinOp::Gt, Int(a), Int(b)) => Bool(a > b),
+        (BinOp::Ge, Int(a), Int(b)) => Bool(a >= b),
+
+        (BinOp::Add, Real(a), Real(b)) => Real(a + b),
+        (BinOp::Sub, Real(a), Real(b)) => Real(a - b),
+        (BinOp::Mul, Real(a), Real(b)) => Real(a * b),
+        (BinOp::Div, Real(a), Real(b)) => Real(a / b),
+        (BinOp::Lt, Real(a), Real(b)) => Bool(a < b),
+        (BinOp::Le, Real(a), Real(b)) => Bool(a <= b),
+        (BinOp::Gt, Real(a), Real(b)) => Bool(a > b),
+        (BinOp::Ge, Real(a), Real(b)) => Bool(a >= b),
+
+        (BinOp::Add, Bits(a), Bits(b)) => Value::bits(a.value.wrapping_add(b.value), a.width),
+        (BinOp::Sub, Bits(a), Bits(b)) => Value::bits(a.value.wrapping_sub(b.value), a.width),
+        (BinOp::Add, Bits(a), Int(b)) => Value::bits(a.value.wrapping_add(b as u128), a.width),
+        (BinOp::Sub, Bits(a), Int(b)) => Value::bits(a.value.wrapping_sub(b as u128), a.width),
+        (BinOp::And, Bits(a), Bits(b)) => Value::bits(a.value & b.value, a.width),
+        (BinOp::Or, Bits(a), Bits(b)) => Value::bits(a.value | b.value, a.width),
+        (BinOp::Eor, Bits(a), Bits(b)) => Value::bits(a.value ^ b.value, a.width),
+        (BinOp::Shl, Bits(a), Int(b)) => Value::bits(a.value.checked_shl(b as u32).unwrap_or(0), a.width),
+        (BinOp::Shr, Bits(a), Int(b)) => Value::bits(a.value.checked_shr(b as u32).unwrap_or(0), a.width),
+        (BinOp::Concat, Bits(a), Bits(b)) => {
+            if a.width + b.width > self::Bits::MAX_WIDTH {
+                return Err(Exception::Unsupported("bitvector wider than 128 bits"));
+            }
+            Value::bits(a.value.checked_shl(b.width).unwrap_or(0) | b.value, a.width + b.width)
+        }
+
+        _ => return Err(Exception::Type("invalid operands"))
+    })
+}
+
+pub fn unary(op: UnOp, a: Value) -> Result<Value> {
+    Ok(match (op, a) {
+        (UnOp::Neg, Value::Int(a)) => Value::Int(a.wrapping_neg()),
+        (UnOp::Neg, Value::Real(a)) => Value::Real(-a),
+        (UnOp::BoolNot, Value::Bool(a)) => Value::Bool(!a),
+        (UnOp::Not, Value::Bits(a)) => Value::bits(!a.value, a.width),
+        _ => return Err(Exception::Type("invalid operand"))
+    })
+}
+
+pub fn equals(a: &Value, b: &Value) -> Result<bool> {
+    match (a, b) {
+        (Value::Bits(a), Value::Bits(b)) => Ok(a.value == b.value),
+        (Value::Int(_), Value::Int(_))
+        | (Value::Bool(_), Value::Bool(_))
+        | (Value::Real(_), Value::Real(_))
+        | (Value::Enum(_), Value::Enum(_))
+        | (Value::Str(_), Value::Str(_))
+        | (Value::Tuple(_), Value::Tuple(_)) => Ok(a == b),
+        _ => Err(Exception::Type("comparing values of different types"))
+    }
+}
+
+/// `value` against a bit pattern such as `'1x0'`, most significant bit first.
+pub fn matches(value: &Value, pattern: &str) -> Result<bool> {
+    let bits = value.as_bits()?;
+    let mut width = 0;
+    let mut matched = true;
+
+    for c in pattern.chars().rev().filter(|c| !c.is_whitespace()) {
+        let bit = (bits.value >> width) & 1;
+        matched &= match c {
+            '0' => bit == 0,
+            '1' => bit == 1,
+            _ => true
+        };
+        width += 1;
+    }
+
+    if width != bits.width {
+        return Err(Exception::Type("pattern width differs from the value"));
+    }
+    Ok(matched)
+}
+
+/// `value<hi:lo>`, integers are sliced in two's complement.
+pub fn slice(value: &Value, hi: Value, lo: Value) -> Result<Value> {
+    let (hi, lo) = (hi.int()?, lo.int()?);
+    if lo < 0 || hi < lo || hi >= Bits::MAX_WIDTH as i128 {
+        return Err(Exception::Type("invalid slice"));
+    }
+
+    let raw = match value {
+        Value::Bits(b) if hi < b.width as i128 => b.value,
+        Value::Int(i) => *i as u128,
+        _ => return Err(Exception::Type("invalid slice"))
+    };
+    Ok(Value::bits(raw >> lo, (hi - lo + 1) as u32))
+}
+
+pub fn set_slice(target: &mut Value, hi: Value, lo: Value, value: Value) -> Result<()> {
+    let (hi, lo) = (hi.int()?, lo.int()?);
+    let Value::Bits(bits) = target else {
+        return Err(Exception::Type("expected a bitvector"));
+    };
+    if lo < 0 || hi < lo || hi >= bits.width as i128 {
+        return Err(Exception::Type("invalid slice"));
+    }
+
+    let mask = Bits::mask((hi - lo + 1) as u32) << lo;
+    let new = (value.as_bits()?.value << lo) & mask;
+    *target = Value::bits((bits.value & !mask) | new, bits.width);
+    Ok(())
+}
+
+/// `bits(width) UNKNOWN`, the runtime picks zero.
+pub fn unknown_bits(width: Value) -> Result<Value> {
+    Ok(Value::bits(0, self::width(&width)?))
+}
+
+/// `PSTATE.<N,Z,C,V> = value`, the current value of each field gives its width.
+pub fn write_fields<C: Cpu + ?Sized>(cpu: &mut C, name: &str, fields: &[&str], value: Value) -> Result<()> {
+    let value = value.as_bits()?;
+    let mut lo = value.width;
+
+    for field in fields {
+        let width = cpu.read_field(name, field)?.as_bits()?.width;
+        lo = lo.checked_sub(width).ok_or(Exception::Type("too few bits for the fields"))?;
+        cpu.write_field(name, field, Value::bits(value.value >> lo, width))?;
+    }
+    Ok(())
+}
+
+fn shift_c(name: &str, x: Bits, amount: i128, carry_in: bool) -> Result<(Bits, bool)> {
+    let n = x.width as i128;
+    if amount < 0 || n == 0 {
+        return Err(Exception::Type("invalid shift"));
+    }
+    if amount == 0 {
+        return Ok((x, carry_in));
+    }
+
+    let bit = |i: i128| (0..n).contains(&i) && (x.value >> i) & 1 == 1;
+    Ok(match name {
+        "LSL" => (Bits::new(if amount >= n { 0 } else { x.value << amount }, x.width), bit(n - amount)),
+        "LSR" => (Bits::new(if amount >= n { 0 } else { x.value >> amount }, x.width), bit(amount - 1)),
+        "ASR" => {
+            let shifted = x.signed() >> amount.min(127);
+            (Bits::new(shifted as u128, x.width), bit(amount.min(n) - 1))
+        }
+        _ => {
+            let m = (amount % n) as u32;
+            let rotated = if m == 0 { x.value } else { (x.value >> m) | (x.value << (x.width - m)) };
+            let result = Bits::new(rotated, x.width);
+            (result, (result.value >> (x.width - 1)) & 1 == 1)
+        }
+    })
+}
+
+/// Runs one of [`BUILTINS`].
+pub fn builtin(name: &str, args: &[Value]) -> Result<Value> {
+    let bits = |i: usize| args.get(i).ok_or(Exception::Type("missing argument"))?.as_bits();
+    let int = |i: usize| args.get(i).ok_or(Exception::Type("missing argument"))?.int();
+    let arity = |n: usize| if args.len() == n { Ok(()) } else { Err(Exception::Unsupported("builtin called with an unsupported arity")) };
+
+    Ok(match name {
+        "UInt" => { arity(1)?; Value::Int(bits(0)?.value as i128) }
+        "SInt" => { arity(1)?; Value::Int(bits(0)?.signed()) }
+        "Int" => {
+            arity(2)?;
+            let x = bits(0)?;
+            Value::Int(if args[1].truthy()? { x.value as i128 } else { x.signed() })
+        }
+        "ZeroExtend" | "SignExtend" => {
+            arity(2)?;
+            let (x, n) = (bits(0)?, width(&args[1])?);
+            if n < x.width {
+                return Err(Exception::Type("extending to a narrower width"));
+            }
+            let raw = if name == "SignExtend" { x.signed() as u128 } else { x.value };
+            Value::bits(raw, n)
+        }
+        "Zeros" => { arity(1)?; Value::bits(0, width(&args[0])?) }
+        "Ones" => { arity(1)?; Value::bits(u128::MAX, width(&args[0])?) }
+        "Replicate" => {
+            arity(2)?;
+            let (x, n) = (bits(0)?, int(1)?);
+            let total = x.width as i128 * n;
+            if n < 0 || total > Bits::MAX_WIDTH as i128 {
+                return Err(Exception::Unsupported("bitvector wider than 128 bits"));
+            }
+            let value = (0..n as u32).fold(0u128, |acc, _| acc.checked_shl(x.width).unwrap_or(0) | x.value);
+            Value::bits(value, total as u32)
+        }
+        "IsZero" => { arity(1)?; Value::Bool(bits(0)?.value == 0) }
+        "IsOnes" => { arity(1)?; let x = bits(0)?; Value::Bool(x.value == Bits::mask(x.width)) }
+        "IsZeroBit" => { arity(1)?; Value::bits((bits(0)?.value == 0) as u128, 1) }
+        "IsOnesBit" => { arity(1)?; let x = bits(0)?; Value::bits((x.value == Bits::mask(x.width)) as u128, 1) }
+        "NOT" => { arity(1)?; unary(UnOp::Not, args[0].clone())? }
+        "Align" => {
+            arity(2)?;
+            let y = int(1)?;
+            if y <= 0 {
+                return Err(Exception::Type("invalid alignment"));
+            }
+            match &args[0] {
+                Value::Int(x) => Value::Int(floor_div(*x, y)? * y),
+                Value::Bits(x) => Value::bits((x.value / y as u128) * y as u128, x.width),
+                _ => return Err(Exception::Type("invalid operands"))
+            }
+        }
+        "LSL" | "LSR" | "ASR" | "ROR" => {
+            arity(2)?;
+            Value::Bits(shift_c(name, bits(0)?, int(1)?, false)?.0)
+        }
+        "LSL_C" | "LSR_C" | "ASR_C" | "ROR_C" => {
+            arity(2)?;
+            let (result, carry) = shift_c(&name[..3], bits(0)?, int(1)?, false)?;
+            Value::Tuple(alloc::vec![Value::Bits(result), Value::bits(carry as u128, 1)])
+        }
+        "Min" | "Max" => {
+            arity(2)?;
+            let (a, b) = (int(0)?, int(1)?);
+            Value::Int(if name == "Min" { a.min(b) } else { a.max(b) })
+        }
+        "Abs" => { arity(1)?; Value::Int(int(0)?.abs()) }
+        "BitCount" => { arity(1)?; Value::Int(bits(0)?.value.count_ones() as i128) }
+        "CountLeadingZeroBits" => {
+            arity(1)?;
+            let x = bits(0)?;
+            Value::Int((x.value.leading_zeros() - (128 - x.width)) as i128)
+        }
+        "HighestSetBit" => {
+            arity(1)?;
+            let x = bits(0)?;
+            Value::Int(if x.value == 0 { -1 } else { 127 - x.value.leading_zeros() as i128 })
+        }
+        "LowestSetBit" => {
+            arity(1)?;
+            let x = bits(0)?;
+            Value::Int(if x.value == 0 { x.width as i128 } else { x.value.trailing_zeros() as i128 })
+        }
+        _ => return Err(Exception::Unsupported("unknown builtin"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        let x = Value::bits(0b1011, 4);
+        assert_eq!(slice(&x, Value::Int(3), Value::Int(2)), Ok(Value::bits(0b10, 2)));
+        assert_eq!(binary(BinOp::Concat, x.clone(), Value::bits(1, 1)), Ok(Value::bits(0b10111, 5)));
+        assert_eq!(builtin("SInt", core::slice::from_ref(&x)), Ok(Value::Int(-5)));
+        assert_eq!(builtin("SignExtend", &[x.clone(), Value::Int(8)]), Ok(Value::bits(0xFB, 8)));
+        assert_eq!(matches(&x, "1x11"), Ok(true));
+        assert_eq!(matches(&x, "0x11"), Ok(false));
+
+        let mut y = Value::bits(0, 8);
+        set_slice(&mut y, Value::Int(7), Value::Int(4), x).unwrap();
+        assert_eq!(y, Value::bits(0xB0, 8));
+    }
+
+    #[test]
+    fn test_shifts() {
+        let x = Value::bits(0x8000_0001, 32);
+        assert_eq!(
+            builtin("ROR_C", &[x.clone(), Value::Int(1)]),
+            Ok(Value::Tuple(alloc::vec![Value::bits(0xC000_0000, 32), Value::bits(1, 1)]))
+        );
+        assert_eq!(builtin("LSL_C", &[x.clone(), Value::Int(1)]), Ok(Value::Tuple(alloc::vec![Value::bits(2, 32), Value::bits(1, 1)])));
+        assert_eq!(builtin("ASR", &[x, Value::Int(4)]), Ok(Value::bits(0xF800_0000, 32)));
+    }
+}
@@ -0,0 +1,109 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Bits(Box<Expr>),
+    Integer,
+    Boolean,
+    Bit,
+    Real,
+    Named(Box<str>),
+    Tuple(Vec<Type>)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinOp {
+    Add, Sub, Mul, Div, IntDiv, Mod, Pow, Shl, Shr,
+    And, Or, Eor, BoolAnd, BoolOr,
+    Eq, Ne, Lt, Le, Gt, Ge,
+    Concat
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    BoolNot,
+    Not
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slice {
+    /// `x<n>`
+    Single(Expr),
+    /// `x<hi:lo>`
+    Range(Expr, Expr),
+    /// `x<lo +: width>`
+    Width(Expr, Expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Int(i128),
+    Real(Box<str>),
+    /// A bit literal, may hold `x` wildcards when used as a pattern.
+    Bits(Box<str>),
+    Str(Box<str>),
+    Var(Box<str>),
+    /// `base.field` or `base.<a, b>`.
+    Field(Box<Expr>, Vec<Box<str>>),
+    Index(Box<Expr>, Vec<Expr>),
+    Slice(Box<Expr>, Vec<Slice>),
+    /// Dotted callee, e.g. `AArch32.CheckAlignment`.
+    Call(Box<str>, Vec<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Vec<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Tuple(Vec<Expr>),
+    Unknown(Type),
+    ImplementationDefined(Option<Box<str>>),
+    /// `-` on the left of a tuple assignment.
+    Discard
+}
+
+impl Expr {
+    /// `a.b.c` as a dotted path, if the expression is only names.
+    pub fn path(&self) -> Option<String> {
+        match self {
+            Expr::Var(name) => Some(name.to_string()),
+            Expr::Field(base, fields) if fields.len() == 1 => Some(format!("{}.{}", base.path()?, fields[0])),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Decl { ty: Type, vars: Vec<(Box<str>, Option<Expr>)> },
+    Assign(Expr, Expr),
+    Expr(Expr),
+    If(Vec<(Expr, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    Case(Expr, Vec<(Vec<Expr>, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    For { var: Box<str>, from: Expr, to: Expr, down: bool, body: Vec<Stmt> },
+    While(Expr, Vec<Stmt>),
+    Repeat(Vec<Stmt>, Expr),
+    Return(Option<Expr>),
+    Assert(Expr),
+    Undefined,
+    Unpredictable,
+    See(Box<str>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: Box<str>,
+    pub params: Vec<(Type, Box<str>)>,
+    pub returns: Option<Type>,
+    pub body: Vec<Stmt>
+}
+
+/// Top level items of the shared pseudocode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition {
+    Function(Function),
+    /// Array style getters and setters (`R[n]`), the CPU state owns those.
+    Accessor(Box<str>),
+    Enumeration(Box<str>, Vec<Box<str>>),
+    Variable(Type, Box<str>),
+    Constant(Type, Box<str>, Expr),
+    /// Type declarations and prototypes, nothing to generate.
+    Declaration
+}
@@ -0,0 +1,162 @@
+use super::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(Box<str>),
+    Int(i128),
+    Real(Box<str>),
+    Bits(Box<str>),
+    Str(Box<str>),
+    /// Operators and punctuation.
+    Punct(&'static str),
+    Newline,
+    Indent,
+    Dedent,
+    Eof
+}
+
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub line: usize
+}
+
+// Longest first.
+const PUNCTUATION: &[&str] = &[
+    "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "+:", "..", "++",
+    "=", "<", ">", "+", "-", "*", "/", "^", "!", ":", ",", ";", "(", ")", "[", "]", "{", "}", ".", "&",
+];
+
+/// Splits ASL into tokens. Indentation is significant like in Python: every line ends with a
+/// `Newline`, deeper lines open an `Indent` and shallower ones close `Dedent`s. Lines inside
+/// brackets continue the current one.
+pub fn tokenize(text: &str) -> Result<Vec<Spanned>, ParseError> {
+    let mut tokens = vec![];
+    let mut indents = vec![0usize];
+    let mut depth = 0usize;
+    let mut in_comment = false;
+
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let mut chars = line.char_indices().peekable();
+        let mut at_line_start = depth == 0;
+
+        while let Some(&(i, c)) = chars.peek() {
+            if in_comment {
+                chars.next();
+                if c == '*' && line[i + 1..].starts_with('/') {
+                    chars.next();
+                    in_comment = false;
+                }
+                continue;
+            }
+
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            if line[i..].starts_with("//") {
+                break;
+            }
+            if line[i..].starts_with("/*") {
+                chars.next();
+                chars.next();
+                in_comment = true;
+                continue;
+            }
+
+            if at_line_start {
+                at_line_start = false;
+                let column = line[..i].chars().map(|c| if c == '\t' { 4 } else { 1 }).sum::<usize>();
+                let current = *indents.last().unwrap();
+
+                if column > current {
+                    indents.push(column);
+                    tokens.push(Spanned { token: Token::Indent, line: number });
+                } else {
+                    while column < *indents.last().unwrap() {
+                        indents.pop();
+                        tokens.push(Spanned { token: Token::Dedent, line: number });
+                    }
+                    if column != *indents.last().unwrap() {
+                        return Err(ParseError::new(number, "inconsistent indentation"));
+                    }
+                }
+            }
+
+            let rest = &line[i..];
+            let token = if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                Token::Ident(Box::from(&rest[..len]))
+            } else if c.is_ascii_digit() {
+                let (token, len) = number_token(rest).ok_or_else(|| ParseError::new(number, "invalid number"))?;
+                for _ in 1..len {
+                    chars.next();
+                }
+                chars.next();
+                tokens.push(Spanned { token, line: number });
+                continue;
+            } else if c == '\'' || c == '"' {
+                let end = rest[1..].find(c).ok_or_else(|| ParseError::new(number, "unterminated literal"))? + 1;
+                let content = &rest[1..end];
+                if c == '\'' {
+                    Token::Bits(content.chars().filter(|c| !c.is_whitespace()).collect::<String>().into())
+                } else {
+                    Token::Str(Box::from(content))
+                }
+            } else {
+                let punct = PUNCTUATION
+                    .iter()
+                    .find(|p| rest.starts_with(**p))
+                    .ok_or_else(|| ParseError::new(number, format!("unexpected character `{c}`")))?;
+                match *punct {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                Token::Punct(punct)
+            };
+
+            let len = match &token {
+                Token::Ident(name) => name.len(),
+                Token::Bits(_) | Token::Str(_) => rest[1..].find(c).unwrap() + 2,
+                Token::Punct(p) => p.len(),
+                _ => unreachable!()
+            };
+            for _ in 0..len {
+                chars.next();
+            }
+            tokens.push(Spanned { token, line: number });
+        }
+
+        let has_tokens = tokens.last().is_some_and(|t| t.line == number && !matches!(t.token, Token::Newline));
+        if depth == 0 && has_tokens {
+            tokens.push(Spanned { token: Token::Newline, line: number });
+        }
+    }
+
+    let last = text.lines().count();
+    while indents.len() > 1 {
+        indents.pop();
+        tokens.push(Spanned { token: Token::Dedent, line: last });
+    }
+    tokens.push(Spanned { token: Token::Eof, line: last });
+    Ok(tokens)
+}
+
+fn number_token(rest: &str) -> Option<(Token, usize)> {
+    if let Some(hex) = rest.strip_prefix("0x") {
+        let len = hex.find(|c: char| !(c.is_ascii_hexdigit() || c == '_')).unwrap_or(hex.len());
+        let digits = hex[..len].replace('_', "");
+        return Some((Token::Int(i128::from_str_radix(&digits, 16).ok()?), len + 2));
+    }
+
+    let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let after = &rest[len..];
+    if after.starts_with('.') && after[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        let frac = after[1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len() - 1);
+        let total = len + 1 + frac;
+        return Some((Token::Real(Box::from(&rest[..total])), total));
+    }
+    Some((Token::Int(rest[..len].parse().ok()?), len))
+}
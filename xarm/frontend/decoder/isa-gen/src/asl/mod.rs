@@ -0,0 +1,29 @@
+//! A front end for the ARM Specification Language found in the `pstext` of the XML release.
+//! It covers the subset used by the decode and execute pseudocode and the shared library, what
+//! it can't parse is reported back so the emitter can leave that code unimplemented.
+
+pub mod ast;
+pub mod lexer;
+pub mod parser;
+
+pub use parser::{parse_expression, parse_library, parse_statements};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: Box<str>
+}
+
+impl ParseError {
+    pub fn new(line: usize, message: impl Into<Box<str>>) -> Self {
+        Self { line, message: message.into() }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,842 @@
+use super::ast::*;
+use super::lexer::{tokenize, Spanned, Token};
+use super::ParseError;
+
+const TYPE_KEYWORDS: &[&str] = &["bits", "integer", "boolean", "bit", "real"];
+
+// Identifiers that continue an expression, a name followed by one of these is not a declaration.
+const OPERATOR_KEYWORDS: &[&str] = &["AND", "OR", "EOR", "DIV", "MOD", "IN", "then", "of", "do", "to", "downto"];
+
+/// Parses a statement list, such as the decode or execute pseudocode of an encoding.
+pub fn parse_statements(text: &str) -> Result<Vec<Stmt>, ParseError> {
+    let mut parser = Parser::new(tokenize(text)?);
+    let mut statements = vec![];
+    loop {
+        parser.skip_newlines();
+        if parser.peek() == &Token::Eof {
+            break;
+        }
+        statements.push(parser.statement()?);
+    }
+    Ok(statements)
+}
+
+/// Parses a single expression, such as the cause of a CONSTRAINED UNPREDICTABLE case.
+pub fn parse_expression(text: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(tokenize(text)?);
+    parser.skip_newlines();
+    let expr = parser.expr()?;
+    parser.skip_newlines();
+    match parser.peek() {
+        Token::Eof => Ok(expr),
+        _ => parser.error("expected the end of the expression")
+    }
+}
+
+/// Parses the definitions of a shared pseudocode block.
+pub fn parse_library(text: &str) -> Result<Vec<Definition>, ParseError> {
+    let mut parser = Parser::new(tokenize(text)?);
+    let mut definitions = vec![];
+    loop {
+        parser.skip_newlines();
+        if parser.peek() == &Token::Eof {
+            break;
+        }
+        definitions.push(parser.definition()?);
+    }
+    Ok(definitions)
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    // Set while parsing the bounds of a slice, where `:` separates and doesn't concatenate.
+    in_slice: bool
+}
+
+impl Parser {
+    fn new(tokens: Vec<Spanned>) -> Self {
+        Self { tokens, pos: 0, in_slice: false }
+    }
+
+    fn peek(&self) -> &Token {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let index = (self.pos + offset).min(self.tokens.len() - 1);
+        &self.tokens[index].token
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos.min(self.tokens.len() - 1)].line
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError::new(self.line(), format!("{}, found {:?}", message.into(), self.peek())))
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn is_ident(&self, ident: &str) -> bool {
+        matches!(self.peek(), Token::Ident(i) if &**i == ident)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, ident: &str) -> bool {
+        let found = self.is_ident(ident);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat_punct(punct) { Ok(()) } else { self.error(format!("expected `{punct}`")) }
+    }
+
+    fn expect_ident(&mut self, ident: &str) -> Result<(), ParseError> {
+        if self.eat_ident(ident) { Ok(()) } else { self.error(format!("expected `{ident}`")) }
+    }
+
+    fn name(&mut self) -> Result<Box<str>, ParseError> {
+        match self.peek() {
+            Token::Ident(name) => {
+                let name = name.clone();
+                self.next();
+                Ok(name)
+            }
+            _ => self.error("expected a name")
+        }
+    }
+
+    /// A possibly dotted name, e.g. `AArch32.ExclusiveMonitorsPass`.
+    fn path(&mut self) -> Result<Box<str>, ParseError> {
+        let mut path = self.name()?.to_string();
+        while self.is_punct(".") && matches!(self.peek_at(1), Token::Ident(_)) {
+            self.next();
+            path.push('.');
+            path.push_str(&self.name()?);
+        }
+        Ok(path.into())
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == &Token::Newline {
+            self.next();
+        }
+    }
+
+    // Statements
+
+    /// An indented block after a newline, or the statements on the rest of the line.
+    fn body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = vec![];
+        if self.peek() == &Token::Newline {
+            self.skip_newlines();
+            if self.next() != Token::Indent {
+                return self.error("expected an indented block");
+            }
+            loop {
+                self.skip_newlines();
+                match self.peek() {
+                    Token::Dedent => {
+                        self.next();
+                        break;
+                    }
+                    Token::Eof => break,
+                    _ => statements.push(self.statement()?)
+                }
+            }
+        } else {
+            while !matches!(self.peek(), Token::Newline | Token::Dedent | Token::Eof)
+                && !self.is_ident("else")
+                && !self.is_ident("elsif")
+                && !self.is_ident("until")
+            {
+                statements.push(self.statement()?);
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Looks past newlines for a continuation keyword like `else`, consuming the newlines if found.
+    fn continues_with(&mut self, keywords: &[&str]) -> bool {
+        let mut offset = 0;
+        while self.peek_at(offset) == &Token::Newline {
+            offset += 1;
+        }
+        let found = matches!(self.peek_at(offset), Token::Ident(i) if keywords.contains(&&**i));
+        if found {
+            self.pos += offset;
+        }
+        found
+    }
+
+    fn starts_declaration(&self) -> bool {
+        match self.peek() {
+            Token::Ident(i) if &**i == "constant" => true,
+            Token::Ident(i) if TYPE_KEYWORDS.contains(&&**i) => !matches!(self.peek_at(1), Token::Ident(u) if &**u == "UNKNOWN"),
+            Token::Ident(_) => {
+                matches!(self.peek_at(1), Token::Ident(n) if !OPERATOR_KEYWORDS.contains(&&**n) && &**n != "UNKNOWN")
+            }
+            Token::Punct("(") => matches!(self.peek_at(1), Token::Ident(i) if TYPE_KEYWORDS.contains(&&**i)),
+            _ => false
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if let Token::Ident(keyword) = self.peek().clone() {
+            match &*keyword {
+                "if" => return self.if_statement(),
+                "case" => return self.case_statement(),
+                "for" => {
+                    self.next();
+                    let var = self.name()?;
+                    self.expect_punct("=")?;
+                    let from = self.expr()?;
+                    let down = if self.eat_ident("downto") {
+                        true
+                    } else {
+                        self.expect_ident("to")?;
+                        false
+                    };
+                    let to = self.expr()?;
+                    let body = self.body()?;
+                    return Ok(Stmt::For { var, from, to, down, body });
+                }
+                "while" => {
+                    self.next();
+                    let cond = self.expr()?;
+                    self.expect_ident("do")?;
+                    return Ok(Stmt::While(cond, self.body()?));
+                }
+                "repeat" => {
+                    self.next();
+                    let body = self.body()?;
+                    self.skip_newlines();
+                    self.expect_ident("until")?;
+                    let cond = self.expr()?;
+                    self.expect_punct(";")?;
+                    return Ok(Stmt::Repeat(body, cond));
+                }
+                "return" => {
+                    self.next();
+                    let value = if self.is_punct(";") { None } else { Some(self.expr()?) };
+                    self.expect_punct(";")?;
+                    return Ok(Stmt::Return(value));
+                }
+                "assert" => {
+                    self.next();
+                    let cond = self.expr()?;
+                    self.expect_punct(";")?;
+                    return Ok(Stmt::Assert(cond));
+                }
+                "UNDEFINED" | "UNPREDICTABLE" => {
+                    self.next();
+                    self.expect_punct(";")?;
+                    return Ok(if &*keyword == "UNDEFINED" { Stmt::Undefined } else { Stmt::Unpredictable });
+                }
+                "SEE" => {
+                    self.next();
+                    let mut target = String::new();
+                    while !self.is_punct(";") {
+                        match self.next() {
+                            Token::Str(s) | Token::Ident(s) => target.push_str(&s),
+                            Token::Newline | Token::Eof => return self.error("expected `;`"),
+                            _ => {}
+                        }
+                    }
+                    self.next();
+                    return Ok(Stmt::See(target.into()));
+                }
+                _ => {}
+            }
+        }
+
+        if self.starts_declaration() {
+            return self.declaration();
+        }
+
+        let target = self.expr()?;
+        let statement = if self.eat_punct("=") {
+            Stmt::Assign(target, self.expr()?)
+        } else {
+            Stmt::Expr(target)
+        };
+        self.expect_punct(";")?;
+        Ok(statement)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.expect_ident("if")?;
+        let mut arms = vec![];
+        let cond = self.expr()?;
+        self.expect_ident("then")?;
+        arms.push((cond, self.body()?));
+
+        let mut otherwise = None;
+        while self.continues_with(&["elsif", "else"]) {
+            if self.eat_ident("elsif") {
+                let cond = self.expr()?;
+                self.expect_ident("then")?;
+                arms.push((cond, self.body()?));
+            } else {
+                self.expect_ident("else")?;
+                otherwise = Some(self.body()?);
+                break;
+            }
+        }
+        Ok(Stmt::If(arms, otherwise))
+    }
+
+    fn case_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.expect_ident("case")?;
+        let subject = self.expr()?;
+        self.expect_ident("of")?;
+        self.skip_newlines();
+        if self.next() != Token::Indent {
+            return self.error("expected indented `when` arms");
+        }
+
+        let mut arms = vec![];
+        let mut otherwise = None;
+        loop {
+            self.skip_newlines();
+            if self.eat_ident("when") {
+                let mut patterns = vec![self.expr()?];
+                while self.eat_punct(",") {
+                    patterns.push(self.expr()?);
+                }
+                arms.push((patterns, self.body()?));
+            } else if self.eat_ident("otherwise") {
+                otherwise = Some(self.body()?);
+            } else if matches!(self.peek(), Token::Dedent | Token::Eof) {
+                self.next();
+                break;
+            } else {
+                return self.error("expected `when` or `otherwise`");
+            }
+        }
+        Ok(Stmt::Case(subject, arms, otherwise))
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        self.eat_ident("constant");
+        let ty = self.ty()?;
+
+        // `(bits(32), bit) (result, carry) = ...`
+        if self.is_punct("(") {
+            let target = self.expr()?;
+            self.expect_punct("=")?;
+            let value = self.expr()?;
+            self.expect_punct(";")?;
+            // The names are declared by the assignment, only the tuple type is lost.
+            return Ok(Stmt::Assign(target, value));
+        }
+
+        let mut vars = vec![];
+        loop {
+            let name = self.name()?;
+            let value = if self.eat_punct("=") { Some(self.expr()?) } else { None };
+            vars.push((name, value));
+            if !self.eat_punct(",") {
+                break;
+            }
+        }
+        self.expect_punct(";")?;
+        Ok(Stmt::Decl { ty, vars })
+    }
+
+    fn ty(&mut self) -> Result<Type, ParseError> {
+        if self.eat_punct("(") {
+            let mut types = vec![self.ty()?];
+            while self.eat_punct(",") {
+                types.push(self.ty()?);
+            }
+            self.expect_punct(")")?;
+            return Ok(Type::Tuple(types));
+        }
+
+        let name = self.name()?;
+        Ok(match &*name {
+            "bits" => {
+                self.expect_punct("(")?;
+                let width = self.expr()?;
+                self.expect_punct(")")?;
+                Type::Bits(Box::new(width))
+            }
+            "integer" => Type::Integer,
+            "boolean" => Type::Boolean,
+            "bit" => Type::Bit,
+            "real" => Type::Real,
+            _ => Type::Named(name)
+        })
+    }
+
+    // Definitions
+
+    /// Skips the rest of the line and the indented block after it.
+    fn skip_definition(&mut self) {
+        while !matches!(self.peek(), Token::Newline | Token::Eof) {
+            self.next();
+        }
+        self.skip_newlines();
+        if self.peek() == &Token::Indent {
+            let mut depth = 0;
+            loop {
+                match self.next() {
+                    Token::Indent => depth += 1,
+                    Token::Dedent => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    Token::Eof => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn definition(&mut self) -> Result<Definition, ParseError> {
+        if self.eat_ident("enumeration") {
+            let name = self.name()?;
+            self.expect_punct("{")?;
+            let mut members = vec![self.name()?];
+            while self.eat_punct(",") {
+                members.push(self.name()?);
+            }
+            self.expect_punct("}")?;
+            self.expect_punct(";")?;
+            return Ok(Definition::Enumeration(name, members));
+        }
+        if self.is_ident("type") || self.is_ident("array") {
+            self.skip_definition();
+            return Ok(Definition::Declaration);
+        }
+        if self.eat_ident("constant") {
+            let ty = self.ty()?;
+            let name = self.name()?;
+            self.expect_punct("=")?;
+            let value = self.expr()?;
+            self.expect_punct(";")?;
+            return Ok(Definition::Constant(ty, name, value));
+        }
+
+        // A procedure has no return type, its name is followed by `(` (or `[`, `=` for a setter).
+        let is_procedure = {
+            let mut offset = 1;
+            while matches!(self.peek_at(offset), Token::Punct(".")) && matches!(self.peek_at(offset + 1), Token::Ident(_)) {
+                offset += 2;
+            }
+            matches!(self.peek_at(offset), Token::Punct("(" | "[" | "="))
+                && !(self.is_ident("bits") && matches!(self.peek_at(1), Token::Punct("(")))
+        };
+        let returns = if is_procedure { None } else { Some(self.ty()?) };
+        let name = self.path()?;
+
+        if self.is_punct("[") || self.is_punct("=") || self.peek() == &Token::Newline {
+            self.skip_definition();
+            return Ok(Definition::Accessor(name));
+        }
+        if self.eat_punct(";") {
+            return Ok(match returns {
+                Some(ty) => Definition::Variable(ty, name),
+                None => Definition::Declaration
+            });
+        }
+
+        self.expect_punct("(")?;
+        let mut params = vec![];
+        if !self.is_punct(")") {
+            loop {
+                let ty = self.ty()?;
+                // By-reference parameters carry a `&`.
+                self.eat_punct("&");
+                params.push((ty, self.name()?));
+                if !self.eat_punct(",") {
+                    break;
+                }
+            }
+        }
+        self.expect_punct(")")?;
+
+        if self.eat_punct(";") {
+            return Ok(Definition::Declaration);
+        }
+        if self.peek() != &Token::Newline {
+            // An accessor with parameters in brackets after the call, e.g. `Elem[...]`.
+            self.skip_definition();
+            return Ok(Definition::Accessor(name));
+        }
+        let body = self.body()?;
+        Ok(Definition::Function(Function { name, params, returns, body }))
+    }
+
+    // Expressions
+
+    pub fn expr(&mut self) -> Result<Expr, ParseError> {
+        self.bool_or()
+    }
+
+    fn bool_or(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.bool_and()?;
+        while self.eat_punct("||") {
+            lhs = Expr::Binary(BinOp::BoolOr, Box::new(lhs), Box::new(self.bool_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn bool_and(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.comparison()?;
+        while self.eat_punct("&&") {
+            lhs = Expr::Binary(BinOp::BoolAnd, Box::new(lhs), Box::new(self.comparison()?));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.additive()?;
+        loop {
+            let op = match self.peek() {
+                Token::Punct("==") => BinOp::Eq,
+                Token::Punct("!=") => BinOp::Ne,
+                Token::Punct("<") => BinOp::Lt,
+                Token::Punct("<=") => BinOp::Le,
+                Token::Punct(">") => BinOp::Gt,
+                Token::Punct(">=") => BinOp::Ge,
+                Token::Ident(i) if &**i == "IN" => {
+                    self.next();
+                    self.expect_punct("{")?;
+                    let mut set = vec![self.expr()?];
+                    while self.eat_punct(",") {
+                        set.push(self.expr()?);
+                    }
+                    self.expect_punct("}")?;
+                    lhs = Expr::In(Box::new(lhs), set);
+                    continue;
+                }
+                _ => return Ok(lhs)
+            };
+            self.next();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.additive()?));
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Token::Punct("+") => BinOp::Add,
+                Token::Punct("-") => BinOp::Sub,
+                Token::Ident(i) if &**i == "OR" => BinOp::Or,
+                Token::Ident(i) if &**i == "EOR" => BinOp::Eor,
+                _ => return Ok(lhs)
+            };
+            self.next();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.concat()?;
+        loop {
+            let op = match self.peek() {
+                Token::Punct("*") => BinOp::Mul,
+                Token::Punct("/") => BinOp::Div,
+                Token::Punct("<<") => BinOp::Shl,
+                Token::Punct(">>") => BinOp::Shr,
+                Token::Ident(i) if &**i == "DIV" => BinOp::IntDiv,
+                Token::Ident(i) if &**i == "MOD" => BinOp::Mod,
+                Token::Ident(i) if &**i == "AND" => BinOp::And,
+                _ => return Ok(lhs)
+            };
+            self.next();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.concat()?));
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.power()?;
+        while !self.in_slice && self.eat_punct(":") {
+            lhs = Expr::Binary(BinOp::Concat, Box::new(lhs), Box::new(self.power()?));
+        }
+        Ok(lhs)
+    }
+
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let lhs = self.unary()?;
+        if self.eat_punct("^") {
+            return Ok(Expr::Binary(BinOp::Pow, Box::new(lhs), Box::new(self.power()?)));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.is_punct("-") && !matches!(self.peek_at(1), Token::Punct("," | ")")) {
+            self.next();
+            return Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)));
+        }
+        if self.eat_punct("!") {
+            return Ok(Expr::Unary(UnOp::BoolNot, Box::new(self.unary()?)));
+        }
+        if self.eat_ident("NOT") {
+            return Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)));
+        }
+        let primary = self.primary()?;
+        self.postfix(primary)
+    }
+
+    /// Parses a nested expression with `:` meaning concatenation again.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        let in_slice = std::mem::replace(&mut self.in_slice, false);
+        let result = f(self);
+        self.in_slice = in_slice;
+        result
+    }
+
+    fn arguments(&mut self, close: &str) -> Result<Vec<Expr>, ParseError> {
+        self.nested(|p| {
+            let mut args = vec![];
+            if !p.is_punct(close) {
+                args.push(p.expr()?);
+                while p.eat_punct(",") {
+                    args.push(p.expr()?);
+                }
+            }
+            p.expect_punct(close)?;
+            Ok(args)
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Token::Int(value) => Ok(Expr::Int(value)),
+            Token::Real(value) => Ok(Expr::Real(value)),
+            Token::Bits(value) => Ok(Expr::Bits(value)),
+            Token::Str(value) => Ok(Expr::Str(value)),
+            Token::Punct("-") => Ok(Expr::Discard),
+            Token::Punct("(") => {
+                let mut items = self.arguments(")")?;
+                Ok(if items.len() == 1 { items.remove(0) } else { Expr::Tuple(items) })
+            }
+            Token::Ident(name) => match &*name {
+                "if" => {
+                    let cond = self.expr()?;
+                    self.expect_ident("then")?;
+                    let then = self.expr()?;
+                    self.expect_ident("else")?;
+                    let otherwise = self.expr()?;
+                    Ok(Expr::If(Box::new(cond), Box::new(then), Box::new(otherwise)))
+                }
+                "IMPLEMENTATION_DEFINED" => {
+                    let what = match self.peek() {
+                        Token::Str(s) => Some(s.clone()),
+                        _ => None
+                    };
+                    if what.is_some() {
+                        self.next();
+                    }
+                    Ok(Expr::ImplementationDefined(what))
+                }
+                _ if matches!(self.peek(), Token::Ident(u) if &**u == "UNKNOWN")
+                    || (&*name == "bits" && self.is_punct("(")) =>
+                {
+                    self.pos -= 1;
+                    let ty = self.ty()?;
+                    self.expect_ident("UNKNOWN")?;
+                    Ok(Expr::Unknown(ty))
+                }
+                _ => Ok(Expr::Var(name))
+            },
+            _ => {
+                self.pos -= 1;
+                self.error("expected an expression")
+            }
+        }
+    }
+
+    fn postfix(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        loop {
+            if self.is_punct(".") {
+                self.next();
+                if self.eat_punct("<") {
+                    let mut fields = vec![self.name()?];
+                    while self.eat_punct(",") {
+                        fields.push(self.name()?);
+                    }
+                    self.expect_punct(">")?;
+                    expr = Expr::Field(Box::new(expr), fields);
+                } else {
+                    expr = Expr::Field(Box::new(expr), vec![self.name()?]);
+                }
+            } else if self.is_punct("(") {
+                let Some(path) = expr.path() else { return Ok(expr) };
+                self.next();
+                expr = Expr::Call(path.into(), self.arguments(")")?);
+            } else if self.is_punct("[") {
+                self.next();
+                expr = Expr::Index(Box::new(expr), self.arguments("]")?);
+            } else if self.is_punct("<") {
+                let start = self.pos;
+                match self.slices() {
+                    Ok(slices) => expr = Expr::Slice(Box::new(expr), slices),
+                    Err(_) => {
+                        // A comparison after all.
+                        self.pos = start;
+                        return Ok(expr);
+                    }
+                }
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn slices(&mut self) -> Result<Vec<Slice>, ParseError> {
+        self.expect_punct("<")?;
+        let in_slice = std::mem::replace(&mut self.in_slice, true);
+        let result = (|| {
+            let mut slices = vec![];
+            loop {
+                let first = self.additive()?;
+                slices.push(if self.eat_punct(":") {
+                    Slice::Range(first, self.additive()?)
+                } else if self.eat_punct("+:") {
+                    Slice::Width(first, self.additive()?)
+                } else {
+                    Slice::Single(first)
+                });
+                if !self.eat_punct(",") {
+                    break;
+                }
+            }
+            Ok(slices)
+        })();
+        self.in_slice = in_slice;
+        let slices = result?;
+
+        // `x<0>=...` lexes the end of the slice into `>=`.
+        match self.peek() {
+            Token::Punct(">") => {
+                self.next();
+            }
+            Token::Punct(">=") => {
+                let line = self.line();
+                self.tokens[self.pos] = Spanned { token: Token::Punct("="), line };
+            }
+            _ => return self.error("expected `>`")
+        }
+        Ok(slices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements() {
+        let text = "\
+if ConditionPassed() then
+    EncodingSpecificOperations();
+    (result, carry) = Shift_C(R[m], shift_t, shift_n, PSTATE.C);
+    if d == 15 then ALUWritePC(result); // comment
+    else
+        R[d] = result<31:0>;
+        if setflags then
+            PSTATE.<N,Z,C,V> = result<31>:IsZeroBit(result):carry:'0';
+";
+        let statements = parse_statements(text).unwrap();
+        assert_eq!(statements.len(), 1);
+
+        let Stmt::If(arms, None) = &statements[0] else { panic!("{:?}", statements[0]) };
+        let body = &arms[0].1;
+        assert_eq!(body.len(), 3);
+        assert!(matches!(&body[1], Stmt::Assign(Expr::Tuple(t), Expr::Call(name, args)) if t.len() == 2 && &**name == "Shift_C" && args.len() == 4));
+
+        let Stmt::If(inner, Some(otherwise)) = &body[2] else { panic!("{:?}", body[2]) };
+        assert_eq!(inner.len(), 1);
+        assert!(matches!(&otherwise[0], Stmt::Assign(Expr::Index(..), Expr::Slice(_, s)) if matches!(s[0], Slice::Range(..))));
+        let Stmt::If(flags, None) = &otherwise[1] else { panic!() };
+        assert!(matches!(&flags[0].1[0], Stmt::Assign(Expr::Field(_, f), Expr::Binary(BinOp::Concat, ..)) if f.len() == 4));
+    }
+
+    #[test]
+    fn test_comparison_is_not_slice() {
+        let statements = parse_statements("if n < 3 && m > 2 then UNPREDICTABLE;\nx = y<3:0> + 1;").unwrap();
+        let Stmt::If(arms, None) = &statements[0] else { panic!() };
+        assert!(matches!(&arms[0].0, Expr::Binary(BinOp::BoolAnd, lhs, _) if matches!(**lhs, Expr::Binary(BinOp::Lt, ..))));
+        assert_eq!(arms[0].1, vec![Stmt::Unpredictable]);
+        assert_eq!(parse_expression("n < 3 && m > 2").unwrap(), arms[0].0);
+        assert!(parse_expression("n < 3 then").is_err());
+        assert!(matches!(&statements[1], Stmt::Assign(_, Expr::Binary(BinOp::Add, lhs, _)) if matches!(**lhs, Expr::Slice(..))));
+    }
+
+    #[test]
+    fn test_case_and_declarations() {
+        let text = "\
+integer d = UInt(Rd);  boolean setflags = (S == '1');
+SRType shift_t;
+case type of
+    when '00' shift_t = SRType_LSL;
+    when '01', '1x'
+        shift_t = SRType_LSR;
+    otherwise SEE \"Related encodings\";
+";
+        let statements = parse_statements(text).unwrap();
+        assert_eq!(statements.len(), 4);
+        assert!(matches!(&statements[0], Stmt::Decl { ty: Type::Integer, vars } if vars.len() == 1));
+        assert!(matches!(&statements[2], Stmt::Decl { ty: Type::Named(n), .. } if &**n == "SRType"));
+        let Stmt::Case(_, arms, Some(otherwise)) = &statements[3] else { panic!() };
+        assert_eq!(arms[1].0, vec![Expr::Bits("01".into()), Expr::Bits("1x".into())]);
+        assert_eq!(otherwise, &vec![Stmt::See("Related encodings".into())]);
+    }
+
+    #[test]
+    fn test_library() {
+        let text = "\
+// AddWithCarry()
+// ==============
+
+(bits(N), bits(4)) AddWithCarry(bits(N) x, bits(N) y, bit carry_in)
+    integer unsigned_sum = UInt(x) + UInt(y) + UInt(carry_in);
+    bits(N) result = unsigned_sum<N-1:0>;
+    return (result, '0000');
+
+bits(32) R[integer n]
+    return _R[n];
+
+enumeration SRType {SRType_LSL, SRType_LSR};
+AArch32.CheckAlignment(bits(32) address)
+    return;
+";
+        let definitions = parse_library(text).unwrap();
+        assert_eq!(definitions.len(), 4);
+        let Definition::Function(add) = &definitions[0] else { panic!() };
+        assert_eq!(add.params.len(), 3);
+        assert!(matches!(add.returns, Some(Type::Tuple(_))));
+        assert_eq!(definitions[1], Definition::Accessor("R".into()));
+        assert!(matches!(&definitions[2], Definition::Enumeration(_, m) if m.len() == 2));
+        assert!(matches!(&definitions[3], Definition::Function(f) if f.returns.is_none() && &*f.name == "AArch32.CheckAlignment"));
+    }
+}
@@ -1,110 +1,105 @@
 use crate::ir;
-use std::collections::{HashSet, HashMap};
+use crate::emitter::classification::Frequencies;
 
-pub fn get_key_for_pattern_nz(pattern: &ir::BitPattern, bits: &[usize]) -> String {
-    bits.iter().map(|b| match pattern[*b] {
+/// The bits of `instruction` at `bits` as a string in that order: `1`/`0` for fixed bits, `N`/`Z`
+/// for free bits a `!=` constraint compares when it rules out a one/zero there, `X` otherwise.
+pub fn get_key_for_pattern_nz(instruction: &ir::Instruction, bits: &[usize]) -> String {
+    bits.iter().map(|b| match instruction.pattern[*b] {
         Some(ir::Bit::One) => "1",
         Some(ir::Bit::Zero) => "0",
-        Some(ir::Bit::NotOne) => "N",
-        Some(ir::Bit::NotZero) => "Z",
-        _ => "X"
+        None => match instruction.field_constraints.iter().find(|c| c.range.contains(b)) {
+            Some(constraint) if constraint.mask().1 & 1 << b != 0 => "N",
+            Some(_) => "Z",
+            None => "X"
+        }
     }).collect::<Vec<_>>().concat()
 }
 
-fn get_key_for_pattern(pattern: &ir::BitPattern, bits: &[usize]) -> String {
-    bits.iter().map(|b| match pattern[*b] {
-        Some(ir::Bit::One) => "1",
-        Some(ir::Bit::Zero) => "0",
-        _ => "X"
-    }).collect::<Vec<_>>().concat()
+/// Gathers the bits of `word` at `bits` into a lookup index, the first of them being the highest.
+pub fn gather(word: u32, bits: &[usize]) -> usize {
+    bits.iter().fold(0, |index, bit| index << 1 | (word >> bit) as usize & 1)
 }
 
-fn expand(current: String, index: usize, results: &mut Vec<String>) {
-    match current[index..].find('X') {
-        Some(pos) => {
-            let x_pos = index + pos;
-
-            let mut zero_branch = current.clone();
-            zero_branch.replace_range(x_pos..x_pos+1, "0");
-            expand(zero_branch, x_pos + 1, results);
-
-            let mut one_branch = current;
-            one_branch.replace_range(x_pos..x_pos+1, "1");
-            expand(one_branch, x_pos + 1, results);
-        },
-        None => results.push(current)
-    }
-}
-
-pub fn get_all_bit_options(instruction: &ir::Instruction, bits: &[usize]) -> Vec<String> {
-    let present_filter_cases = instruction
-        .filters
+/// Every lookup index over `bits` words of `instruction` take, in ascending order. Constraints
+/// on fields inside `bits` drop the values they rule out, the others are left to the leaf.
+pub fn get_all_bit_options(instruction: &ir::Instruction, bits: &[usize]) -> Vec<usize> {
+    // The constraints as index masks, a field partly outside of `bits` can't be told.
+    let present_constraints = instruction
+        .field_constraints
         .iter()
-        .filter(|range| (*range).clone().all(|bit| bits.contains(&bit)));
-
-    let key = get_key_for_pattern(&instruction.pattern, bits);
-
-    let mut results = Vec::new();
-    expand(key, 0, &mut results);
-    
-    results
-        .into_iter()
-        .filter(|res| {
-            let passes_filter = |string: String, filter_range: std::ops::RangeInclusive<usize>| {
-                let string_filter_range = filter_range
-                    .clone()
-                    .map(|i| string.chars().nth(bits.iter().position(|b| b == &i).unwrap()).unwrap())
-                    .collect::<String>();
-
-                let pattern_key = filter_range
-                    .map(|b| match instruction.pattern[b] {
-                        Some(ir::Bit::NotZero) => '0',
-                        Some(ir::Bit::NotOne) => '1',
-                        None => 'X',
-                        _ => unreachable!()
-                    })
-                    .collect::<String>();
-
-                let mut blacklisted = Vec::new();
-                expand(pattern_key.clone(), 0, &mut blacklisted);
-
-                // TODO: this should be tested better
-                !blacklisted.contains(&string_filter_range)
-            };
-            present_filter_cases.clone().all(|filter| passes_filter(res.clone(), filter.clone()))
+        .filter(|constraint| constraint.range.clone().all(|bit| bits.contains(&bit)))
+        .map(|constraint| {
+            let (mask, value) = constraint.mask();
+            (constraint.op, gather(mask, bits), gather(value, bits))
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>();
+
+    let (mask, value) = instruction.fixed_bits();
+    let free = !gather(mask, bits) & ((1 << bits.len()) - 1);
+    let fixed = gather(value, bits);
+
+    // Counts through the free bits of the index, the fixed ones stay as they are.
+    let mut options = Vec::with_capacity(1 << free.count_ones());
+    let mut subset = 0usize;
+    loop {
+        let index = fixed | subset;
+        let holds = present_constraints.iter().all(|&(op, mask, value)| match op {
+            ir::FieldOp::Ne => index & mask != value
+        });
+        if holds {
+            options.push(index);
+        }
+        if subset == free {
+            return options;
+        }
+        subset = subset.wrapping_sub(free) & free;
+    }
 }
 
-pub fn create_bit_mapping<'a>(instructions: &[&'a ir::Instruction], bits: &[usize]) -> HashMap<String, HashSet<&'a ir::Instruction>> {
-    let mut result: HashMap<_, HashSet<_>> = HashMap::new();
-    
+/// The encodings in every entry of a lookup over `bits`, indexed as `gather` does and in the
+/// order of `instructions`.
+pub fn create_bit_mapping<'a>(instructions: &[&'a ir::Instruction], bits: &[usize]) -> Vec<Vec<&'a ir::Instruction>> {
+    let mut buckets = vec![Vec::new(); 1 << bits.len()];
     for inst in instructions {
         for option in get_all_bit_options(inst, bits) {
-            result.entry(option).or_default().insert(*inst);
+            buckets[option].push(*inst);
         }
-
     }
 
-    result
+    buckets
 }
 
-pub fn min_bits_for_individualisation<'a>(instructions: &[&'a ir::Instruction], budget: usize) -> (Vec<usize>, HashMap<String, HashSet<&'a ir::Instruction>>) {
+pub fn min_bits_for_individualisation<'a>(
+    instructions: &[&'a ir::Instruction],
+    budget: usize,
+    frequencies: &Frequencies
+) -> (Vec<usize>, Vec<Vec<&'a ir::Instruction>>) {
     // TODO: make it simple for start, but it needs a heuristics aswell likely
     // TODO: this is shitty, and hella slow, but this can work
     
-    let patterns = instructions.iter().map(|i| &i.pattern).collect::<Vec<_>>();
-
-    let mut last_bits = crate::emitter::classification::simple_individualistic_differentiation(&patterns, 1, true);
+    let fixed = instructions.iter().map(|i| i.fixed_bits()).collect::<Vec<_>>();
+    let weights = instructions.iter().map(|i| frequencies.weight(i)).collect::<Vec<_>>();
+    // The heaviest bucket, by count when every encoding weighs the same.
+    let heaviest = |mapping: &[Vec<&ir::Instruction>]| {
+        mapping.iter().map(|c| c.iter().map(|i| frequencies.weight(i)).sum::<f64>()).fold(0.0, f64::max)
+    };
+
+    // Without a histogram the buckets are balanced on their size, with one on the expected depth.
+    let select = |bit_count| match frequencies.is_empty() {
+        true => crate::emitter::classification::simple_individualistic_differentiation(&fixed, &weights, bit_count, true),
+        false => crate::emitter::classification::weighted_differentiation(instructions, frequencies, bit_count)
+    };
+
+    let mut last_bits = select(1);
     last_bits.sort_by(|a, b| b.cmp(a));
     let mut last_mapping = create_bit_mapping(instructions, &last_bits);
-    let mut last_max = last_mapping.iter().map(|(_, c)| c.len()).max().unwrap();
+    let mut last_max = heaviest(&last_mapping);
 
     for bit_count in 2..=budget {
-        let mut bits = crate::emitter::classification::simple_individualistic_differentiation(&patterns, bit_count, true);
+        let mut bits = select(bit_count);
         bits.sort_by(|a, b| b.cmp(a));
         let mapping = create_bit_mapping(instructions, &bits);
-        let max = mapping.iter().map(|(_, c)| c.len()).max().unwrap();
+        let max = heaviest(&mapping);
 
         if max < last_max {
             last_max = max;
@@ -117,3 +112,36 @@ pub fn min_bits_for_individualisation<'a>(instructions: &[&'a ir::Instruction],
 
     return (last_bits, last_mapping);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::fixtures::instruction;
+
+    #[test]
+    fn test_unconditional_space() {
+        // `cond != 1111` only rules out the unconditional space, every other cond stays.
+        let conditional = instruction("CONDITIONAL", "NNNN 0000 xxxx xxxx xxxx xxxx xxxx xxxx");
+        let unconditional = instruction("UNCONDITIONAL", "1111 0000 xxxx xxxx xxxx xxxx xxxx xxxx");
+        assert_eq!(get_key_for_pattern_nz(&conditional, &[31, 30, 29, 28, 27]), "NNNN0");
+
+        let cond = [31, 30, 29, 28];
+        let options = get_all_bit_options(&conditional, &cond);
+        assert_eq!(options.len(), 15);
+        assert!(!options.contains(&0b1111) && options.contains(&0b0111));
+        assert!(options.is_sorted());
+        assert_eq!(get_all_bit_options(&unconditional, &cond), [0b1111]);
+
+        // Looking at part of the field, every value is still possible.
+        assert_eq!(get_all_bit_options(&conditional, &[31, 30]).len(), 4);
+        assert_eq!(get_all_bit_options(&conditional, &[31, 30, 29, 28, 20]).len(), 30);
+        // Fixed bits stay put wherever they are in the index.
+        assert_eq!(get_all_bit_options(&unconditional, &[24, 28, 20]), [0b010, 0b011]);
+        assert_eq!(gather(0xF100_0000, &[24, 28, 20]), 0b110);
+
+        let mapping = create_bit_mapping(&[&conditional, &unconditional], &cond);
+        assert_eq!(mapping.len(), 16);
+        assert!(mapping.iter().all(|bucket| bucket.len() == 1));
+        assert_eq!(mapping[0b1111], [&unconditional]);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::emitter::strategies::latency::graph;
 use crate::ir;
+use std::collections::HashMap;
 
 // Basically everything in here needs work.
 
@@ -9,52 +11,131 @@ use crate::ir;
 // We should "scale vertically" in two cases:
 // - Specialization of an instruction word in a scenario that can't be differentiated (already
 // handled)
-// TODO: - Adjusting the balance of the tree to account for real world probability based on code samples
-// 
+// - Adjusting the balance of the tree to account for real world probability based on code
+// samples, see `Frequencies`
+//
 // ARM decoding is not perfect. Lot of special cases and not balanced buckets.
 
+/// How often each encoding is decoded, from a histogram of encoding names or raw instruction
+/// words out of a trace. Splits balance on it instead of the number of encodings, so frequent
+/// encodings end up near the root.
+#[derive(Debug, Clone, Default)]
+pub struct Frequencies {
+    names: HashMap<Box<str>, f64>,
+    words: Vec<(u32, f64)>
+}
+
+#[derive(Debug)]
+pub struct InvalidHistogram {
+    pub line: usize,
+    pub reason: Box<str>
+}
+
+impl std::fmt::Display for InvalidHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidHistogram {}
+
+impl Frequencies {
+    /// One `<encoding name or 0x word> [count]` per line, the count defaults to 1 so a plain
+    /// dump of words works as well. T32 words are `hw1 << 16 | hw2` as the decoder takes them.
+    pub fn parse(contents: &str) -> Result<Self, InvalidHistogram> {
+        let mut frequencies = Self::default();
+
+        for (ndx, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = |reason: &str| InvalidHistogram { line: ndx + 1, reason: Box::from(reason) };
+
+            let mut columns = line.split_whitespace();
+            let key = columns.next().unwrap_or_default();
+            let count = match columns.next() {
+                Some(count) => count.parse::<u64>().map_err(|_| invalid("malformed count"))? as f64,
+                None => 1.0
+            };
+            if columns.next().is_some() {
+                return Err(invalid("expected `<encoding or word> [count]`"));
+            }
+
+            match key.strip_prefix("0x") {
+                Some(hex) => {
+                    let word = u32::from_str_radix(hex, 16).map_err(|_| invalid("malformed instruction word"))?;
+                    frequencies.words.push((word, count));
+                }
+                None => *frequencies.names.entry(Box::from(key)).or_default() += count
+            }
+        }
+
+        Ok(frequencies)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.words.is_empty()
+    }
+
+    /// Counts the words as the encodings they decode as. Of the encodings matching a word, the
+    /// tree gives it to the first in `graph::precedence_order` not redirecting it with `SEE`,
+    /// words none of them match are dropped.
+    pub fn resolve(&self, instructions: &[&ir::Instruction]) -> Self {
+        let mut names = self.names.clone();
+        for &(word, count) in &self.words {
+            let matching = instructions.iter().copied().filter(|i| i.matches(word)).collect::<Vec<_>>();
+            let order = graph::precedence_order(&matching, &mut graph::Overlaps::default());
+            // The last one keeps what the others redirect, as in `graph::precedence_chain`.
+            let redirects = |inst: &ir::Instruction| inst.see.iter().any(|see| see.covers(u32::MAX, word));
+            let Some(decoded) = order.iter().find(|i| !redirects(i)).or(order.last()) else { continue };
+            *names.entry(decoded.name.clone()).or_default() += count;
+        }
+
+        Self { names, words: vec![] }
+    }
+
+    /// The count of an encoding plus one, encodings the histogram never saw still weigh in.
+    /// Without a histogram every encoding weighs the same.
+    pub fn weight(&self, inst: &ir::Instruction) -> f64 {
+        1.0 + self.names.get(&inst.name).copied().unwrap_or_default()
+    }
+}
+
 pub fn categorization_differentiation() {
     todo!()
     // Less handlers, which are wider.
 }
 
-// Score how an individual bit "splits" the instructions into 50/50
-pub fn score_split<'a>(bit_patterns: &[&'a [Option<ir::Bit>; 32]], bit: usize, score_var: bool) -> (f64, Vec<&'a [Option<ir::Bit>; 32]>, Vec<&'a [Option<ir::Bit>; 32]>) {
+// Score how an individual bit "splits" the weight of the instructions into 50/50
+pub fn score_split(fixed: &[(u32, u32)], weights: &[f64], bit: usize, score_var: bool) -> f64 {
     // TODO: all these classification functions are huristic shits
     // TODO: need to take in mind all other states this is shit
 
-    let mut counter_0: usize = 0;
-    let mut counter_1: usize = 0;
-    let mut counter_var: usize = 0;
+    let mut counter_0 = 0.0;
+    let mut counter_1 = 0.0;
+    let mut counter_var = 0.0;
 
-    let mut res_0 = Vec::new();
-    let mut res_1 = Vec::new();
-
-    for bit_pattern in bit_patterns {
-        match bit_pattern[bit] {
-            Some(ir::Bit::One) => {
-                counter_1 += 1;
-                res_1.push(*bit_pattern);
-            }
-            Some(ir::Bit::Zero) => {
-                counter_0 += 1;
-                res_0.push(*bit_pattern);
-            }
-            None => counter_var += 1,
-            _ => {}
-        };
+    for (&(mask, value), weight) in fixed.iter().zip(weights) {
+        match (mask >> bit & 1, value >> bit & 1) {
+            (0, _) => counter_var += weight,
+            (_, 1) => counter_1 += weight,
+            _ => counter_0 += weight
+        }
     }
 
-    let pc0 = counter_0 as f64 / bit_patterns.len() as f64;
-    let pc1 = counter_1 as f64 / bit_patterns.len() as f64;
-    let pcv = counter_var as f64 / bit_patterns.len() as f64;
+    let total: f64 = weights.iter().sum();
+    let pc0 = counter_0 / total;
+    let pc1 = counter_1 / total;
+    let pcv = counter_var / total;
 
     let mut score = (pc0 - 0.5).abs() + (pc1 - 0.5).abs();
     if score_var {
         score += pcv * 2.0;
     }
 
-    (score, res_0, res_1)
+    score
 }
 
 pub fn get_instruction_specialization<'a>(insts: &[&'a ir::Instruction]) -> Option<(u32, u32, Box<[&'a ir::Instruction]>, Box<[&'a ir::Instruction]>)> {
@@ -70,12 +151,11 @@ pub fn get_instruction_specialization<'a>(insts: &[&'a ir::Instruction]) -> Opti
     let mut then = vec![];
     let mut r#else = vec![];
 
+    let fixed = insts.iter().map(|i| i.fixed_bits()).collect::<Vec<_>>();
     for col in 0..32 {
-        let bits: Vec<_> = insts.iter().map(|i| i.pattern[col]).collect();
-
-        let none = bits.iter().position(|b| b.is_none() || *b == Some(ir::Bit::NotZero) || *b == Some(ir::Bit::NotOne));
-        let ones = bits.iter().position(|b| *b == Some(ir::Bit::One));
-        let zeros = bits.iter().position(|b| *b == Some(ir::Bit::Zero));
+        let none = fixed.iter().position(|(mask, _)| mask & 1 << col == 0);
+        let ones = fixed.iter().position(|(mask, value)| mask & value & 1 << col != 0);
+        let zeros = fixed.iter().position(|(mask, value)| mask & !value & 1 << col != 0);
 
         if none.is_none() {
             continue;
@@ -113,37 +193,27 @@ pub fn get_instruction_specialization<'a>(insts: &[&'a ir::Instruction]) -> Opti
     Some((mask, value, then.into(), r#else.into()))
 }
 
-pub fn can_individually_differentiate(bit_patterns: &[&[Option<ir::Bit>; 32]]) -> bool {
-    (0..32).any(|col| {
-        let mut bits = bit_patterns.iter().map(|p| match p[col] {
-            Some(ir::Bit::NotZero) => None,
-            Some(ir::Bit::NotOne) => None,
-            None => None,
-            other => other
-        });
-        
-        let first = bits.next().flatten();
-        match first {
-            None => false,
-            Some(f) => bits.all(|b| b.is_some()) && 
-                       bit_patterns.iter().any(|p| p[col] != Some(f))
-        }
-    })
+/// Whether a bit is fixed in every encoding, and not to the same value in all of them.
+pub fn can_individually_differentiate(fixed: &[(u32, u32)]) -> bool {
+    let everywhere = fixed.iter().fold(u32::MAX, |everywhere, (mask, _)| everywhere & mask);
+    let ones = fixed.iter().fold(0, |ones, (_, value)| ones | value);
+    let zeros = fixed.iter().fold(0, |zeros, (mask, value)| zeros | mask & !value);
+    everywhere & ones & zeros != 0
 }
 
 // This should prefer bits which are not X?
-pub fn simple_individualistic_differentiation(bit_patterns: &[&[Option<ir::Bit>; 32]], budget: usize, penalize: bool) -> Vec<usize> {
+pub fn simple_individualistic_differentiation(fixed: &[(u32, u32)], weights: &[f64], budget: usize, penalize: bool) -> Vec<usize> {
     let mut selected_bits = Vec::with_capacity(budget);
+    // A score doesn't depend on the bits picked before it.
+    let scores = (0..32).map(|i| score_split(fixed, weights, i, penalize)).collect::<Vec<_>>();
 
     for _ in 0..budget {
         let mut best_bit = None;
         let mut best_total_score = f64::MAX;
 
-        for i in 0..32 {
+        for (i, &score) in scores.iter().enumerate() {
             if selected_bits.contains(&i) { continue; }
 
-            let (score, _, _) = score_split(bit_patterns, i, penalize);
-
             if score < best_total_score {
                 best_total_score = score;
                 best_bit = Some(i);
@@ -158,6 +228,56 @@ pub fn simple_individualistic_differentiation(bit_patterns: &[&[Option<ir::Bit>;
     selected_bits
 }
 
+/// Picks `budget` bits one at a time, each leaving the least expected work behind: every
+/// bucket the bits sort the encodings into costs the weight in it times the lookups still
+/// needed to tell its encodings apart, about log2 of their count.
+pub fn weighted_differentiation(instructions: &[&ir::Instruction], frequencies: &Frequencies, budget: usize) -> Vec<usize> {
+    // Encodings sharing a name count as one when their weight is split.
+    let names = instructions
+        .iter()
+        .map(|i| instructions.iter().position(|other| other.name == i.name).unwrap())
+        .collect::<Vec<_>>();
+    let weights = instructions.iter().map(|i| frequencies.weight(i)).collect::<Vec<_>>();
+
+    let cost = |bits: &[usize]| {
+        let options = instructions.iter().map(|i| crate::emitter::bits::get_all_bit_options(i, bits)).collect::<Vec<_>>();
+        let mut buckets_of = vec![0usize; instructions.len()];
+        for (name, options) in names.iter().zip(&options) {
+            buckets_of[*name] += options.len();
+        }
+
+        // Every bucket counted in place, indexed like the entries of the lookup.
+        let mut count = vec![0usize; 1 << bits.len()];
+        let mut weight = vec![0.0; 1 << bits.len()];
+        for ((options, name), w) in options.iter().zip(&names).zip(&weights) {
+            // An encoding spread over several buckets splits its weight between them.
+            let share = w / buckets_of[*name] as f64;
+            for &index in options {
+                count[index] += 1;
+                weight[index] += share;
+            }
+        }
+        count.iter().zip(&weight).filter(|(count, _)| **count > 0).map(|(&count, weight)| weight * (count as f64).log2()).sum::<f64>()
+    };
+
+    let mut selected_bits: Vec<usize> = Vec::with_capacity(budget);
+    for _ in 0..budget {
+        let best_bit = (0..32)
+            .filter(|i| !selected_bits.contains(i))
+            .map(|i| (i, cost(&[selected_bits.as_slice(), &[i]].concat())))
+            .fold(None, |best: Option<(usize, f64)>, (i, c)| match best {
+                Some((_, best_cost)) if best_cost <= c => best,
+                _ => Some((i, c))
+            });
+
+        if let Some((b, _)) = best_bit {
+            selected_bits.push(b);
+        }
+    }
+
+    selected_bits
+}
+
 /*
 pub fn individualization_differentiation(bit_patterns: &[&[Option<ir::Bit>; 32]], budget: usize) -> Vec<usize> {
     // WTF
@@ -214,18 +334,46 @@ mod tests {
 
     #[test]
     fn test_score_split() {
-        let mut inst_1 = [None; 32];
-        let mut inst_2 = [None; 32];
-        inst_1[0] = Some(ir::Bit::One);
-        inst_2[0] = Some(ir::Bit::Zero);
-
-        let instructions = &[
-            &inst_1,
-            &inst_2,
-        ];
+        // Bit 0 is one in the first and zero in the second, the others are free.
+        let instructions = &[(1, 1), (1, 0)];
+
+        assert!(score_split(instructions, &[1., 1.], 0, false) == 0.);
+        assert!(score_split(instructions, &[1., 1.], 1, false) == 1.);
+        // A frequent instruction unbalances an even split.
+        assert!(score_split(instructions, &[3., 1.], 0, false) == 0.5);
+        assert!(can_individually_differentiate(instructions));
+        assert!(!can_individually_differentiate(&[(1, 1), (1, 1), (2, 2)]));
+        assert!(!can_individually_differentiate(&[(1, 1), (0, 0)]));
+    }
 
-        assert!(score_split(instructions, 0, false).0 == 0.);
-        assert!(score_split(instructions, 1, false).0 == 1.);
+    #[test]
+    fn test_frequencies() {
+        use crate::ir::fixtures::instruction;
+
+        let general = instruction("GENERAL", "1110 1110 xxxx xxxx xxxx 1011 xxx1 0000");
+        let special = instruction("SPECIAL", "1110 1110 000x xxxx xxxx 1011 0001 0000");
+        let other = instruction("OTHER", "1111 0010 xxxx xxxx xxxx 1000 xxx0 xxxx");
+
+        let histogram = "# name or word, then count\nOTHER 10\n0xEE000B10 4\n0xEEF00B90\n\n0x00000000 7\nOTHER";
+        let frequencies = Frequencies::parse(histogram).unwrap().resolve(&[&general, &special, &other]);
+        assert_eq!(frequencies.weight(&other), 12.);
+        assert_eq!(frequencies.weight(&special), 5.);
+        assert_eq!(frequencies.weight(&general), 2.);
+        assert_eq!(Frequencies::default().weight(&general), 1.);
+
+        // A word of two encodings isn't split, it goes to the one the tree decodes it as.
+        let mut first = instruction("FIRST", "1111 0010 xxxx xxxx xxxx 1000 xxx0 xxxx");
+        let mut second = instruction("SECOND", "1111 0010 xxxx xxxx xxxx 1000 xxxx xxx0");
+        (first.index, second.index) = (Some(1), Some(0));
+        let words = Frequencies::parse("0xF2000800 4").unwrap();
+        assert_eq!(words.resolve(&[&first, &second]).weight(&second), 5.);
+        // Unless it redirects the word with `SEE`.
+        second.see = Box::new([ir::See { mask: 0xF, value: 0, target: Box::from("FIRST") }]);
+        assert_eq!(words.resolve(&[&first, &second]).weight(&first), 5.);
+
+        let error = Frequencies::parse("OTHER\n0xZZ 3").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: malformed instruction word");
+        assert!(Frequencies::parse("OTHER 1 2").is_err());
     }
 
     /*
@@ -249,11 +397,9 @@ mod tests {
     #[test]
     fn test_individualization() {
         return;
-        const ARM_SPEC_32: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
-
         //const ARM_SPEC: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-A64-ISA/ISA_A64/ISA_A64_xml_A_profile-2025-06.tar.gz";
-        let stream = crate::fetcher::arm::InstructionSpecificationStream::connect(ARM_SPEC_32).unwrap();
-        let instructions = crate::parser::arm::parse_into_ir(stream);
+        let source = crate::fetcher::arm::SpecSource::aarch32().unwrap();
+        let instructions = crate::parser::arm::parse_into_ir(&source, &mut vec![]).unwrap();
         println!("{}", instructions.len());
 
         let mapped: Vec<_> = instructions.iter().map(|e| &e.pattern).collect();
@@ -293,8 +439,6 @@ mod tests {
                 let s = match b {
                     Some(ir::Bit::One) => "1",
                     Some(ir::Bit::Zero) => "0",
-                    Some(ir::Bit::NotOne) => "N",
-                    Some(ir::Bit::NotZero) => "Z",
                     None => "X"
                 };
                 result = result + s;
@@ -305,7 +449,8 @@ mod tests {
 
 
         for i in 1..13 {
-        let res = simple_individualistic_differentiation(mapped.as_slice(), i, false);
+        let fixed = instructions.iter().map(|e| e.fixed_bits()).collect::<Vec<_>>();
+        let res = simple_individualistic_differentiation(&fixed, &vec![1.0; mapped.len()], i, false);
         println!("{res:?}");
 
         
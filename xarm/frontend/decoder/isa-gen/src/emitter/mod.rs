@@ -1,4 +1,6 @@
 pub mod bits;
 pub mod classification;
 pub mod traits;
+pub mod options;
 pub mod strategies;
+pub mod semantics;
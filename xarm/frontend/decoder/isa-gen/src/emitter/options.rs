@@ -0,0 +1,188 @@
+use crate::emitter::classification::{Frequencies, InvalidHistogram};
+use crate::emitter::strategies::latency::{graph, search::Search};
+use crate::fetcher::arm::{FetchError, SpecSource};
+use crate::ir;
+use std::ffi::OsString;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Comma separated `instr-class` docvars to generate tables for, all supported classes by default.
+pub const CLASSES_ENV: &str = "XARM_ISA_CLASSES";
+/// Architecture version and features of the target core, e.g. `ARMv7` or `ARMv8.1,FEAT_PAN`.
+/// Encodings it doesn't implement decode as UNDEFINED, every encoding is implemented by default.
+pub const PROFILE_ENV: &str = "XARM_ISA_PROFILE";
+/// Histogram of encoding names or instruction words to balance the tables on, see `Frequencies`.
+pub const FREQUENCIES_ENV: &str = "XARM_ISA_FREQUENCIES";
+/// Share of the weight a filter has to split off to become a branch, `0.3..0.7` by default.
+pub const BRANCH_RATIO_ENV: &str = "XARM_ISA_BRANCH_RATIO";
+/// Nodes the search for a cheaper tree may expand per ISA, see `latency::search`. Unset the
+/// greedy tree is built.
+pub const SEARCH_ENV: &str = "XARM_ISA_SEARCH";
+/// Every variable [`Options::from_env`] reads.
+pub const ENV: [&str; 5] = [CLASSES_ENV, PROFILE_ENV, FREQUENCIES_ENV, BRANCH_RATIO_ENV, SEARCH_ENV];
+
+/// A variable [`Options::from_env`] couldn't make sense of.
+#[derive(Debug)]
+pub enum EnvError {
+    Invalid { var: &'static str, value: Box<str>, expected: &'static str },
+    /// The histogram `XARM_ISA_FREQUENCIES` names couldn't be read.
+    Io(PathBuf, io::Error),
+    Histogram(PathBuf, InvalidHistogram),
+}
+
+impl std::fmt::Display for EnvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvError::Invalid { var, value, expected } => write!(f, "{var}: expected {expected}, got `{value}`"),
+            EnvError::Io(path, e) => write!(f, "{FREQUENCIES_ENV}: {}: {e}", path.display()),
+            EnvError::Histogram(path, e) => write!(f, "{FREQUENCIES_ENV}: {}:{e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+/// What a `CodeEmitter` generates and how the tree is shaped.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Where the specification is read from, `SpecSource::for_isa` when `None`.
+    pub source: Option<SpecSource>,
+    pub isa: ir::Isa,
+    /// `instr-class` docvars to generate tables for.
+    pub classes: ir::InstrClassSet,
+    /// Encodings the profile doesn't implement decode as UNDEFINED.
+    pub profile: ir::Profile,
+    /// The histogram the tree is balanced on, see `Frequencies`.
+    pub frequencies: Frequencies,
+    /// Bits a lookup takes at most. The default `Entry` has room for [`graph::BUDGET`], wider
+    /// budgets up to `lut::MAX_BUDGET` take entries of 8 masks and 256 ways, `PextCodeEmitter`
+    /// allows up to `pext::BUDGET`.
+    pub budget: usize,
+    /// Share of the weight a filter has to carve out of a bucket to become a branch before
+    /// falling back to a lookup, branches outside of it would leave a lopsided tree.
+    pub branch_ratio: Range<f64>,
+    /// Searches for a cheaper tree than the greedy one, `None` builds the greedy one alone.
+    pub search: Option<Search>
+}
+
+impl Options {
+    pub fn new(isa: ir::Isa) -> Self {
+        Self {
+            source: None,
+            isa,
+            classes: ir::InstrClassSet::default(),
+            profile: ir::Profile::default(),
+            frequencies: Frequencies::default(),
+            budget: graph::BUDGET,
+            branch_ratio: 0.30..0.70,
+            search: None
+        }
+    }
+
+    /// The defaults with whatever the `XARM_ISA_*` variables set, the way the `decoder` build
+    /// reads them. `isa`, `source` and `budget` are left to the caller.
+    pub fn from_env() -> Result<Self, EnvError> {
+        Self::from_vars(std::env::var_os)
+    }
+
+    fn from_vars(var: impl Fn(&'static str) -> Option<OsString>) -> Result<Self, EnvError> {
+        let mut options = Self::default();
+        let string = |name| var(name).map(|value| value.to_string_lossy().into_owned());
+        let invalid = |var, value: String, expected| EnvError::Invalid { var, value: value.into(), expected };
+
+        if let Some(value) = string(CLASSES_ENV).filter(|value| !value.is_empty()) {
+            options.classes = ir::InstrClassSet::parse(&value)
+                .ok_or_else(|| invalid(CLASSES_ENV, value, "comma separated instruction classes"))?;
+        }
+        if let Some(value) = string(PROFILE_ENV) {
+            options.profile = ir::Profile::parse(&value)
+                .ok_or_else(|| invalid(PROFILE_ENV, value, "a version and FEAT_* list"))?;
+        }
+        if let Some(path) = var(FREQUENCIES_ENV).filter(|path| !path.is_empty()) {
+            let path = PathBuf::from(path);
+            let contents = std::fs::read_to_string(&path).map_err(|e| EnvError::Io(path.clone(), e))?;
+            options.frequencies = Frequencies::parse(&contents).map_err(|e| EnvError::Histogram(path, e))?;
+        }
+        if let Some(value) = string(BRANCH_RATIO_ENV) {
+            options.branch_ratio = parse_ratio(&value)
+                .ok_or_else(|| invalid(BRANCH_RATIO_ENV, value, "`start..end` within 0..1"))?;
+        }
+        if let Some(value) = string(SEARCH_ENV) {
+            options.search = Some(parse_search(&value).ok_or_else(|| invalid(SEARCH_ENV, value, "a number of nodes"))?);
+        }
+        Ok(options)
+    }
+
+    /// [`Self::source`], or the release `isa` is described in.
+    pub fn spec_source(&self) -> Result<SpecSource, FetchError> {
+        match &self.source {
+            Some(source) => Ok(source.clone()),
+            None => SpecSource::for_isa(self.isa)
+        }
+    }
+
+    /// The specification `source` describes, encodings that couldn't be parsed are added to
+    /// `warnings`.
+    pub fn load(&self, warnings: &mut Vec<crate::parser::arm::Warning>) -> Vec<ir::Instruction> {
+        let source = self.spec_source();
+        let source = source.and_then(|source| crate::parser::arm::parse_into_ir(&source, warnings));
+        source.unwrap_or_else(|e| panic!("Failed to load the ISA specification: {e}"))
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new(ir::Isa::A32)
+    }
+}
+
+/// A branch ratio window as `start..end`, e.g. `0.3..0.7`.
+pub fn parse_ratio(value: &str) -> Option<Range<f64>> {
+    let (start, end) = value.split_once("..")?;
+    let (start, end) = (start.trim().parse::<f64>().ok()?, end.trim().parse::<f64>().ok()?);
+    (0.0..=1.0).contains(&start).then_some(())?;
+    (start < end && end <= 1.0).then_some(start..end)
+}
+
+/// A search budget in nodes to expand, with the default costs and time limit of `Search::new`.
+pub fn parse_search(value: &str) -> Option<Search> {
+    value.trim().parse().ok().map(Search::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("0.3..0.7"), Some(0.3..0.7));
+        assert_eq!(parse_ratio(" 0 .. 1 "), Some(0.0..1.0));
+        assert_eq!(parse_ratio("0.7..0.3"), None);
+        assert_eq!(parse_ratio("0.5..1.5"), None);
+        assert_eq!(parse_ratio("0.5"), None);
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let vars = |vars: &'static [(&str, &str)]| move |var| {
+            vars.iter().find(|(name, _)| *name == var).map(|(_, value)| OsString::from(value))
+        };
+        let options = Options::from_vars(vars(&[(BRANCH_RATIO_ENV, "0.2..0.8"), (SEARCH_ENV, "100"), (CLASSES_ENV, "")])).unwrap();
+        assert_eq!(options.branch_ratio, 0.2..0.8);
+        assert_eq!(options.search.map(|search| search.expansions), Some(100));
+        assert_eq!(options.classes, ir::InstrClassSet::default());
+
+        let error = Options::from_vars(vars(&[(SEARCH_ENV, "soon")])).unwrap_err();
+        assert_eq!(error.to_string(), "XARM_ISA_SEARCH: expected a number of nodes, got `soon`");
+        let missing = Options::from_vars(vars(&[(FREQUENCIES_ENV, "/nonexistent/histogram")])).unwrap_err();
+        assert!(matches!(missing, EnvError::Io(..)));
+    }
+
+    #[test]
+    fn test_parse_search() {
+        assert_eq!(parse_search(" 250 ").map(|search| search.expansions), Some(250));
+        assert_eq!(parse_search("0.5"), None);
+        assert_eq!(parse_search("-1"), None);
+    }
+}
@@ -0,0 +1,798 @@
+//! Turns the ASL of the specification into Rust running against `isa_gen_nostd::semantics`.
+//!
+//! Every value is a dynamic `rt::Value`, so the generated code doesn't need the type inference ASL
+//! relies on. Registers, memory and anything else not defined by the pseudocode itself goes through
+//! the `rt::Cpu` trait. Constructs the generator can't translate turn the whole function into one
+//! returning `Exception::Unsupported`, and are listed in the [`Report`].
+
+use std::collections::{HashMap, HashSet};
+
+use crate::asl::ast::*;
+use crate::{asl, ir};
+
+use isa_gen_nostd::semantics::BUILTINS;
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote};
+
+/// What couldn't be translated, by function or encoding.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub generated: usize,
+    pub unsupported: Vec<(Box<str>, Box<str>)>
+}
+
+impl Report {
+    fn unsupported(&mut self, item: impl Into<Box<str>>, reason: impl Into<Box<str>>) {
+        self.unsupported.push((item.into(), reason.into()));
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} functions generated, {} unsupported", self.generated, self.unsupported.len())?;
+        for (item, reason) in &self.unsupported {
+            writeln!(f, "{item}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+type Unsupported = Box<str>;
+
+fn unsupported<T>(what: impl std::fmt::Display) -> Result<T, Unsupported> {
+    Err(what.to_string().into())
+}
+
+/// Functions, enumerations and globals of the shared pseudocode.
+#[derive(Debug, Default)]
+pub struct Library {
+    functions: Vec<Function>,
+    /// name -> arities with a generated function.
+    arities: HashMap<Box<str>, Vec<usize>>,
+    enumerations: HashSet<Box<str>>,
+    /// Globals and accessors, writes to them go to the CPU.
+    globals: HashSet<Box<str>>
+}
+
+fn library_ident(name: &str, arity: usize) -> proc_macro2::Ident {
+    format_ident!("lib_{}_{}", name.replace('.', "__"), arity)
+}
+
+impl Library {
+    /// Parses the blocks of `shared_pseudocode.xml`, blocks that don't parse are reported and left out.
+    pub fn parse(blocks: &[Box<str>], report: &mut Report) -> Self {
+        let mut library = Library::default();
+
+        for block in blocks {
+            let definitions = match asl::parse_library(block) {
+                Ok(definitions) => definitions,
+                Err(e) => {
+                    let title = block
+                        .lines()
+                        .map(str::trim)
+                        .find(|l| !l.is_empty() && !l.starts_with("//"))
+                        .unwrap_or_default();
+                    report.unsupported(title, format!("parse error, {e}"));
+                    continue;
+                }
+            };
+
+            for definition in definitions {
+                match definition {
+                    Definition::Function(function) => library.add(function, report),
+                    Definition::Enumeration(_, members) => library.enumerations.extend(members),
+                    Definition::Variable(_, name) | Definition::Accessor(name) => {
+                        library.globals.insert(name);
+                    }
+                    Definition::Constant(ty, name, value) => library.add(
+                        Function { name, params: vec![], returns: Some(ty), body: vec![Stmt::Return(Some(value))] },
+                        report
+                    ),
+                    Definition::Declaration => {}
+                }
+            }
+        }
+        library
+    }
+
+    /// Parses the shared pseudocode of the release `options` read, see
+    /// [`Options::spec_source`](crate::emitter::options::Options::spec_source). What the XML
+    /// parser couldn't read is reported as unsupported.
+    pub fn load(options: &crate::emitter::options::Options, report: &mut Report) -> Self {
+        let mut warnings = vec![];
+        let source = options.spec_source();
+        let blocks = source.and_then(|source| crate::parser::arm::parse_shared_pseudocode(&source, &mut warnings));
+        let blocks = blocks.unwrap_or_else(|e| panic!("Failed to load the shared pseudocode: {e}"));
+        for warning in warnings {
+            report.unsupported(warning.dropped, warning.error.to_string());
+        }
+        Self::parse(&blocks, report)
+    }
+
+    fn add(&mut self, function: Function, report: &mut Report) {
+        let arities = self.arities.entry(function.name.clone()).or_default();
+        // Overloads only differing in parameter types can't be told apart without types.
+        if arities.contains(&function.params.len()) {
+            report.unsupported(function.name, "overloaded by parameter types");
+            return;
+        }
+        arities.push(function.params.len());
+        self.functions.push(function);
+    }
+
+    fn has_function(&self, name: &str, arity: usize) -> bool {
+        self.arities.get(name).is_some_and(|a| a.contains(&arity))
+    }
+
+    /// Emits a `lib_<name>_<arity>` function per definition.
+    pub fn emit(&self, report: &mut Report) -> TokenStream {
+        let functions = self.functions.iter().map(|function| {
+            let ident = library_ident(&function.name, function.params.len());
+            let params = function.params.iter().map(|(_, name)| format_ident!("v_{}", &**name));
+
+            let mut scope = Scope::new(self, None);
+            scope.locals.extend(function.params.iter().map(|(_, name)| name.clone()));
+            // `bits(N) x` binds `N` to the width of the argument.
+            let mut widths = vec![];
+            for (ty, name) in &function.params {
+                if let Type::Bits(width) = ty
+                    && let Expr::Var(width) = &**width
+                    && !scope.locals.contains(width)
+                {
+                    let (param, width_ident) = (format_ident!("v_{}", &**name), format_ident!("v_{}", &**width));
+                    widths.push(quote! { let mut #width_ident = rt::Value::Int(#param.width()?); });
+                    scope.locals.insert(width.clone());
+                }
+            }
+
+            let body = scope.body(&function.body).unwrap_or_else(|reason| {
+                report.unsupported(function.name.clone(), reason.clone());
+                let reason = &*reason;
+                quote! { return Err(rt::Exception::Unsupported(#reason)); }
+            });
+            report.generated += 1;
+
+            quote! {
+                pub fn #ident<C: rt::Cpu + ?Sized>(cpu: &mut C, #(mut #params: rt::Value),*) -> rt::Result<rt::Value> {
+                    #(#widths)*
+                    #body
+                }
+            }
+        });
+
+        quote! {
+            use isa_gen_nostd::semantics as rt;
+
+            #(#functions)*
+        }
+    }
+}
+
+struct Scope<'a> {
+    library: &'a Library,
+    /// Prefix the generated library is reachable at from the emitted code.
+    library_path: TokenStream,
+    locals: HashSet<Box<str>>
+}
+
+impl<'a> Scope<'a> {
+    /// `library_path` is `None` inside the library, whose functions call each other directly.
+    fn new(library: &'a Library, library_path: Option<&TokenStream>) -> Self {
+        let library_path = library_path.map(|path| quote!(#path::)).unwrap_or_default();
+        Scope { library, library_path, locals: HashSet::new() }
+    }
+
+    fn local(&self, expr: &Expr) -> Option<proc_macro2::Ident> {
+        match expr {
+            Expr::Var(name) if self.locals.contains(name) => Some(format_ident!("v_{}", &**name)),
+            _ => None
+        }
+    }
+
+    /// Declares every local of `statements` up front, then runs them.
+    fn body(&mut self, statements: &[Stmt]) -> Result<TokenStream, Unsupported> {
+        let mut declared = vec![];
+        collect_locals(statements, self.library, &mut declared);
+        declared.retain(|name| self.locals.insert(name.clone()));
+
+        let declared = declared.iter().map(|name| format_ident!("v_{}", &**name));
+        let statements = self.statements(statements)?;
+        Ok(quote! {
+            #(let mut #declared = rt::Value::Unset;)*
+            #statements
+            Ok(rt::Value::Unset)
+        })
+    }
+
+    fn statements(&self, statements: &[Stmt]) -> Result<TokenStream, Unsupported> {
+        let statements = statements.iter().map(|s| self.statement(s)).collect::<Result<Vec<_>, _>>()?;
+        Ok(quote! { #(#statements)* })
+    }
+
+    fn statement(&self, statement: &Stmt) -> Result<TokenStream, Unsupported> {
+        Ok(match statement {
+            Stmt::Decl { ty, vars } => {
+                let vars = vars
+                    .iter()
+                    .map(|(name, value)| {
+                        let ident = format_ident!("v_{}", &**name);
+                        let value = match (value, ty) {
+                            (Some(value), _) => self.expr(value)?,
+                            // Bitvectors get their width, a slice may be assigned before the whole.
+                            (None, Type::Bits(_)) => self.unknown(ty)?,
+                            (None, _) => quote!(rt::Value::Unset)
+                        };
+                        Ok(quote! { #ident = #value; })
+                    })
+                    .collect::<Result<Vec<_>, Unsupported>>()?;
+                quote! { #(#vars)* }
+            }
+            Stmt::Assign(target, value) => {
+                let value = self.expr(value)?;
+                let assign = self.assign(target, quote!(value))?;
+                quote! {{ let value = #value; #assign }}
+            }
+            Stmt::Expr(expr) => {
+                let expr = self.expr(expr)?;
+                quote! { let _ = #expr; }
+            }
+            Stmt::If(arms, otherwise) => {
+                let arms = arms
+                    .iter()
+                    .map(|(cond, body)| Ok((self.expr(cond)?, self.statements(body)?)))
+                    .collect::<Result<Vec<_>, Unsupported>>()?;
+                let (conds, bodies): (Vec<_>, Vec<_>) = arms.into_iter().unzip();
+                let otherwise = match otherwise {
+                    Some(body) => self.statements(body)?,
+                    None => quote!()
+                };
+                quote! {
+                    #(if #conds.truthy()? { #bodies } else)* { #otherwise }
+                }
+            }
+            Stmt::Case(subject, arms, otherwise) => {
+                let subject = self.expr(subject)?;
+                let arms = arms
+                    .iter()
+                    .map(|(patterns, body)| {
+                        let tests = patterns.iter().map(|p| self.matches(quote!(subject), p)).collect::<Result<Vec<_>, _>>()?;
+                        Ok((quote! { #(#tests)||* }, self.statements(body)?))
+                    })
+                    .collect::<Result<Vec<_>, Unsupported>>()?;
+                let (tests, bodies): (Vec<_>, Vec<_>) = arms.into_iter().unzip();
+                let otherwise = match otherwise {
+                    Some(body) => self.statements(body)?,
+                    None => quote!()
+                };
+                quote! {{
+                    let subject = #subject;
+                    #(if #tests { #bodies } else)* { #otherwise }
+                }}
+            }
+            Stmt::For { var, from, to, down, body } => {
+                let ident = format_ident!("v_{}", &**var);
+                let (from, to) = (self.expr(from)?, self.expr(to)?);
+                let range = if *down { quote!((to..=from).rev()) } else { quote!(from..=to) };
+                let body = self.statements(body)?;
+                quote! {{
+                    let (from, to) = (#from.int()?, #to.int()?);
+                    for i in #range {
+                        #ident = rt::Value::Int(i);
+                        #body
+                    }
+                }}
+            }
+            Stmt::While(cond, body) => {
+                let (cond, body) = (self.expr(cond)?, self.statements(body)?);
+                quote! { while #cond.truthy()? { #body } }
+            }
+            Stmt::Repeat(body, cond) => {
+                let (body, cond) = (self.statements(body)?, self.expr(cond)?);
+                quote! { loop { #body if #cond.truthy()? { break; } } }
+            }
+            Stmt::Return(Some(value)) => {
+                let value = self.expr(value)?;
+                quote! { return Ok(#value); }
+            }
+            Stmt::Return(None) => quote! { return Ok(rt::Value::Unset); },
+            Stmt::Assert(cond) => {
+                let cond = self.expr(cond)?;
+                quote! { if !#cond.truthy()? { return Err(rt::Exception::AssertionFailed); } }
+            }
+            Stmt::Undefined => quote! { return Err(rt::Exception::Undefined); },
+            Stmt::Unpredictable => quote! { return Err(rt::Exception::Unpredictable); },
+            Stmt::See(target) => {
+                let target = &**target;
+                quote! { return Err(rt::Exception::See(#target)); }
+            }
+        })
+    }
+
+    /// Stores `value` (an expression of an already evaluated `rt::Value`) into `target`.
+    fn assign(&self, target: &Expr, value: TokenStream) -> Result<TokenStream, Unsupported> {
+        if let Some(ident) = self.local(target) {
+            return Ok(quote! { #ident = #value; });
+        }
+
+        Ok(match target {
+            Expr::Discard => quote! { let _ = #value; },
+            Expr::Var(name) => {
+                let name = &**name;
+                quote! { cpu.write(#name, #value)?; }
+            }
+            Expr::Field(base, fields) => {
+                if self.local(base).is_some() {
+                    return unsupported("assignment to a field of a local");
+                }
+                let Some(base) = base.path() else { return unsupported("assignment to a field of an expression") };
+                match &fields[..] {
+                    [field] => {
+                        let field = &**field;
+                        quote! { cpu.write_field(#base, #field, #value)?; }
+                    }
+                    fields => {
+                        let fields = fields.iter().map(|f| &**f);
+                        quote! { rt::write_fields(cpu, #base, &[#(#fields),*], #value)?; }
+                    }
+                }
+            }
+            Expr::Index(base, index) => {
+                let Some(base) = base.path() else { return unsupported("assignment to an index of an expression") };
+                if self.locals.contains(base.as_str()) {
+                    return unsupported("assignment to an index of a local");
+                }
+                let index = self.exprs(index)?;
+                quote! {{
+                    let index = [#(#index),*];
+                    cpu.write_array(#base, &index, #value)?;
+                }}
+            }
+            Expr::Slice(base, slices) => {
+                let [slice] = &slices[..] else { return unsupported("assignment to several slices") };
+                let bounds = self.bounds(slice)?;
+                if let Some(ident) = self.local(base) {
+                    quote! {{
+                        let (hi, lo) = #bounds;
+                        rt::set_slice(&mut #ident, hi, lo, #value)?;
+                    }}
+                } else {
+                    // Read, modify and write back whatever the slice is of.
+                    let current = self.expr(base)?;
+                    let store = self.assign(base, quote!(current))?;
+                    quote! {{
+                        let (hi, lo) = #bounds;
+                        let mut current = #current;
+                        rt::set_slice(&mut current, hi, lo, #value)?;
+                        #store
+                    }}
+                }
+            }
+            Expr::Tuple(items) => {
+                let len = items.len();
+                let stores = items
+                    .iter()
+                    .map(|item| {
+                        let store = self.assign(item, quote!(item))?;
+                        Ok(quote! {{ let item = items.next().unwrap(); #store }})
+                    })
+                    .collect::<Result<Vec<_>, Unsupported>>()?;
+                quote! {{
+                    let mut items = (#value).into_tuple(#len)?.into_iter();
+                    #(#stores)*
+                }}
+            }
+            _ => return unsupported("assignment to an expression")
+        })
+    }
+
+    fn exprs(&self, exprs: &[Expr]) -> Result<Vec<TokenStream>, Unsupported> {
+        exprs.iter().map(|e| self.expr(e)).collect()
+    }
+
+    /// A `(hi, lo)` tuple of `rt::Value`s for a slice.
+    fn bounds(&self, slice: &Slice) -> Result<TokenStream, Unsupported> {
+        Ok(match slice {
+            Slice::Single(bit) => {
+                let bit = self.expr(bit)?;
+                quote! {{ let bit = #bit; (bit.clone(), bit) }}
+            }
+            Slice::Range(hi, lo) => {
+                let (hi, lo) = (self.expr(hi)?, self.expr(lo)?);
+                quote! { (#hi, #lo) }
+            }
+            Slice::Width(lo, width) => {
+                let (lo, width) = (self.expr(lo)?, self.expr(width)?);
+                quote! {{
+                    let (lo, width) = (#lo, #width);
+                    (rt::Value::Int(lo.int()? + width.int()? - 1), lo)
+                }}
+            }
+        })
+    }
+
+    /// A test of `subject` (an `rt::Value` place) against a `when` or `IN` pattern.
+    fn matches(&self, subject: TokenStream, pattern: &Expr) -> Result<TokenStream, Unsupported> {
+        Ok(match pattern {
+            Expr::Bits(bits) => {
+                let bits = &**bits;
+                quote! { rt::matches(&#subject, #bits)? }
+            }
+            pattern => {
+                let pattern = self.expr(pattern)?;
+                quote! { rt::equals(&#subject, &#pattern)? }
+            }
+        })
+    }
+
+    fn unknown(&self, ty: &Type) -> Result<TokenStream, Unsupported> {
+        Ok(match ty {
+            Type::Bits(width) => {
+                let width = self.expr(width)?;
+                quote! { rt::unknown_bits(#width)? }
+            }
+            Type::Bit => quote! { rt::Value::bits(0, 1) },
+            Type::Integer => quote! { rt::Value::Int(0) },
+            Type::Boolean => quote! { rt::Value::Bool(false) },
+            Type::Real => quote! { rt::Value::Real(0.0) },
+            Type::Named(name) => return unsupported(format!("UNKNOWN of type {name}")),
+            Type::Tuple(_) => return unsupported("UNKNOWN tuple")
+        })
+    }
+
+    fn call(&self, name: &str, args: &[Expr]) -> Result<TokenStream, Unsupported> {
+        let values = self.exprs(args)?;
+        let bindings = (0..args.len()).map(|i| format_ident!("a{}", i)).collect::<Vec<_>>();
+
+        let call = if name == "EncodingSpecificOperations" {
+            // Decode already ran, it only matters to the interleaving in the specification.
+            quote! { rt::Value::Unset }
+        } else if BUILTINS.contains(&name) {
+            quote! { rt::builtin(#name, &[#(#bindings),*])? }
+        } else if self.library.has_function(name, args.len()) {
+            let path = &self.library_path;
+            let ident = library_ident(name, args.len());
+            quote! { #path #ident(&mut *cpu, #(#bindings),*)? }
+        } else {
+            quote! { cpu.call(#name, &[#(#bindings),*])? }
+        };
+
+        Ok(quote! {{
+            #(let #bindings = #values;)*
+            #call
+        }})
+    }
+
+    fn expr(&self, expr: &Expr) -> Result<TokenStream, Unsupported> {
+        Ok(match expr {
+            Expr::Int(value) => {
+                let value = Literal::i128_suffixed(*value);
+                quote! { rt::Value::Int(#value) }
+            }
+            Expr::Real(value) => {
+                let value = Literal::f64_suffixed(value.parse::<f64>().map_err(|_| Box::from("invalid real"))?);
+                quote! { rt::Value::Real(#value) }
+            }
+            Expr::Bits(bits) => {
+                if bits.len() > 128 || !bits.chars().all(|c| c == '0' || c == '1') {
+                    return unsupported(format!("bit pattern '{bits}' outside of a comparison"));
+                }
+                let value = Literal::u128_suffixed(if bits.is_empty() { 0 } else { u128::from_str_radix(bits, 2).unwrap() });
+                let width = bits.len() as u32;
+                quote! { rt::Value::bits(#value, #width) }
+            }
+            Expr::Str(value) => {
+                let value = &**value;
+                quote! { rt::Value::Str(#value) }
+            }
+            Expr::Var(name) => match &**name {
+                "TRUE" => quote! { rt::Value::Bool(true) },
+                "FALSE" => quote! { rt::Value::Bool(false) },
+                _ if self.locals.contains(name) => {
+                    let ident = format_ident!("v_{}", &**name);
+                    quote! { #ident.clone() }
+                }
+                name if self.library.enumerations.contains(name) => quote! { rt::Value::Enum(#name) },
+                // Constants are generated as functions without parameters.
+                name if self.library.has_function(name, 0) => self.call(name, &[])?,
+                name => quote! { cpu.read(#name)? }
+            },
+            Expr::Field(base, fields) => {
+                if self.local(base).is_some() {
+                    return unsupported("field of a local");
+                }
+                let Some(base) = base.path() else { return unsupported("field of an expression") };
+                let reads = fields.iter().map(|f| {
+                    let f = &**f;
+                    quote! { cpu.read_field(#base, #f)? }
+                });
+                let mut reads = reads.collect::<Vec<_>>();
+                let first = reads.remove(0);
+                reads.into_iter().fold(first, |acc, read| quote! { rt::binary(rt::BinOp::Concat, #acc, #read)? })
+            }
+            Expr::Index(base, index) => {
+                let Some(base) = base.path() else { return unsupported("index of an expression") };
+                if self.locals.contains(base.as_str()) {
+                    return unsupported("index of a local");
+                }
+                let index = self.exprs(index)?;
+                quote! {{
+                    let index = [#(#index),*];
+                    cpu.read_array(#base, &index)?
+                }}
+            }
+            Expr::Slice(base, slices) => {
+                let base = self.expr(base)?;
+                let parts = slices
+                    .iter()
+                    .map(|slice| {
+                        let bounds = self.bounds(slice)?;
+                        Ok(quote! {{ let (hi, lo) = #bounds; rt::slice(&base, hi, lo)? }})
+                    })
+                    .collect::<Result<Vec<_>, Unsupported>>()?;
+                let mut parts = parts.into_iter();
+                let first = parts.next().unwrap();
+                let value = parts.fold(first, |acc, part| quote! { rt::binary(rt::BinOp::Concat, #acc, #part)? });
+                quote! {{
+                    let base = #base;
+                    #value
+                }}
+            }
+            Expr::Call(name, args) => self.call(name, args)?,
+            Expr::Unary(op, value) => {
+                let value = self.expr(value)?;
+                let op = match op {
+                    UnOp::Neg => quote!(Neg),
+                    UnOp::BoolNot => quote!(BoolNot),
+                    UnOp::Not => quote!(Not)
+                };
+                quote! { rt::unary(rt::UnOp::#op, #value)? }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                // Comparisons against patterns with `x` in them.
+                if matches!(op, BinOp::Eq | BinOp::Ne) && matches!(**rhs, Expr::Bits(ref b) if b.contains('x')) {
+                    let lhs = self.expr(lhs)?;
+                    let test = self.matches(quote!(lhs), rhs)?;
+                    let negate = (*op == BinOp::Ne).then(|| quote!(!));
+                    return Ok(quote! {{ let lhs = #lhs; rt::Value::Bool(#negate #test) }});
+                }
+
+                let (lhs, rhs) = (self.expr(lhs)?, self.expr(rhs)?);
+                match op {
+                    BinOp::BoolAnd => quote! { rt::Value::Bool(#lhs.truthy()? && #rhs.truthy()?) },
+                    BinOp::BoolOr => quote! { rt::Value::Bool(#lhs.truthy()? || #rhs.truthy()?) },
+                    op => {
+                        let op = format_ident!("{}", format!("{op:?}"));
+                        quote! { rt::binary(rt::BinOp::#op, #lhs, #rhs)? }
+                    }
+                }
+            }
+            Expr::In(value, set) => {
+                let value = self.expr(value)?;
+                let tests = set.iter().map(|p| self.matches(quote!(value), p)).collect::<Result<Vec<_>, _>>()?;
+                quote! {{ let value = #value; rt::Value::Bool(#(#tests)||*) }}
+            }
+            Expr::If(cond, then, otherwise) => {
+                let (cond, then, otherwise) = (self.expr(cond)?, self.expr(then)?, self.expr(otherwise)?);
+                quote! { if #cond.truthy()? { #then } else { #otherwise } }
+            }
+            Expr::Tuple(items) => {
+                let items = self.exprs(items)?;
+                quote! { rt::Value::tuple([#(#items),*]) }
+            }
+            Expr::Unknown(ty) => self.unknown(ty)?,
+            Expr::ImplementationDefined(what) => {
+                let what = what.as_deref().unwrap_or_default();
+                quote! { cpu.call("IMPLEMENTATION_DEFINED", &[rt::Value::Str(#what)])? }
+            }
+            Expr::Discard => return unsupported("`-` outside of an assignment")
+        })
+    }
+}
+
+/// Names `statements` declare or assign that aren't globals of the library.
+fn collect_locals(statements: &[Stmt], library: &Library, locals: &mut Vec<Box<str>>) {
+    fn target(expr: &Expr, library: &Library, locals: &mut Vec<Box<str>>) {
+        match expr {
+            Expr::Var(name) if !library.globals.contains(name) => locals.push(name.clone()),
+            Expr::Tuple(items) => items.iter().for_each(|i| target(i, library, locals)),
+            // A slice assigned to a name whose declaration we don't see, the whole is local too.
+            Expr::Slice(base, _) => target(base, library, locals),
+            _ => {}
+        }
+    }
+
+    for statement in statements {
+        match statement {
+            Stmt::Decl { vars, .. } => locals.extend(vars.iter().map(|(name, _)| name.clone())),
+            Stmt::Assign(lhs, _) => target(lhs, library, locals),
+            Stmt::If(arms, otherwise) => {
+                arms.iter().for_each(|(_, body)| collect_locals(body, library, locals));
+                otherwise.iter().for_each(|body| collect_locals(body, library, locals));
+            }
+            Stmt::Case(_, arms, otherwise) => {
+                arms.iter().for_each(|(_, body)| collect_locals(body, library, locals));
+                otherwise.iter().for_each(|body| collect_locals(body, library, locals));
+            }
+            Stmt::For { var, body, .. } => {
+                locals.push(var.clone());
+                collect_locals(body, library, locals);
+            }
+            Stmt::While(_, body) | Stmt::Repeat(body, _) => collect_locals(body, library, locals),
+            _ => {}
+        }
+    }
+}
+
+fn is_identifier(label: &str) -> bool {
+    label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn execute_fn(inst: &ir::Instruction, library: &Library, library_path: &TokenStream) -> Result<TokenStream, Unsupported> {
+    let (Some(decode), Some(execute)) = (&inst.pseudocode.decode, &inst.pseudocode.execute) else {
+        return unsupported("no decode or execute pseudocode");
+    };
+    let mut statements = asl::parse_statements(decode).map_err(|e| format!("decode: {e}"))?;
+    statements.extend(asl::parse_statements(execute).map_err(|e| format!("execute: {e}"))?);
+
+    let mut scope = Scope::new(library, Some(library_path));
+
+    // Encoding fields are bitvectors of the word, fixed ones included, decode tests them.
+    let mut fields: Vec<(&str, Vec<std::ops::Range<usize>>)> = vec![];
+    for region in inst.regions.iter().filter(|r| is_identifier(&r.label)) {
+        match fields.iter_mut().find(|(n, _)| *n == &*region.label) {
+            Some((_, parts)) => parts.push(region.range.clone()),
+            None => fields.push((&region.label, vec![region.range.clone()])),
+        }
+    }
+    let fields = fields.into_iter().map(|(name, mut parts)| {
+        parts.sort_by_key(|p| std::cmp::Reverse(p.start));
+        scope.locals.insert(Box::from(name));
+
+        let ident = format_ident!("v_{}", name);
+        let width = parts.iter().map(|p| p.len() as u32).sum::<u32>();
+        let value = super::strategies::latency::instruction::extraction(&parts);
+        quote! { let mut #ident = rt::Value::bits((#value) as u128, #width); }
+    });
+    let fields = fields.collect::<Vec<_>>();
+
+    let body = scope.body(&statements)?;
+    Ok(quote! {
+        #(#fields)*
+        #body
+    })
+}
+
+/// Emits `InstructionView::execute`, running the decode and execute pseudocode of an encoding.
+/// `library_path` is where the output of [`Library::emit`] is reachable from the emitted code.
+pub fn emit_execute(instructions: &[&ir::Instruction], library: &Library, library_path: TokenStream, report: &mut Report) -> TokenStream {
+    let functions = instructions.iter().map(|inst| {
+        let ident = format_ident!("execute_{}", &*inst.name);
+        let body = execute_fn(inst, library, &library_path).unwrap_or_else(|reason| {
+            report.unsupported(inst.name.clone(), reason.clone());
+            let reason = &*reason;
+            quote! { Err(rt::Exception::Unsupported(#reason)) }
+        });
+        report.generated += 1;
+
+        quote! {
+            fn #ident<C: rt::Cpu + ?Sized>(cpu: &mut C, word: u32) -> rt::Result<rt::Value> {
+                #body
+            }
+        }
+    });
+    let functions = functions.collect::<Vec<_>>();
+
+    let arms = instructions.iter().map(|inst| {
+        let member = format_ident!("{}", &*inst.name);
+        let ident = format_ident!("execute_{}", &*inst.name);
+        quote! { InstructionView::#member => #ident(cpu, word) }
+    });
+
+    quote! {
+        use isa_gen_nostd::semantics as rt;
+
+        #(#functions)*
+
+        impl InstructionView {
+            /// Runs the decode and execute pseudocode of this encoding on `cpu`. `word` is the one
+            /// this view was decoded from.
+            pub fn execute<C: rt::Cpu + ?Sized>(self, cpu: &mut C, word: u32) -> rt::Result<()> {
+                match self {
+                    InstructionView::Undefined => Err(rt::Exception::Undefined),
+                    InstructionView::Unpredictable | InstructionView::ConstrainedUnpredictable => {
+                        Err(rt::Exception::Unpredictable)
+                    }
+                    #(#arms,)*
+                }
+                .map(|_| ())
+            }
+        }
+    }
+}
+
+/// Emits the execute functions for the `classes` of `isa` out of the `instructions`
+/// `Options::load` read, the same encodings `LatencyOptimizedCodeEmitter::emit` builds its
+/// `InstructionView` from. `library_path` is where `library` was emitted to, e.g.
+/// `crate::semantics::aarch32`.
+pub fn emit_isa(
+    instructions: &[ir::Instruction],
+    isa: ir::Isa,
+    classes: ir::InstrClassSet,
+    profile: &ir::Profile,
+    library: &Library,
+    library_path: &str,
+    report: &mut Report
+) -> TokenStream {
+    // The same leaves as the table, `execute` matches on its `InstructionView`.
+    let (_, patterns) = crate::emitter::strategies::latency::select(instructions, isa, classes, profile);
+    let library_path = library_path.parse::<TokenStream>().expect("library_path is not a path");
+    emit_execute(&patterns, library, library_path, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_report() {
+        let blocks = [
+            Box::from("integer Twice(integer x)\n    return x * 2;"),
+            Box::from("integer Twice(bits(N) x)\n    return UInt(x) * 2;"),
+            Box::from("SRType Pick()\n    return SRType UNKNOWN;"),
+            Box::from("bits(4) Broken(\n"),
+            Box::from("enumeration SRType {SRType_LSL, SRType_ROR};"),
+        ];
+        let mut report = Report::default();
+        let library = Library::parse(&blocks, &mut report);
+        assert!(library.has_function("Twice", 1));
+        assert!(library.enumerations.contains("SRType_ROR"));
+
+        let tokens = library.emit(&mut report).to_string();
+        assert!(tokens.contains("lib_Twice_1"));
+        assert!(tokens.contains("lib_Pick_0"));
+
+        let items = report.unsupported.iter().map(|(item, _)| &**item).collect::<Vec<_>>();
+        assert_eq!(items, ["Twice", "bits(4) Broken(", "Pick"]);
+        assert_eq!(report.generated, 2);
+    }
+
+    #[test]
+    fn test_execute_fields() {
+        let mut inst = ir::Instruction {
+            pattern: [None; 32],
+            regions: Box::new([
+                ir::BitRegion { label: "imm".into(), range: 28..32 },
+                ir::BitRegion { label: "S".into(), range: 20..21 },
+                ir::BitRegion { label: "imm".into(), range: 0..4 },
+            ]),
+            field_constraints: Box::new([]),
+            name: "TEST_A1".into(),
+            isa: ir::Isa::A32,
+            width: ir::Width::Word,
+            class: ir::InstrClass::General,
+            docvars: ir::Docvars::default(),
+            arch_variants: Box::new([]),
+            see: Box::new([]),
+            unpredictable: Box::new([]),
+            index: None,
+            pseudocode: ir::Pseudocode::default()
+        };
+        let mut report = Report::default();
+        let library = Library::default();
+        emit_execute(&[&inst], &library, quote!(lib), &mut report);
+        assert_eq!(report.unsupported.len(), 1);
+
+        inst.pseudocode = ir::Pseudocode {
+            decode: Some("setflags = (S == '1');  imm32 = ZeroExtend(imm, 32);".into()),
+            execute: Some("if setflags then PSTATE.<N,Z> = imm32<31:30>;\nR[0] = imm32;".into())
+        };
+        let mut report = Report::default();
+        let tokens = emit_execute(&[&inst], &library, quote!(lib), &mut report).to_string();
+        assert!(report.unsupported.is_empty(), "{report}");
+        // Split fields are joined highest part first, fixed or not every field is bound.
+        assert!(tokens.contains("let mut v_imm = rt :: Value :: bits"));
+        assert!(tokens.contains("let mut v_S"));
+        assert!(tokens.contains("rt :: write_fields (cpu , \"PSTATE\" , & [\"N\" , \"Z\"]"));
+        assert!(tokens.contains("cpu . write_array (\"R\""));
+    }
+}
@@ -0,0 +1,76 @@
+//! Writes the `Entry` pool as an artifact the `decoder` crate loads at runtime, see
+//! `isa_gen_nostd::artifact` for the layout.
+
+use isa_gen_nostd::{Entry, Word};
+use isa_gen_nostd::artifact::{self, Header};
+use crate::ir;
+
+/// The artifact of `pool` rooted at `root`, leaf `n` being named `names[n - Descriptor::RESERVED]`.
+pub fn write<const MASKS: usize, const WAYS: usize, W: Word>(
+    isa: ir::Isa,
+    pool: &[Entry<MASKS, WAYS, W>],
+    root: W,
+    names: &[&str]
+) -> Vec<u8> {
+    let isa = match isa {
+        ir::Isa::A32 => artifact::Isa::A32,
+        ir::Isa::T32 => artifact::Isa::T32,
+        ir::Isa::A64 => artifact::Isa::A64
+    };
+    let header = Header {
+        root: root.to_usize() as u32,
+        entries: pool.len() as u32,
+        leaves: names.len() as u32,
+        names_len: names.iter().map(|name| name.len() as u32 + 1).sum(),
+        ..Header::for_entry::<MASKS, WAYS, W>(isa)
+    };
+
+    let mut bytes = Vec::with_capacity(header.artifact_len());
+    bytes.extend_from_slice(&header.to_bytes());
+    for entry in pool {
+        let start = bytes.len();
+        bytes.resize(start + Entry::<MASKS, WAYS, W>::LEN, 0);
+        entry.write_le_bytes(&mut bytes[start..]);
+    }
+    for name in names {
+        debug_assert!(!name.contains('\0'));
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isa_gen_nostd::Descriptor;
+
+    #[test]
+    fn test_write() {
+        let mut entry = Entry { bitmasks: [0x0F00_0000, 0, 0, 0], expected: [0x0100_0000, 1, 1, 1], ..Default::default() };
+        entry.entries[0] = Descriptor::new_leaf(Descriptor::RESERVED);
+        entry.entries[1] = Descriptor::new_leaf(Descriptor::RESERVED + 1);
+
+        let bytes = write(ir::Isa::T32, &[entry], 0, &["ADC", "B"]);
+        assert_eq!(bytes.len(), artifact::HEADER_LEN + artifact::ENTRY_LEN + 6);
+        assert_eq!(&bytes[bytes.len() - 6..], b"ADC\0B\0");
+
+        let header = Header::from_bytes(bytes[..artifact::HEADER_LEN].try_into().unwrap()).unwrap();
+        let expected = Header { version: artifact::VERSION, isa: 1, masks: 4, descriptor: 2, root: 0, entries: 1, leaves: 2, names_len: 6 };
+        assert_eq!(header, expected);
+        assert!(header.is_entry::<4, 16, u16>() && !header.is_entry::<4, 16, u32>());
+        let read: Entry = Entry::from_le_bytes(&bytes[artifact::HEADER_LEN..]);
+        assert_eq!(read, entry);
+        assert_eq!(read.entries[1].0, Descriptor::RESERVED + 1);
+
+        // 8 masks and `u32` descriptors, 1088 bytes an entry.
+        let mut wide = Entry::<8, 256, u32> { bitmasks: [1; 8], expected: [1; 8], ..Default::default() };
+        wide.entries[255] = Descriptor::new_entry(0x1_0000);
+        let bytes = write(ir::Isa::A64, &[wide, wide], 1, &[]);
+        let header = Header::from_bytes(bytes[..artifact::HEADER_LEN].try_into().unwrap()).unwrap();
+        assert_eq!((header.masks, header.descriptor, header.root, header.entry_len()), (8, 4, 1, 1088));
+        assert_eq!(bytes.len(), header.artifact_len());
+        let read = Entry::<8, 256, u32>::from_le_bytes(&bytes[artifact::HEADER_LEN + header.entry_len()..]);
+        assert_eq!(read, wide);
+    }
+}
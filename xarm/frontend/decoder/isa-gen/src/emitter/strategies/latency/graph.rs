@@ -1,11 +1,10 @@
 // TODO: graph implies it connects to self
 use crate::{
     ir,
-    emitter::{classification, bits}
+    emitter::{classification::{self, Frequencies}, bits, options::Options}
 };
 
 use std::collections::HashMap;
-use std::ops::RangeInclusive;
 
 #[derive(Debug, Clone)]
 pub enum Node<'a> {
@@ -24,6 +23,23 @@ pub enum Node<'a> {
     Leaf(&'a ir::Instruction)
 }
 
+/// Encodings no bit tells apart and the spec gives no precedence between, the table would
+/// decode their shared words as either.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overlaps(pub Vec<(Box<str>, Box<str>)>);
+
+impl std::fmt::Display for Overlaps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} pairs of encodings overlap without a precedence:", self.0.len())?;
+        for (a, b) in &self.0 {
+            write!(f, "\n  {a} / {b}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Overlaps {}
+
 impl<'a> Node<'a> {
     /// Returns the maximum depth of the tree.
     /// A single Leaf has a depth of 1.
@@ -51,6 +67,38 @@ impl<'a> Node<'a> {
         if count == 0 { 0.0 } else { total_depth as f64 / count as f64 }
     }
 
+    /// Returns the path length to reach a leaf weighted by how often its encoding is decoded.
+    /// An encoding in several leaves splits its weight between them.
+    pub fn expected_depth(&self, frequencies: &Frequencies) -> f64 {
+        let mut leaves = vec![];
+        self.leaf_depths(1, &mut leaves);
+
+        let mut occurrences = HashMap::<&str, usize>::new();
+        for (inst, _) in &leaves {
+            *occurrences.entry(&inst.name).or_default() += 1;
+        }
+        let weight = |inst: &ir::Instruction| frequencies.weight(inst) / occurrences[&*inst.name] as f64;
+
+        let total = leaves.iter().map(|(inst, _)| weight(inst)).sum::<f64>();
+        let depths = leaves.iter().map(|(inst, depth)| weight(inst) * *depth as f64).sum::<f64>();
+        if total == 0.0 { 0.0 } else { depths / total }
+    }
+
+    pub(crate) fn leaf_depths(&self, current_depth: usize, leaves: &mut Vec<(&'a ir::Instruction, usize)>) {
+        match self {
+            Node::Leaf(inst) => leaves.push((inst, current_depth)),
+            Node::Branch { then, r#else, .. } => {
+                then.leaf_depths(current_depth + 1, leaves);
+                r#else.leaf_depths(current_depth + 1, leaves);
+            }
+            Node::Lookup { entries, .. } => {
+                for entry in entries.iter().flatten() {
+                    entry.leaf_depths(current_depth + 1, leaves);
+                }
+            }
+        }
+    }
+
     /// Helper for average_depth: returns (sum of all leaf depths, count of leaves)
     pub fn depth_sum_and_count(&self, current_depth: usize) -> (usize, usize) {
         match self {
@@ -71,73 +119,61 @@ impl<'a> Node<'a> {
     }
 }
 
-fn is_instruction_specialized(inst: &ir::Instruction, filter_range: RangeInclusive<usize>, bits: &[Option<ir::Bit>]) -> bool {
-    inst.pattern[filter_range].iter().zip(bits).all(|(inst_bit, filter_bit)| {
-        let filter_bit_mapped = match filter_bit {
-            Some(ir::Bit::NotOne) => Some(ir::Bit::One),
-            Some(ir::Bit::NotZero) => Some(ir::Bit::Zero),
-            _ => None
-        };
-
-        if let Some(filter_bit_required) = filter_bit_mapped {
-            inst_bit == &Some(filter_bit_required)
-        } else {
-            true
-        }
-    })
+/// Whether every word of `inst` falls in the value `constraint` rules out, like the
+/// unconditional encodings in the `cond == 1111` space.
+fn is_instruction_specialized(inst: &ir::Instruction, constraint: &ir::FieldConstraint) -> bool {
+    let (mask, value) = inst.fixed_bits();
+    constraint.excludes(mask, value)
 }
 
 #[derive(Debug)]
 struct SpecializationBranch<'a> {
-    filter_range: RangeInclusive<usize>,
-    filter_bits: &'a [Option<ir::Bit>],
+    constraint: &'a ir::FieldConstraint,
     specialized: Vec<&'a ir::Instruction>,
     not_specialized: Vec<&'a ir::Instruction>
 }
 
-fn decide_specialization_branch<'a>(instructions: &[&'a ir::Instruction]) -> Option<SpecializationBranch<'a>> {
-    let filter_cases = instructions
+fn decide_specialization_branch<'a>(
+    instructions: &[&'a ir::Instruction],
+    frequencies: &Frequencies,
+    options: &Options
+) -> Option<SpecializationBranch<'a>> {
+    let mut constraints = instructions
         .iter()
-        .filter(|i| !i.filters.is_empty())
-        .flat_map(|i| {
-            i.filters.iter().map(|f| {
-                (f, &i.pattern[f.clone()])
-            })
-        })
-        // Use RangeInclusive as hash key to remove duplicates
-        .collect::<HashMap<_, _>>()
-        .into_iter()
+        .flat_map(|i| i.field_constraints.iter())
         .collect::<Vec<_>>();
+    // Ties keep the first candidate, sorted it doesn't depend on the order of the encodings.
+    constraints.sort_by_key(|c| (*c.range.start(), *c.range.end(), c.value));
+    constraints.dedup();
 
     let mut best_specialized_ratio: Option<f64> = None;
     let mut best_filter = None;
-    for (range, bits) in &filter_cases {
+    for constraint in constraints {
         let mut specialized = vec![];
         let mut not_specialized = vec![];
 
         for inst in instructions {
-            if is_instruction_specialized(inst, (*range).clone(), bits) {
+            if is_instruction_specialized(inst, constraint) {
                 specialized.push(*inst);
             } else {
                 not_specialized.push(*inst);
             }
         }
 
-        let specialized_ratio = specialized.len() as f64 / instructions.len() as f64;
+        let weight = |insts: &[&ir::Instruction]| insts.iter().map(|i| frequencies.weight(i)).sum::<f64>();
+        let specialized_ratio = weight(&specialized) / weight(instructions);
         if best_specialized_ratio.is_none() || ((specialized_ratio - 0.5).abs() < (best_specialized_ratio.unwrap() - 0.5).abs()) {
             best_specialized_ratio = Some(specialized_ratio);
             best_filter = Some(SpecializationBranch {
-                filter_range: (*range).clone(),
-                filter_bits: bits,
+                constraint,
                 specialized,
                 not_specialized,
             });
         }
     }
 
-    let specialized_range = 0.30..0.70;
     if let Some(best_ratio) = best_specialized_ratio {
-        if specialized_range.contains(&best_ratio) {
+        if options.branch_ratio.contains(&best_ratio) {
             return best_filter;
         }
     }
@@ -145,12 +181,132 @@ fn decide_specialization_branch<'a>(instructions: &[&'a ir::Instruction]) -> Opt
     None
 }
 
-fn individualize_prefer_branch<'a>(instructions: &[&'a ir::Instruction], budget: usize) -> Node<'a> {
-    let debugging = 
-        instructions.iter().find(|i| i.name == Box::from("PLDW_i_A1")).is_some() &&
-        instructions.iter().find(|i| i.name == Box::from("TST_i_A1")).is_some();
+/// The words both encodings match as a mask and value, `None` when they don't overlap.
+fn intersection(a: &ir::Instruction, b: &ir::Instruction) -> Option<(u32, u32)> {
+    let ((mask_a, value_a), (mask_b, value_b)) = (a.fixed_bits(), b.fixed_bits());
+    if mask_a & mask_b & (value_a ^ value_b) != 0 {
+        return None;
+    }
+
+    let (mask, value) = (mask_a | mask_b, value_a | value_b);
+    let excluded = |inst: &ir::Instruction| inst.field_constraints.iter().any(|c| c.excludes(mask, value));
+    (!excluded(a) && !excluded(b)).then_some((mask, value))
+}
+
+/// Whether `a` decodes the `shared` words of both instead of `b`, by the precedence the spec
+/// gives: a `SEE` to the other encoding, a special case carved out of a general encoding and at
+/// last the order of the encoding index. `None` when it gives none.
+fn precedes(a: &ir::Instruction, b: &ir::Instruction, (mask, value): (u32, u32)) -> Option<bool> {
+    let redirects = |inst: &ir::Instruction| inst.see.iter().any(|see| see.covers(mask, value));
+    match (redirects(a), redirects(b)) {
+        (true, false) => return Some(false),
+        (false, true) => return Some(true),
+        _ => {}
+    }
+
+    let (mask_a, mask_b) = (a.fixed_bits().0, b.fixed_bits().0);
+    if mask_a != mask_b && mask_a & mask_b == mask_b {
+        return Some(true);
+    }
+    if mask_a != mask_b && mask_a & mask_b == mask_a {
+        return Some(false);
+    }
+
+    match (a.index, b.index) {
+        (Some(index_a), Some(index_b)) if index_a != index_b => Some(index_a < index_b),
+        _ => None
+    }
+}
+
+fn overlap(overlaps: &mut Overlaps, a: &ir::Instruction, b: &ir::Instruction) {
+    let pair = if a.name <= b.name { (a.name.clone(), b.name.clone()) } else { (b.name.clone(), a.name.clone()) };
+    if !overlaps.0.contains(&pair) {
+        overlaps.0.push(pair);
+    }
+}
 
+/// Orders `instructions` so each comes before the overlapping ones it takes precedence over,
+/// otherwise keeping their order. Pairs without a precedence are added to `overlaps`.
+pub(crate) fn precedence_order<'a>(instructions: &[&'a ir::Instruction], overlaps: &mut Overlaps) -> Vec<&'a ir::Instruction> {
+    let mut before = vec![vec![]; instructions.len()];
+    for (i, a) in instructions.iter().enumerate() {
+        for (j, b) in instructions.iter().enumerate().skip(i + 1) {
+            let Some(shared) = intersection(a, b) else { continue };
+            match precedes(a, b, shared) {
+                Some(true) => before[j].push(i),
+                Some(false) => before[i].push(j),
+                None => overlap(overlaps, a, b)
+            }
+        }
+    }
+
+    let mut placed = vec![false; instructions.len()];
+    let mut order = vec![];
+    while order.len() < instructions.len() {
+        let next = (0..instructions.len()).find(|&i| !placed[i] && before[i].iter().all(|&p| placed[p]));
+        let Some(next) = next else {
+            // A cycle, the precedences contradict each other.
+            for (i, preceding) in before.iter().enumerate().filter(|(i, _)| !placed[*i]) {
+                for &p in preceding.iter().filter(|&&p| !placed[p]) {
+                    overlap(overlaps, instructions[p], instructions[i]);
+                }
+            }
+            order.extend((0..instructions.len()).filter(|&i| !placed[i]).map(|i| instructions[i]));
+            break;
+        };
+        placed[next] = true;
+        order.push(instructions[next]);
+    }
+    order
+}
+
+/// Last resort for encodings no bit can tell apart, e.g. a special case carved out of a more
+/// general encoding. They are tested in precedence order, words the first one redirects with
+/// `SEE` go on to the rest.
+fn resolve_overlap<'a>(instructions: &[&'a ir::Instruction], overlaps: &mut Overlaps) -> Node<'a> {
+    let order = precedence_order(instructions, overlaps);
+    precedence_chain(&order)
+}
+
+fn precedence_chain<'a>(order: &[&'a ir::Instruction]) -> Node<'a> {
+    let (first, rest) = order.split_first().unwrap();
+    let (bitmask, value) = first.fixed_bits();
+    if rest.is_empty() {
+        return Node::Leaf(first);
+    }
+
+    let mut node = if bitmask == 0 {
+        Node::Leaf(first)
+    } else {
+        Node::Branch {
+            bitmask,
+            value,
+            then: Box::new(Node::Leaf(first)),
+            r#else: Box::new(precedence_chain(rest))
+        }
+    };
+
+    // Only redirects to one of the rest matter here, the others were decided further up.
+    let relevant = first.see.iter().filter(|see| {
+        rest.iter().any(|r| intersection(first, r).is_some_and(|(mask, value)| see.covers(mask, value)))
+    });
+    for see in relevant {
+        node = Node::Branch {
+            bitmask: see.mask,
+            value: see.value,
+            then: Box::new(precedence_chain(rest)),
+            r#else: Box::new(node)
+        };
+    }
+    node
+}
 
+pub(super) fn individualize_prefer_branch<'a>(
+    instructions: &[&'a ir::Instruction],
+    options: &Options,
+    frequencies: &Frequencies,
+    overlaps: &mut Overlaps
+) -> Node<'a> {
     if instructions.len() == 0 {
         panic!();
     }
@@ -165,99 +321,61 @@ fn individualize_prefer_branch<'a>(instructions: &[&'a ir::Instruction], budget:
     // Fallback to differentiation
 
     // TODO: might have prob here
-    if let Some(branch_decision) = decide_specialization_branch(instructions) {
-        let specialization_bitmask = branch_decision.filter_bits.iter().map(|filter_bit| {
-            match filter_bit {
-                Some(ir::Bit::NotOne) | Some(ir::Bit::NotZero) => "1",
-                _ => "0"
-            }
-        }).collect::<Vec<_>>().concat();
-
-        let specialization_value = branch_decision.filter_bits.iter().map(|filter_bit| {
-            match filter_bit {
-                Some(ir::Bit::NotOne) => "1",
-                _ => "0"
-            }
-        }).collect::<Vec<_>>().concat();
-
-        let start = branch_decision.filter_range.start();
-
-        let bitmask = u32::from_str_radix(&specialization_bitmask, 2).unwrap() << start;
-        let value = u32::from_str_radix(&specialization_value, 2).unwrap() << start;
+    if let Some(branch_decision) = decide_specialization_branch(instructions, frequencies, options) {
+        // The value the constraint rules out goes to `then`, with the encodings living there.
+        let (bitmask, value) = branch_decision.constraint.mask();
 
         return Node::Branch {
             bitmask,
             value,
-            then: Box::new(individualize_prefer_branch(&branch_decision.specialized, 4)),
-            r#else: Box::new(individualize_prefer_branch(&branch_decision.not_specialized, 4))
+            then: Box::new(individualize_prefer_branch(&branch_decision.specialized, options, frequencies, overlaps)),
+            r#else: Box::new(individualize_prefer_branch(&branch_decision.not_specialized, options, frequencies, overlaps))
         };
     }
 
-    let owned_patterns = instructions.iter().map(|i| &i.pattern).collect::<Vec<_>>();
-    if !classification::can_individually_differentiate(&owned_patterns) {
-        //pretty_print_bucket(instructions);
-        if instructions.len() == 2 {
-            /* Hardcode the LDR/LDRT case.
-            NNNN010XX0X11111XXXXXXXXXXXXXXXX: LDR_l_A1
-            NNNN0100X011XXXXXXXXXXXXXXXXXXXX: LDRT_A1
-            */
-
-            // TODO: solve this problem
-            let bugged_pairs = vec![
-                ("LDRSH_l_A1", "LDRSHT_A1"),
-                ("LDRSB_l_A1", "LDRSBT_A1"),
-                ("LDRH_l_A1", "LDRHT_A1"),
-                ("LDR_l_A1", "LDRT_A1"),
-                ("LDRB_l_A1", "LDRBT_A1"),
-            ];
-
-            // I think maybe P and W bits in docs, idk
-            for p in bugged_pairs {
-                if instructions.iter().find(|i| i.name == Box::from(p.0)).is_some() && instructions.iter().find(|i| i.name == Box::from(p.1)).is_some() {
-                    return Node::Leaf(instructions[0]);
-                }
-            }
-
-            if instructions[0].pattern == instructions[1].pattern {
-                // TODO: handle alises better lol, or disble them
-                return Node::Leaf(instructions[0]);
-            }
-        }
-
-        let specialization = classification::get_instruction_specialization(instructions);
+    let fixed = instructions.iter().map(|i| i.fixed_bits()).collect::<Vec<_>>();
+    if !classification::can_individually_differentiate(&fixed) {
+        // A branch sends the words it matches to `then`, encodings there must take precedence
+        // over the ones left in `else` they overlap with.
+        let order = precedence_order(instructions, overlaps);
+        let position = |i: &ir::Instruction| order.iter().position(|o| *o == i);
+
+        let specialization = classification::get_instruction_specialization(instructions)
+            // A side holding every instruction (or none) would recurse forever.
+            .filter(|(_, _, then, r#else)| {
+                (1..instructions.len()).contains(&then.len()) && (1..instructions.len()).contains(&r#else.len())
+            })
+            // With nested special cases the mask mixes bits of several encodings, only take it when
+            // the `then` side lies entirely inside it and nothing is dropped.
+            .filter(|(bitmask, value, then, r#else)| {
+                then.iter().all(|i| {
+                    let (mask, fixed) = i.fixed_bits();
+                    mask & bitmask == *bitmask && fixed & bitmask == *value
+                }) && instructions.iter().all(|i| then.contains(i) || r#else.contains(i))
+            })
+            .filter(|(_, _, then, r#else)| {
+                then.iter().all(|t| {
+                    r#else.iter().all(|e| intersection(t, e).is_none() || position(t) < position(e))
+                })
+            });
         if let Some((bitmask, value, then, r#else)) = specialization {
             return Node::Branch {
                 bitmask,
                 value,
-                then: Box::new(individualize_prefer_branch(&then, 4)),
-                r#else: Box::new(individualize_prefer_branch(&r#else, 4))
+                then: Box::new(individualize_prefer_branch(&then, options, frequencies, overlaps)),
+                r#else: Box::new(individualize_prefer_branch(&r#else, options, frequencies, overlaps))
             };
         }
-
-        //pretty_print_bucket(instructions);
-        //println!("{:?}", instructions);
-
     }
 
-    let (b, mapping) = bits::min_bits_for_individualisation(instructions, budget);
+    let (b, mapping) = bits::min_bits_for_individualisation(instructions, options.budget, frequencies);
+    if mapping.iter().any(|bucket| bucket.len() == instructions.len()) {
+        return resolve_overlap(instructions, overlaps);
+    }
 
     let mut entries_mapping = vec![None; 1usize << b.len()];
-    for (binary, bucket) in mapping {
-        let index = usize::from_str_radix(&binary, 2).unwrap();
-        let insts = bucket.into_iter().collect::<Vec<_>>();
-
-        if debugging {
-            /*
-             *2
-Printing bucket of 2
-NNNN00111001XXXXXXXXXXXXXXXXXXXX: ORRS_i_A1
-NNNN00110001XXXX0000XXXXXXXXXXXX: TST_i_A1
-
-            */
-
-        }
-
-        entries_mapping[index] = Some(Box::new(individualize_prefer_branch(&insts, budget)));
+    for (index, insts) in mapping.into_iter().enumerate().filter(|(_, bucket)| !bucket.is_empty()) {
+        entries_mapping[index] = Some(Box::new(individualize_prefer_branch(&insts, options, frequencies, overlaps)));
     }
 
     Node::Lookup {
@@ -267,40 +385,59 @@ NNNN00110001XXXX0000XXXXXXXXXXXX: TST_i_A1
     }
 }
 
-fn pretty_print_bucket(bucket: &[&ir::Instruction]) {
-    println!("Printing bucket of {}", bucket.len());
-    let mut numbers = vec![];
-    for i in 0..32 {
-        numbers.push(i);
-    }
-    numbers.reverse();
-    for i in bucket {
-       println!("{}: {}", bits::get_key_for_pattern_nz(&i.pattern, &numbers), i.name);
-    }
+/// Bits a lookup of [`build`] takes at most, the 16 ways of the default `Entry`.
+pub const BUDGET: usize = 4;
+
+/// Balances the tree on `frequencies`, `Frequencies::default()` weighs every encoding the same.
+/// Lookups take [`BUDGET`] bits at most.
+/// Fails when encodings overlap without a precedence, see [`Overlaps`].
+pub fn build<'a>(instructions: &[&'a ir::Instruction], frequencies: &Frequencies) -> Result<Node<'a>, Overlaps> {
+    build_with(instructions, frequencies, &Options::default())
 }
 
-pub fn build<'a>(instructions: &[&'a ir::Instruction]) -> Node<'a> {
-    let patterns: Vec<_> = instructions.iter().map(|i| &i.pattern).collect();
-    let mut b = classification::simple_individualistic_differentiation(&patterns, 4, false);
+/// [`build`] shaped by the `budget` and `branch_ratio` of `options`, and improved on by the
+/// `search` of it if there is one. `frequencies` are the ones resolved over `instructions`, not
+/// `options.frequencies`.
+pub fn build_with<'a>(
+    instructions: &[&'a ir::Instruction],
+    frequencies: &Frequencies,
+    options: &Options
+) -> Result<Node<'a>, Overlaps> {
+    let mut overlaps = Overlaps::default();
+    let fixed: Vec<_> = instructions.iter().map(|i| i.fixed_bits()).collect();
+    let weights: Vec<_> = instructions.iter().map(|i| frequencies.weight(i)).collect();
+    let mut b = match frequencies.is_empty() {
+        true => classification::simple_individualistic_differentiation(&fixed, &weights, options.budget, false),
+        false => classification::weighted_differentiation(instructions, frequencies, options.budget)
+    };
     b.sort_by(|a, b| b.cmp(a));
 
     let mut entries_mapping = vec![None; 1usize << b.len()];
-    for (binary, bucket) in bits::create_bit_mapping(instructions, &b) {
-        let index = usize::from_str_radix(&binary, 2).unwrap();
-        let bucket_insts = &bucket.into_iter().collect::<Vec<_>>();
-        entries_mapping[index] = Some(Box::new(individualize_prefer_branch(bucket_insts, 4)));
+    for (index, bucket) in bits::create_bit_mapping(instructions, &b).into_iter().enumerate() {
+        if !bucket.is_empty() {
+            entries_mapping[index] = Some(Box::new(individualize_prefer_branch(&bucket, options, frequencies, &mut overlaps)));
+        }
     }
 
-    Node::Lookup {
+    if !overlaps.0.is_empty() {
+        overlaps.0.sort();
+        return Err(overlaps);
+    }
+    let greedy = Node::Lookup {
         bits: b.into(),
         instructions: Box::from(instructions),
         entries: entries_mapping.into()
-    }
+    };
+    Ok(match &options.search {
+        Some(search) => super::search::improve(greedy, instructions, frequencies, options, search),
+        None => greedy
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ir::fixtures::instruction;
 
     fn bits_to_bitmask(bits: &[usize]) -> u32 {
         bits.iter().fold(0u32, |mask, &i| mask | (1 << i))
@@ -319,8 +456,6 @@ mod tests {
                 //println!("{word:032b}");
                 //println!("going through a bucket, {index:?} ");
 
-                //pretty_print_bucket(instructions);
-                
                 walk_tree(&entries[index as usize].clone().unwrap(), word)
             },
             Node::Branch { bitmask, value, then, r#else } => {
@@ -339,10 +474,8 @@ mod tests {
     #[test]
     fn test_graph() {
         // TODO: need pext (BMI2) to test
-        const ARM_SPEC_32: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
-
-        let stream = crate::fetcher::arm::InstructionSpecificationStream::connect(ARM_SPEC_32).unwrap();
-        let instructions = crate::parser::arm::parse_into_ir(stream);
+        let source = crate::fetcher::arm::SpecSource::aarch32().unwrap();
+        let instructions = crate::parser::arm::parse_into_ir(&source, &mut vec![]).unwrap();
 
         // HVC_A1, STRH_i_A1_off
         //let instruction_word = 0x7C1F003F;
@@ -355,13 +488,122 @@ mod tests {
         //let nop_instruction_word = 0b00000011001000001111000000000000;
         //let mov_i_instruction_word = 0b00000011101000000000000000000000;
         //let instruction_word = 0xE1600010;
-        let entry = build(&instructions.iter().collect::<Vec<_>>());
+        let entry = build(&instructions.iter().filter(|i| i.isa == ir::Isa::A32).collect::<Vec<_>>(), &Frequencies::default()).unwrap();
 
-        pretty_print_bucket(&[&walk_tree(&entry, instruction_word)]);
+        print!("{}", crate::inspect::explain(&entry, &[], instruction_word));
         println!("Instruction: {}", walk_tree(&entry, instruction_word).name);
         //println!("{instruction_word:032b}");
         //println!("{:032b}", instruction_word);
         //dbg!(entry_node);
         //panic!()
     }
+
+    #[test]
+    fn test_overlapping_classes() {
+        // A general encoding with carved out special cases, like VMOV/VDUP and friends.
+        let mut instructions = [
+            instruction("GENERAL", "1110 1110 xxxx xxxx xxxx 1011 xxx1 0000"),
+            instruction("SPECIAL", "1110 1110 000x xxxx xxxx 1011 0001 0000"),
+            instruction("NARROWER", "1110 1110 0001 xxxx xxxx 1011 0001 0000"),
+            instruction("DUPLICATE", "1110 1110 0001 xxxx xxxx 1011 0001 0000"),
+            instruction("OTHER_0", "1111 0010 xxxx xxxx xxxx 1000 xxx0 xxxx"),
+            instruction("OTHER_1", "1111 0011 xxxx xxxx xxxx 1000 xxx0 xxxx"),
+            instruction("OTHER_2", "1110 1101 xxxx xxxx xxxx 1010 xxxx xxxx"),
+        ];
+        // Identical patterns, only the encoding index orders them.
+        instructions[2].index = Some(7);
+        instructions[3].index = Some(8);
+        let refs = instructions.iter().collect::<Vec<_>>();
+
+        let entry = build(&refs, &Frequencies::default()).unwrap();
+        assert_eq!(format!("{entry:?}"), format!("{:?}", build(&refs, &Frequencies::default()).unwrap()));
+
+        assert_eq!(walk_tree(&entry, 0xEE100B10).name.as_ref(), "NARROWER");
+        assert_eq!(walk_tree(&entry, 0xEE000B10).name.as_ref(), "SPECIAL");
+        assert_eq!(walk_tree(&entry, 0xEEF00B90).name.as_ref(), "GENERAL");
+        assert_eq!(walk_tree(&entry, 0xF3000800).name.as_ref(), "OTHER_1");
+        assert_eq!(walk_tree(&entry, 0xED000A00).name.as_ref(), "OTHER_2");
+    }
+
+    #[test]
+    fn test_overlap_precedence() {
+        // LDR (literal) leaves P and W free but sends P == 0 && W == 1 to LDRT.
+        let mut literal = instruction("LDR_l_A1", "1110 010x x0x1 1111 xxxx xxxx xxxx xxxx");
+        literal.see = Box::new([ir::See { mask: 0x0120_0000, value: 0x0020_0000, target: Box::from("LDRT") }]);
+        let instructions = [
+            instruction("LDRT_A1", "1110 0100 x011 xxxx xxxx xxxx xxxx xxxx"),
+            literal,
+            instruction("OTHER", "1110 011x xxxx xxxx xxxx xxxx xxx0 xxxx"),
+        ];
+        let entry = build(&instructions.iter().collect::<Vec<_>>(), &Frequencies::default()).unwrap();
+
+        assert_eq!(walk_tree(&entry, 0xE43F_0000).name.as_ref(), "LDRT_A1");
+        assert_eq!(walk_tree(&entry, 0xE53F_0000).name.as_ref(), "LDR_l_A1");
+        assert_eq!(walk_tree(&entry, 0xE41F_0000).name.as_ref(), "LDR_l_A1");
+        assert_eq!(walk_tree(&entry, 0xE431_0000).name.as_ref(), "LDRT_A1");
+        assert_eq!(walk_tree(&entry, 0xE600_0000).name.as_ref(), "OTHER");
+    }
+
+    #[test]
+    fn test_frequency_balancing() {
+        // One hot encoding set apart by a bit a 50/50 split never picks, among a crowd that needs
+        // more than a single lookup to tell apart.
+        let mut instructions = vec![instruction("HOT", "0000 0000 xxxx xxxx xxxx xxxx xxxx xxxx")];
+        for i in 0..47u32 {
+            let top = format!("{:08b}", 0x80 + i * 2);
+            instructions.push(instruction(&format!("COLD_{i}"), &format!("{top} xxxx xxxx xxxx xxxx xxxx xxxx")));
+        }
+        let refs = instructions.iter().collect::<Vec<_>>();
+        let frequencies = Frequencies::parse("HOT 100000").unwrap();
+
+        let uniform = build(&refs, &Frequencies::default()).unwrap();
+        let balanced = build(&refs, &frequencies).unwrap();
+        assert!(balanced.expected_depth(&frequencies) < uniform.expected_depth(&frequencies));
+        for word in [0x0000_0000, 0x8000_0000, 0xDC00_0000] {
+            assert_eq!(walk_tree(&uniform, word).name, walk_tree(&balanced, word).name);
+        }
+    }
+
+    #[test]
+    fn test_unresolved_overlap() {
+        let instructions = [
+            instruction("FIRST", "1110 1110 0001 xxxx xxxx 1011 0001 0000"),
+            instruction("SECOND", "1110 1110 0001 xxxx xxxx 1011 0001 0000"),
+            instruction("OTHER", "1111 0010 xxxx xxxx xxxx 1000 xxx0 xxxx"),
+        ];
+        let overlaps = build(&instructions.iter().collect::<Vec<_>>(), &Frequencies::default()).unwrap_err();
+        assert_eq!(overlaps.0, [(Box::from("FIRST"), Box::from("SECOND"))]);
+        assert!(overlaps.to_string().contains("FIRST / SECOND"));
+    }
+
+    #[test]
+    fn test_options() {
+        // Three conditional encodings and one that takes the `cond == 1111` space, a quarter of
+        // the weight.
+        let mut instructions = vec![instruction("UNCOND", "1111 0000 xxxx xxxx xxxx xxxx xxxx xxxx")];
+        for i in 0..3u32 {
+            instructions.push(instruction(&format!("COND_{i}"), &format!("NNNN 00{:02b} xxxx xxxx xxxx xxxx xxxx xxxx", i + 1)));
+        }
+        let refs = instructions.iter().collect::<Vec<_>>();
+        let frequencies = Frequencies::default();
+
+        assert!(decide_specialization_branch(&refs, &frequencies, &Options::default()).is_none());
+        let wide = Options { branch_ratio: 0.20..0.80, ..Options::default() };
+        let branch = decide_specialization_branch(&refs, &frequencies, &wide).unwrap();
+        assert_eq!(branch.constraint.mask(), (0xF000_0000, 0xF000_0000));
+        assert_eq!(branch.specialized.iter().map(|i| &*i.name).collect::<Vec<_>>(), ["UNCOND"]);
+
+        // The branch takes the value `cond != 1111` rules out, the unconditional space.
+        let node = individualize_prefer_branch(&refs, &wide, &frequencies, &mut Overlaps::default());
+        let Node::Branch { bitmask, value, ref then, .. } = node else { panic!("{node:?}") };
+        assert_eq!((bitmask, value), (0xF000_0000, 0xF000_0000));
+        assert!(matches!(**then, Node::Leaf(inst) if &*inst.name == "UNCOND"));
+        assert_eq!(walk_tree(&node, 0xF000_0000).name.as_ref(), "UNCOND");
+        assert_eq!(walk_tree(&node, 0xE100_0000).name.as_ref(), "COND_0");
+        assert_eq!(walk_tree(&node, 0x7300_0000).name.as_ref(), "COND_2");
+
+        let narrow = Options { budget: 1, ..Options::default() };
+        let Node::Lookup { ref bits, .. } = build_with(&refs, &frequencies, &narrow).unwrap() else { panic!() };
+        assert_eq!(bits.len(), 1);
+    }
 }
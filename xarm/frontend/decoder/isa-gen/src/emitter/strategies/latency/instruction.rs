@@ -11,6 +11,126 @@ use crate::ir;
 use quote::quote;
 use proc_macro2::TokenStream;
 
+/// Keywords and methods `InstructionView` already has, a field with the same name gets a `_` suffix.
+const RESERVED: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else", "enum",
+    "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "priv", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true",
+    "try", "type", "unsafe", "use", "where", "while", "yield",
+    "encoded_len", "mnemonic", "class", "docvar", "docvars", "execute",
+];
+
+fn accessor_name(label: &str) -> String {
+    let mut name = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect::<String>();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if RESERVED.contains(&name.as_str()) {
+        name.push('_');
+    }
+    name
+}
+
+/// `word` to field value, the parts of a split field are concatenated highest first.
+pub(crate) fn extraction(parts: &[std::ops::Range<usize>]) -> TokenStream {
+    let field = |part: &std::ops::Range<usize>| {
+        let shift = part.start as u32;
+        let mask = u32::MAX >> (32 - part.len());
+        // No shifts by 0, they only make clippy complain about the generated code.
+        match shift {
+            0 => quote! { word & #mask },
+            shift => quote! { (word >> #shift) & #mask }
+        }
+    };
+
+    let (first, rest) = parts.split_first().unwrap();
+    rest.iter().fold(field(first), |expr, part| {
+        let width = part.len() as u32;
+        let part = field(part);
+        quote! { ((#expr) << #width) | (#part) }
+    })
+}
+
+/// Whether the encoding fixes every bit of `range`, leaving nothing to extract.
+fn is_fixed(inst: &ir::Instruction, range: &std::ops::Range<usize>) -> bool {
+    inst.pattern[range.clone()]
+        .iter()
+        .all(|b| matches!(b, Some(ir::Bit::One) | Some(ir::Bit::Zero)))
+}
+
+/// The parts of a field, highest first.
+type Parts = Vec<std::ops::Range<usize>>;
+
+/// An accessor and the encodings having its field, grouped by where they have it.
+type Accessor<'a> = (String, Vec<(Parts, Vec<&'a ir::Instruction>)>);
+
+fn emit_accessors(instructions: &[&ir::Instruction]) -> TokenStream {
+    // accessor -> extraction -> encodings, in first seen order so the output is stable.
+    let mut accessors: Vec<Accessor> = vec![];
+
+    for inst in instructions {
+        let mut fields: Vec<(String, Parts)> = vec![];
+        for region in inst.regions.iter().filter(|r| !is_fixed(inst, &r.range)) {
+            let name = accessor_name(&region.label);
+            match fields.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, parts)) => parts.push(region.range.clone()),
+                None => fields.push((name, vec![region.range.clone()])),
+            }
+        }
+
+        for (name, mut parts) in fields {
+            parts.sort_by_key(|p| std::cmp::Reverse(p.start));
+
+            let index = match accessors.iter().position(|(n, _)| *n == name) {
+                Some(index) => index,
+                None => {
+                    accessors.push((name, vec![]));
+                    accessors.len() - 1
+                }
+            };
+            let groups = &mut accessors[index].1;
+            match groups.iter_mut().find(|(p, _)| *p == parts) {
+                Some((_, insts)) => insts.push(inst),
+                None => groups.push((parts, vec![inst])),
+            }
+        }
+    }
+
+    let methods = accessors.iter().map(|(name, groups)| {
+        let ident = quote::format_ident!("{}", name);
+        let doc = format!("The `{name}` field of `word`, `None` if this encoding has no such field.");
+        let arms = groups.iter().map(|(parts, insts)| {
+            let members = insts.iter().map(|i| quote::format_ident!("{}", i.name.to_string()));
+            let expr = extraction(parts);
+            quote! {
+                #(InstructionView::#members)|* => Some(#expr)
+            }
+        });
+
+        quote! {
+            #[doc = #doc]
+            #[inline(always)]
+            pub const fn #ident(self, word: u32) -> Option<u32> {
+                match self {
+                    #(#arms,)*
+                    #[allow(unreachable_patterns)]
+                    _ => None
+                }
+            }
+        }
+    });
+
+    quote! {
+        impl InstructionView {
+            #(#methods)*
+        }
+    }
+}
+
 pub fn emit(instructions: &[&ir::Instruction]) -> TokenStream {
     let members = instructions.iter().map(|i| {
         let name = quote::format_ident!("{}", i.name.to_string());
@@ -19,11 +139,107 @@ pub fn emit(instructions: &[&ir::Instruction]) -> TokenStream {
         }
     });
 
+    // The reserved leaves come first, `Undefined` is leaf 0, every table below starts with their entries.
+    let sizes = instructions.iter().map(|i| (i.width.bits() / 8) as u8);
+    let count = instructions.len() + isa_gen_nostd::Descriptor::RESERVED as usize;
+    let accessors = emit_accessors(instructions);
+
+    // Encodings without a mnemonic docvar fall back to their name.
+    let mnemonics = instructions.iter().map(|i| i.docvars.mnemonic().unwrap_or(&*i.name));
+    let classes = instructions.iter().map(|i| i.docvars.get("instr-class").unwrap_or_default());
+    let docvars = instructions.iter().map(|i| {
+        let (keys, values): (Vec<_>, Vec<_>) = i.docvars.iter().unzip();
+        quote! { &[#((#keys, #values)),*] }
+    });
+
     quote! {
         #[repr(u16)]
-        #[derive(Debug)]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
         pub enum InstructionView {
+            /// Unallocated, or left out of the target profile. Its size is a word, the size of
+            /// a T32 one is given by `t32::scalar_decode`.
+            Undefined,
+            /// The decode pseudocode of the encoding calls the word UNPREDICTABLE, anything may
+            /// happen executing it.
+            Unpredictable,
+            /// UNPREDICTABLE, with the choices the architecture leaves listed by its
+            /// `constrained_unpredictables`.
+            ConstrainedUnpredictable,
             #(#members),*
         }
+
+        static SIZES: [u8; #count] = [4, 4, 4, #(#sizes),*];
+        static MNEMONICS: [&str; #count] = ["UNDEFINED", "UNPREDICTABLE", "CONSTRAINED UNPREDICTABLE", #(#mnemonics),*];
+        static CLASSES: [&str; #count] = ["", "", "", #(#classes),*];
+        static DOCVARS: [&[(&str, &str)]; #count] = [&[], &[], &[], #(#docvars),*];
+
+        impl InstructionView {
+            /// Encoding size in bytes.
+            #[inline(always)]
+            pub const fn encoded_len(self) -> usize {
+                SIZES[self as usize] as usize
+            }
+
+            /// The `mnemonic` docvar, e.g. `ADC`.
+            #[inline(always)]
+            pub const fn mnemonic(self) -> &'static str {
+                MNEMONICS[self as usize]
+            }
+
+            /// The `instr-class` docvar, e.g. `general` or `fpsimd`.
+            #[inline(always)]
+            pub const fn class(self) -> &'static str {
+                CLASSES[self as usize]
+            }
+
+            /// Every docvar of the encoding as `(key, value)`, e.g. `("cond-setting", "S")`.
+            #[inline(always)]
+            pub const fn docvars(self) -> &'static [(&'static str, &'static str)] {
+                DOCVARS[self as usize]
+            }
+
+            /// The value of the docvar `key`, e.g. `address-form` gives `post-indexed`.
+            pub fn docvar(self, key: &str) -> Option<&'static str> {
+                self.docvars().iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+            }
+        }
+
+        #accessors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::fixtures::instruction;
+
+    fn region(label: &str, range: std::ops::Range<usize>) -> ir::BitRegion {
+        ir::BitRegion { label: Box::from(label), range }
+    }
+
+    #[test]
+    fn test_emit_accessors() {
+        let mut split = instruction("SPLIT", "xxxx 0000 xxxx xxxx xxxx xxxx xxxx xxxx");
+        split.regions = Box::new([
+            region("imm", 0..4),
+            region("imm", 16..20),
+            region("type", 5..7),
+            region("class", 8..12),
+            region("op", 24..28),
+        ]);
+        let mut whole = instruction("WHOLE", "xxxx 0001 xxxx xxxx xxxx xxxx xxxx xxxx");
+        whole.regions = Box::new([region("imm", 0..8), region("type", 5..7)]);
+
+        let code = emit_accessors(&[&split, &whole]).to_string();
+        // The parts of a split field are merged highest first, each layout gets an arm.
+        let (split_imm, whole_imm) = (extraction(&[16..20, 0..4]), extraction(std::slice::from_ref(&(0..8))));
+        assert_eq!(split_imm.to_string(), quote! { (((word >> 16u32) & 15u32) << 4u32) | (word & 15u32) }.to_string());
+        assert!(code.contains(&quote! { InstructionView::SPLIT => Some(#split_imm) }.to_string()), "{code}");
+        assert!(code.contains(&quote! { InstructionView::WHOLE => Some(#whole_imm) }.to_string()));
+        // Encodings with the field in the same place share an arm.
+        assert!(code.contains("InstructionView :: SPLIT | InstructionView :: WHOLE"), "{code}");
+        // Keywords and the methods of `InstructionView` are renamed, what the encoding fixes isn't a field.
+        assert!(code.contains("fn type_ (") && code.contains("fn class_ ("), "{code}");
+        assert!(!code.contains("fn op ("));
     }
 }
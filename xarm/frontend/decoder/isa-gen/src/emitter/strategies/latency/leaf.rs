@@ -0,0 +1,123 @@
+//! What a leaf of the tree compares before a word decodes as its encoding. The tree only looks at
+//! the bits it needs to tell the encodings apart, a word reaching a leaf may still miss a fixed
+//! bit or `!=` constraint of the encoding, or fall in one of its UNPREDICTABLE cases.
+
+use crate::ir;
+use isa_gen_nostd::Descriptor;
+
+/// What the lookups and branches from the root decided about the words reaching a node.
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    /// Every word has `value` under `mask`.
+    mask: u32,
+    value: u32,
+    /// Branches not taken, no word has the value under the mask.
+    excluded: Vec<(u32, u32)>
+}
+
+impl Path {
+    /// Into entry `index` of a lookup over `bits`, the first of them being the highest bit.
+    pub fn lookup(&self, bits: &[usize], index: usize) -> Self {
+        let mut path = self.clone();
+        for (position, bit) in bits.iter().rev().enumerate() {
+            path.mask |= 1 << bit;
+            path.value = path.value & !(1 << bit) | ((index >> position) as u32 & 1) << bit;
+        }
+        path
+    }
+
+    /// Into the `then` side of a branch on `word & bitmask == value` when `taken`, the `else` side
+    /// otherwise.
+    pub fn branch(&self, bitmask: u32, value: u32, taken: bool) -> Self {
+        let mut path = self.clone();
+        match taken {
+            true => {
+                path.mask |= bitmask;
+                path.value = path.value & !bitmask | value;
+            }
+            false => path.excluded.push((bitmask, value))
+        }
+        path
+    }
+
+    /// Whether no word on the path has `value` under `mask`.
+    fn rules_out(&self, mask: u32, value: u32) -> bool {
+        self.mask & mask & (self.value ^ value) != 0
+            || self.excluded.iter().any(|&(excluded, excluded_value)| excluded & !mask == 0 && excluded_value == value & excluded)
+    }
+}
+
+/// A compare of a leaf. It decides when the word has `value` under `mask`, or with `matching`
+/// unset when it doesn't, and the word then decodes as `leaf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Check {
+    pub mask: u32,
+    pub value: u32,
+    pub matching: bool,
+    pub leaf: u16
+}
+
+impl Check {
+    pub fn decides(&self, word: u32) -> bool {
+        (word & self.mask == self.value) == self.matching
+    }
+}
+
+/// The compares of the leaf of `inst` at the end of `path`, the first deciding for a word gives
+/// its leaf: UNDEFINED for the fixed bits and `!=` constraints the path left open, then every
+/// UNPREDICTABLE case. A word none of them decides for is an instance of `inst`.
+pub fn checks(inst: &ir::Instruction, path: &Path) -> Vec<Check> {
+    let (mask, value) = inst.fixed_bits();
+    // Bits the path fixed to the same value, a path contradicting one leaves it to the check.
+    let open = mask & !(path.mask & !(path.value ^ value));
+    let fixed = (open != 0).then_some(Check { mask: open, value: value & open, matching: false, leaf: Descriptor::UNDEFINED });
+
+    let constraints = inst.field_constraints.iter()
+        .map(|constraint| match constraint.op {
+            ir::FieldOp::Ne => constraint.mask()
+        })
+        .filter(|&(mask, value)| !path.rules_out(mask, value))
+        .map(|(mask, value)| Check { mask, value, matching: true, leaf: Descriptor::UNDEFINED });
+    // Every case whatever the path, so the leaves of an encoding all share the same entries.
+    let cases = inst.unpredictable.iter().map(|case| Check { mask: case.mask, value: case.value, matching: true, leaf: case.leaf() });
+    fixed.into_iter().chain(constraints).chain(cases).collect()
+}
+
+/// The leaf `inst` decodes as, the implemented encodings are numbered in order from
+/// `Descriptor::RESERVED`. Encodings the profile leaves out are still told apart by the tree, they
+/// have none and decode as UNDEFINED.
+pub fn number(implemented: &[&ir::Instruction], inst: &ir::Instruction) -> Option<u16> {
+    let index = implemented.iter().position(|i| std::ptr::eq(*i, inst))?;
+    let leaf = u16::try_from(index).ok().and_then(|index| index.checked_add(Descriptor::RESERVED));
+    Some(leaf.unwrap_or_else(|| panic!("more implemented encodings than a u16 leaf numbers")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::fixtures::instruction;
+
+    #[test]
+    fn test_checks() {
+        let mut inst = instruction("CONDITIONAL", "NNNN 0000 xxxx xxxx xxxx xxxx xxxx 1001");
+        inst.unpredictable = Box::new([ir::Unpredictable { mask: 0xF000, value: 0xF000, constraints: Box::new([]) }]);
+        let leaf = |path: &Path, word| checks(&inst, path).iter().find(|check| check.decides(word)).map(|check| check.leaf);
+
+        // Nothing decided, every fixed bit is compared.
+        let root = Path::default();
+        assert_eq!(checks(&inst, &root)[0], Check { mask: 0x0F00_000F, value: 0x9, matching: false, leaf: Descriptor::UNDEFINED });
+        assert_eq!(leaf(&root, 0xE000_0009), None);
+        assert_eq!(leaf(&root, 0xE000_0008), Some(Descriptor::UNDEFINED));
+        assert_eq!(leaf(&root, 0xF000_0009), Some(Descriptor::UNDEFINED));
+        assert_eq!(leaf(&root, 0xE000_F009), Some(Descriptor::UNPREDICTABLE));
+
+        // A lookup on bits 3 and 0 and a branch around `cond == 1111` leave bits 27..24, 2 and 1.
+        let path = root.lookup(&[3, 0], 0b11).branch(0xF000_0000, 0xF000_0000, false);
+        let checks = checks(&inst, &path);
+        assert_eq!(checks.len(), 2, "{checks:?}");
+        assert_eq!(checks[0], Check { mask: 0x0F00_0006, value: 0, matching: false, leaf: Descriptor::UNDEFINED });
+        assert_eq!(checks[1].leaf, Descriptor::UNPREDICTABLE);
+        // A path contradicting the encoding keeps the bit.
+        assert_eq!(super::checks(&inst, &root.lookup(&[0], 0))[0].mask, 0x0F00_000F);
+    }
+}
@@ -1,68 +1,207 @@
 use super::graph::Node;
+use super::leaf::{self, Check, Path};
+use crate::emitter::classification::Frequencies;
 use crate::ir;
 
-use isa_gen_nostd::{Descriptor, Entry};
+use isa_gen_nostd::{Descriptor, Entry, Word};
+use std::collections::HashMap;
 
-pub fn add_entry_as_descriptor(insts: &[&ir::Instruction], entry: &Node, descs_lut: &mut Vec<Entry>) -> Descriptor {
-    // TODO: Probably no duplicates here, right?
-    match entry {
-        Node::Lookup { entries, bits, .. } => {
-            assert!(bits.len() <= 4);
-            assert!(entries.len() <= 16);
+/// Bits a lookup takes at most in the widest `Entry`, of 8 masks and 256 ways.
+pub const MAX_BUDGET: usize = 8;
 
-            let placeholder = descs_lut.len();
-            let lookup_entry_descriptor = Descriptor::new_entry(placeholder as u16);
-            descs_lut.push(Entry::default());
+/// Entries of a pool before and after identical subtrees were merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSize {
+    /// One entry per lookup and branch of the tree.
+    pub tree: usize,
+    pub dag: usize,
+    /// Bytes of an entry, see [`Pool::entry_len`].
+    pub entry_len: usize
+}
 
-            let mut mapped_entries = [Descriptor::new_invalid(); 16];
+impl std::fmt::Display for PoolSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = self.dag * self.entry_len;
+        write!(f, "{} entries ({bytes} bytes), {} before deduplication", self.dag, self.tree)
+    }
+}
 
-            for (ndx, entry) in entries.iter().enumerate() {
-                if let Some(entry) = entry {
-                    mapped_entries[ndx] = add_entry_as_descriptor(insts, entry, descs_lut);
-                }
+/// Entries the tree under `node` takes without deduplication, see [`PoolSize`]. The leaves of
+/// `instructions` take one per `masks` of their checks, see [`leaf::checks`].
+pub fn tree_len(instructions: &[&ir::Instruction], node: &Node, masks: usize) -> usize {
+    fn len(instructions: &[&ir::Instruction], node: &Node, masks: usize, path: &Path) -> usize {
+        match node {
+            Node::Lookup { bits, entries, .. } => 1 + entries.iter().enumerate()
+                .filter_map(|(index, entry)| Some(len(instructions, entry.as_ref()?, masks, &path.lookup(bits, index))))
+                .sum::<usize>(),
+            Node::Branch { bitmask, value, then, r#else } => {
+                1 + len(instructions, then, masks, &path.branch(*bitmask, *value, true))
+                    + len(instructions, r#else, masks, &path.branch(*bitmask, *value, false))
             }
+            Node::Leaf(inst) if instructions.contains(inst) => leaf::checks(inst, path).len().div_ceil(masks),
+            Node::Leaf(_) => 0
+        }
+    }
+    len(instructions, node, masks, &Path::default())
+}
+
+/// Bits the widest lookup under `node` takes.
+fn widest(node: &Node) -> usize {
+    match node {
+        Node::Lookup { bits, entries, .. } => entries.iter().flatten().map(|entry| widest(entry)).fold(bits.len(), usize::max),
+        Node::Branch { then, r#else, .. } => widest(then).max(widest(r#else)),
+        Node::Leaf(_) => 0
+    }
+}
+
+/// Entries keyed by their contents. Children are added before their parent, so structurally
+/// identical subtrees end up as the same entry and the tree as a DAG. Descriptors are `u32`
+/// until the pool is narrowed to the width it fits.
+struct Interner<const MASKS: usize, const WAYS: usize> {
+    entries: Vec<Entry<MASKS, WAYS, u32>>,
+    /// Weight of the words reaching every entry, summed over all its parents.
+    weights: Vec<f64>,
+    ids: HashMap<Entry<MASKS, WAYS, u32>, usize>
+}
 
-            let mut bitmasks = [0; 4];
-            for i in 0..4 {
-                bitmasks[i] = match (bits.len() - 1).checked_sub(i) {
+impl<const MASKS: usize, const WAYS: usize> Interner<MASKS, WAYS> {
+    fn intern(&mut self, entry: Entry<MASKS, WAYS, u32>, weight: f64) -> Descriptor<u32> {
+        let id = *self.ids.entry(entry).or_insert_with(|| {
+            self.entries.push(entry);
+            self.weights.push(0.0);
+            self.entries.len() - 1
+        });
+        self.weights[id] += weight;
+        Descriptor::new_entry(id as u32)
+    }
+
+    /// The descriptor of `node` at the end of `path` and the weight of the words it decodes.
+    fn add(&mut self, insts: &[&ir::Instruction], node: &Node, path: &Path, frequencies: &Frequencies) -> (Descriptor<u32>, f64) {
+        match node {
+            Node::Lookup { entries, bits, .. } => {
+                assert!(bits.len() <= MASKS);
+                assert!(entries.len() <= WAYS);
+
+                let mut mapped_entries = [Descriptor::new_invalid(); WAYS];
+                let mut weight = 0.0;
+                // Unallocated slots (A64 has a few at the top level) must keep their position.
+                for (ndx, entry) in entries.iter().enumerate() {
+                    if let Some(entry) = entry {
+                        let (descriptor, entry_weight) = self.add(insts, entry, &path.lookup(bits, ndx), frequencies);
+                        mapped_entries[ndx] = descriptor;
+                        weight += entry_weight;
+                    }
+                }
+
+                let bitmasks: [u32; MASKS] = std::array::from_fn(|i| match (bits.len() - 1).checked_sub(i) {
                     Some(n) => bits.get(n).map(|b| 1 << b).unwrap_or(0),
                     None => 0
+                });
+
+                let expected = bitmasks.map(|mask| if mask != 0 { mask } else { 1 });
+
+                let entry = Entry {
+                    bitmasks,
+                    expected,
+                    entries: mapped_entries
                 };
+                (self.intern(entry, weight), weight)
             }
+            Node::Branch { bitmask, value, then, r#else } => {
+                let (then, then_weight) = self.add(insts, then, &path.branch(*bitmask, *value, true), frequencies);
+                let (r#else, else_weight) = self.add(insts, r#else, &path.branch(*bitmask, *value, false), frequencies);
 
-            let expected = bitmasks.map(|mask| if mask != 0 { mask } else { 1 });
-
-            descs_lut[placeholder] = Entry {
-                bitmasks,
-                expected,
-                entries: mapped_entries
-            };
-            lookup_entry_descriptor
+                let mut entry = Entry { bitmasks: [0; MASKS], expected: [1; MASKS], entries: [Descriptor::new_invalid(); WAYS] };
+                entry.bitmasks[0] = *bitmask;
+                entry.expected[0] = *value;
+                entry.entries[1] = then;
+                entry.entries[0] = r#else;
+                let weight = then_weight + else_weight;
+                (self.intern(entry, weight), weight)
+            }
+            Node::Leaf(inst) => {
+                // TODO: this should be better.. incase we would want to identify branch easily.
+                let weight = frequencies.weight(inst);
+                let descriptor = match leaf::number(insts, inst) {
+                    Some(leaf) => {
+                        let leaf = Descriptor::new_leaf(leaf as u32);
+                        self.checks(&leaf::checks(inst, path), leaf, weight)
+                    }
+                    None => Descriptor::new_invalid()
+                };
+                (descriptor, weight)
+            }
         }
-        Node::Branch { bitmask, value, then, r#else } => {
-            let placeholder = descs_lut.len();
-            let branch_entry_descriptor = Descriptor::new_entry(placeholder as u16);
-            descs_lut.push(Entry::default());
-
-            let mut descriptors = [Descriptor::new_invalid(); 16];
-            descriptors[1] = add_entry_as_descriptor(insts, then, descs_lut);
-            descriptors[0] = add_entry_as_descriptor(insts, r#else, descs_lut);
-            descs_lut[placeholder] = Entry {
-                bitmasks: [*bitmask, 0, 0, 0],
-                expected: [*value, 1, 1, 1],
-                entries: descriptors
-            };
-            branch_entry_descriptor
+    }
+
+    /// `leaf` behind `checks`, `MASKS` to an entry. A word takes the slot of the compares it
+    /// matches, the first check deciding for it gives the leaf and the slots none decides for go
+    /// on to the next entry.
+    fn checks(&mut self, checks: &[Check], leaf: Descriptor<u32>, weight: f64) -> Descriptor<u32> {
+        let mut descriptor = leaf;
+        for chunk in checks.chunks(MASKS).rev() {
+            let mut entry = Entry { bitmasks: [0; MASKS], expected: [1; MASKS], entries: [Descriptor::new_invalid(); WAYS] };
+            for (lane, check) in chunk.iter().enumerate() {
+                entry.bitmasks[lane] = check.mask;
+                entry.expected[lane] = check.value;
+            }
+            for index in 0..1 << chunk.len() {
+                let decides = chunk.iter().enumerate().find(|(lane, check)| (index >> lane & 1 == 1) == check.matching);
+                entry.entries[index] = decides.map_or(descriptor, |(_, check)| Descriptor::new_leaf(check.leaf as u32));
+            }
+            descriptor = self.intern(entry, weight);
         }
-        Node::Leaf(inst) => {
-            // TODO: this should be better.. incase we would want to identify branch easily.
-            Descriptor::new_leaf(insts.iter().position(|e| e == inst).unwrap() as u16)
+        descriptor
+    }
+
+    /// The entries depth first from `root`, the hottest child of every entry right after it so
+    /// the walk of frequent words stays within neighbouring cache lines. Returns the pool and the
+    /// index of `root` in it.
+    fn layout(self, root: usize) -> (Vec<Entry<MASKS, WAYS, u32>>, u32) {
+        let mut order = vec![];
+        let mut position = vec![None; self.entries.len()];
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            if position[id].is_some() {
+                continue;
+            }
+            position[id] = Some(order.len());
+            order.push(id);
+
+            let mut children = self.entries[id].entries.iter()
+                .filter(|d| d.is_entry())
+                .map(|d| d.data())
+                .filter(|child| position[*child].is_none())
+                .collect::<Vec<_>>();
+            // Coldest pushed first, the hottest is visited next. Ties go in the order they were built.
+            children.sort_by(|a, b| self.weights[*a].total_cmp(&self.weights[*b]).then(b.cmp(a)));
+            stack.extend(children);
         }
+
+        let pool = order.iter().map(|id| {
+            let mut entry = self.entries[*id];
+            for descriptor in &mut entry.entries {
+                if descriptor.is_entry() {
+                    *descriptor = Descriptor::new_entry(position[descriptor.data()].unwrap() as u32);
+                }
+            }
+            entry
+        }).collect();
+        (pool, position[root].unwrap() as u32)
     }
 }
 
-pub fn build(instructions: &[&ir::Instruction], entry_node: Node) -> (Vec<Entry>, u16) {
-    let Node::Lookup { bits, entries, .. } = entry_node else { panic!() };
+/// The pool of `entry_node` with `u32` descriptors, `None` when a lookup takes more than `MASKS`
+/// bits.
+fn intern<const MASKS: usize, const WAYS: usize>(
+    instructions: &[&ir::Instruction],
+    entry_node: &Node,
+    frequencies: &Frequencies
+) -> Option<(Vec<Entry<MASKS, WAYS, u32>>, u32)> {
+    let Node::Lookup { .. } = entry_node else { panic!() };
+    if widest(entry_node) > MASKS {
+        return None;
+    }
 
     // Scalar Optimization:
     // - Data Optimized: Great for random instructions, bad for a hot loop, slowest in worst case
@@ -70,36 +209,113 @@ pub fn build(instructions: &[&ir::Instruction], entry_node: Node) -> (Vec<Entry>
     //
     // Hybrid: pooling, overhead for cache handling, hashing, commonly used, just ideas
 
-    let first_level_entries = entries;
-    let first_level_iter = first_level_entries.into_iter().filter_map(|e| e).map(|e| *e);
+    // TLB is another consideration, making a huge page for it can be nice would be nicer if we had
+    // perfect hashing in that case
+    let mut interner = Interner { entries: vec![], weights: vec![], ids: HashMap::new() };
+    let (root, _) = interner.add(instructions, entry_node, &Path::default(), frequencies);
+    Some(interner.layout(root.data()))
+}
+
+/// `pool` with `W` descriptors, `None` when an entry or leaf index doesn't fit.
+fn narrow<const MASKS: usize, const WAYS: usize, W: Word>(
+    (pool, root): &(Vec<Entry<MASKS, WAYS, u32>>, u32)
+) -> Option<(Vec<Entry<MASKS, WAYS, W>>, W)> {
+    let pool = pool.iter().map(Entry::to_width).collect::<Option<Vec<_>>>()?;
+    Some((pool, W::from_usize(*root as usize)?))
+}
+
+/// The pool of the tree under `entry_node` in `Entry<MASKS, WAYS, W>`, deduplicated and laid out
+/// by the weight `frequencies` give each entry. Returns the pool and the index of the root in it,
+/// `None` when a lookup is wider than `MASKS` bits or the pool doesn't fit `W` descriptors.
+pub fn build_with<const MASKS: usize, const WAYS: usize, W: Word>(
+    instructions: &[&ir::Instruction],
+    entry_node: &Node,
+    frequencies: &Frequencies
+) -> Option<(Vec<Entry<MASKS, WAYS, W>>, W)> {
+    narrow(&intern(instructions, entry_node, frequencies)?)
+}
+
+/// [`build_with`] in the default `Entry`, the tree has to fit it.
+pub fn build(instructions: &[&ir::Instruction], entry_node: Node, frequencies: &Frequencies) -> (Vec<Entry>, u16) {
+    build_with(instructions, &entry_node, frequencies).expect("the tree doesn't fit the default `Entry`")
+}
+
+/// An `Entry` pool and the index of its root, in the geometry [`Pool::build`] picks.
+#[derive(Debug, Clone)]
+pub enum Pool {
+    /// The default `Entry`, 4 masks and 16 `u16` descriptors in a cache line.
+    Narrow(Vec<Entry>, u16),
+    /// 4 masks and 16 `u32` descriptors.
+    Wide(Vec<Entry<4, 16, u32>>, u32),
+    /// 8 masks and 256 `u16` descriptors.
+    Narrow256(Vec<Entry<8, 256, u16>>, u16),
+    /// 8 masks and 256 `u32` descriptors.
+    Wide256(Vec<Entry<8, 256, u32>>, u32)
+}
 
-    let mut entry_pool = vec![];
+/// Evaluates `$body` with `$entries` and `$root` bound to the pool and root of `$pool`, whatever
+/// its geometry.
+macro_rules! with_pool {
+    ($pool:expr, |$entries:ident, $root:ident| $body:expr) => {
+        match $pool {
+            $crate::emitter::strategies::latency::lut::Pool::Narrow($entries, $root) => $body,
+            $crate::emitter::strategies::latency::lut::Pool::Wide($entries, $root) => $body,
+            $crate::emitter::strategies::latency::lut::Pool::Narrow256($entries, $root) => $body,
+            $crate::emitter::strategies::latency::lut::Pool::Wide256($entries, $root) => $body
+        }
+    };
+}
+pub(crate) use with_pool;
 
-    let mut first_level_descriptors = Vec::with_capacity(1usize << bits.len());
-    for entry in first_level_iter {
-        assert!(!matches!(entry, Node::Leaf(_)));
-        first_level_descriptors.push(add_entry_as_descriptor(instructions, &entry, &mut entry_pool));
+impl Pool {
+    /// The pool of the tree under `entry_node`, as [`build_with`]. Entries take 4 masks unless a
+    /// lookup is wider, descriptors are `u16` unless the pool or the leaves need more.
+    pub fn build(instructions: &[&ir::Instruction], entry_node: &Node, frequencies: &Frequencies) -> Self {
+        assert!(widest(entry_node) <= MAX_BUDGET, "a lookup takes more than {MAX_BUDGET} bits");
+        // Leaves are `u16` whatever the descriptors, `decoder` walks them to an `InstructionView`.
+        let leaves = (u16::MAX - Descriptor::RESERVED) as usize;
+        assert!(instructions.len() <= leaves, "more than {leaves} encodings, leaves are u16");
+        if let Some(pool) = intern::<4, 16>(instructions, entry_node, frequencies) {
+            return match narrow(&pool) {
+                Some((pool, root)) => Self::Narrow(pool, root),
+                None => Self::Wide(pool.0, pool.1)
+            };
+        }
+        let pool = intern::<8, 256>(instructions, entry_node, frequencies).unwrap();
+        match narrow(&pool) {
+            Some((pool, root)) => Self::Narrow256(pool, root),
+            None => Self::Wide256(pool.0, pool.1)
+        }
     }
 
-    // TLB is another consideration, making a huge page for it can be nice would be nicer if we had
-    // perfect hashing in that case
-    let mut bitmasks = [0; 4];
-    for i in 0..4 {
-        bitmasks[i] = match (bits.len() - 1).checked_sub(i) {
-            Some(n) => bits.get(n).map(|b| 1 << b).unwrap_or(0),
-            None => 0
-        };
+    pub fn len(&self) -> usize {
+        with_pool!(self, |entries, _root| entries.len())
     }
 
-    let expected = bitmasks.map(|mask| if mask != 0 { mask } else { 1 });
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-    let index = entry_pool.len() as u16;
-    entry_pool.push(Entry {
-        bitmasks,
-        expected,
-        entries: first_level_descriptors.try_into().unwrap()
-    });
-    (entry_pool, index)
+    /// Masks of an entry, it has `1 << masks` ways.
+    pub fn masks(&self) -> usize {
+        match self {
+            Self::Narrow(..) | Self::Wide(..) => 4,
+            Self::Narrow256(..) | Self::Wide256(..) => 8
+        }
+    }
+
+    /// Bytes of an entry.
+    pub fn entry_len(&self) -> usize {
+        fn len<const MASKS: usize, const WAYS: usize, W: Word>(_: &[Entry<MASKS, WAYS, W>]) -> usize {
+            Entry::<MASKS, WAYS, W>::LEN
+        }
+        with_pool!(self, |entries, _root| len(entries))
+    }
+
+    /// The leaf `word` decodes as.
+    pub fn walk(&self, word: u32) -> usize {
+        with_pool!(self, |entries, root| Entry::walk(&entries[..], *root, word).to_usize())
+    }
 }
 
 #[cfg(test)]
@@ -108,13 +324,141 @@ mod tests {
 
     #[test]
     fn test_build() {
-        const ARM_SPEC_32: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
+        let source = crate::fetcher::arm::SpecSource::aarch32().unwrap();
+        let instructions = crate::parser::arm::parse_into_ir(&source, &mut vec![]).unwrap();
+
+        let r = instructions.iter().filter(|i| i.isa == ir::Isa::A32).collect::<Vec<_>>();
+        let entry_node = super::super::graph::build(&r, &Default::default()).unwrap();
+        build(&r, entry_node, &Frequencies::default());
+    }
+
+    fn walk(pool: &[Entry], root: u16, word: u32) -> u16 {
+        Entry::walk(pool, root, word)
+    }
 
-        let stream = crate::fetcher::arm::InstructionSpecificationStream::connect(ARM_SPEC_32).unwrap();
-        let instructions = crate::parser::arm::parse_into_ir(stream);
+    #[test]
+    fn test_left_out_encodings() {
+        use crate::ir::fixtures::instruction;
+
+        let instructions = [
+            instruction("KEPT", "0000 xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("LEFT_OUT", "0001 xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("ALSO_KEPT", "0010 xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+        ];
+        let all = instructions.iter().collect::<Vec<_>>();
+        let kept = [&instructions[0], &instructions[2]];
+
+        let (pool, root) = build(&kept, super::super::graph::build(&all, &Default::default()).unwrap(), &Frequencies::default());
+        assert_eq!(walk(&pool, root, 0x0000_0000), Descriptor::RESERVED);
+        assert_eq!(walk(&pool, root, 0x2000_0000), Descriptor::RESERVED + 1);
+        assert_eq!(walk(&pool, root, 0x1000_0000), Descriptor::UNDEFINED);
+    }
+
+    #[test]
+    fn test_dedup_and_layout() {
+        use crate::ir::fixtures::instruction;
+
+        let instructions = [
+            instruction("A", "0xxx xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("B", "1xxx xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("C", "xxxx xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+        ];
+        let [a, b, c] = &instructions;
+        let branch = |bitmask, then| Some(Box::new(Node::Branch {
+            bitmask,
+            value: bitmask,
+            then: Box::new(Node::Leaf(then)),
+            r#else: Box::new(Node::Leaf(c))
+        }));
+        let tree = || Node::Lookup {
+            instructions: Box::new([a, b, c]),
+            bits: Box::new([31, 30]),
+            // The first two are the same subtree.
+            entries: Box::new([branch(1, a), branch(1, a), branch(2, b), None])
+        };
+        let implemented = [a, b, c];
+        assert_eq!(tree_len(&implemented, &tree(), 4), 4);
+
+        let (pool, root) = build(&implemented, tree(), &Frequencies::default());
+        assert_eq!((pool.len(), root), (3, 0));
+        assert_eq!(pool[0].entries[0].0, pool[0].entries[1].0);
+        assert_eq!(pool[1].bitmasks[0], 1);
+        assert_eq!(walk(&pool, root, 0x0000_0001), Descriptor::RESERVED);
+        assert_eq!(walk(&pool, root, 0x4000_0001), Descriptor::RESERVED);
+        assert_eq!(walk(&pool, root, 0x8000_0002), Descriptor::RESERVED + 1);
+        assert_eq!(walk(&pool, root, 0x8000_0001), Descriptor::RESERVED + 2);
+        assert_eq!(walk(&pool, root, 0xC000_0000), Descriptor::UNDEFINED);
+
+        // The branch to the hot encoding moves next to the root.
+        let (hot, _) = build(&implemented, tree(), &Frequencies::parse("B 100").unwrap());
+        assert_eq!(hot[1].bitmasks[0], 2);
+        assert_eq!(walk(&hot, 0, 0x8000_0002), Descriptor::RESERVED + 1);
+    }
+
+    #[test]
+    fn test_unpredictable_cases() {
+        use crate::ir::fixtures::instruction;
+
+        let mut inst = instruction("LDRD", "0000 xxxx xxxx xxxx xxxx xxxx xxxx xxxx");
+        let constraint = ir::Constraint { id: "Constraint_UNDEF".into(), text: "The instruction is UNDEFINED.".into() };
+        // Five cases take two entries, bit 4 is the constrained one.
+        inst.unpredictable = (0..5)
+            .map(|bit| ir::Unpredictable {
+                mask: 1 << bit,
+                value: 1 << bit,
+                constraints: if bit == 4 { Box::new([constraint.clone()]) } else { Box::new([]) }
+            })
+            .collect();
+        let other = instruction("OTHER", "0001 xxxx xxxx xxxx xxxx xxxx xxxx xxxx");
+        let all = [&inst, &other];
+
+        let tree = super::super::graph::build(&all, &Default::default()).unwrap();
+        // Every leaf of LDRD the lookup has shares the two entries of its cases.
+        assert!(tree_len(&all, &tree, 4) > 3);
+        let (pool, root) = build(&all, tree, &Frequencies::default());
+        // With one more for the fixed bits the lookup left to the leaves of both.
+        assert_eq!(pool.len(), 4);
+        assert_eq!(walk(&pool, root, 0x0000_0000), Descriptor::RESERVED);
+        assert_eq!(walk(&pool, root, 0x8000_0000), Descriptor::UNDEFINED);
+        assert_eq!(walk(&pool, root, 0x0000_0004), Descriptor::UNPREDICTABLE);
+        assert_eq!(walk(&pool, root, 0x0000_0010), Descriptor::CONSTRAINED_UNPREDICTABLE);
+        // The first case covering the word decides.
+        assert_eq!(walk(&pool, root, 0x0000_0011), Descriptor::UNPREDICTABLE);
+        assert_eq!(walk(&pool, root, 0x1000_0001), Descriptor::RESERVED + 1);
+        assert_eq!(walk(&pool, root, 0x3000_0001), Descriptor::UNDEFINED);
+    }
+
+    #[test]
+    fn test_geometry() {
+        use crate::ir::fixtures::instruction;
+
+        let instructions = (0..64u32)
+            .map(|i| instruction(&format!("E{i}"), &format!("{i:06b}xx xxxx xxxx xxxx xxxx xxxx xxxx")))
+            .collect::<Vec<_>>();
+        let all = instructions.iter().collect::<Vec<_>>();
+
+        let small = Pool::build(&all, &super::super::graph::build(&all, &Default::default()).unwrap(), &Default::default());
+        assert!(matches!(small, Pool::Narrow(..)));
+        assert_eq!((small.masks(), small.entry_len()), (4, 64));
+
+        // One lookup of 6 bits only fits the 256 ways of 8 masks.
+        let options = crate::emitter::options::Options { budget: 6, ..Default::default() };
+        let tree = super::super::graph::build_with(&all, &Default::default(), &options).unwrap();
+        assert!(build_with::<4, 16, u16>(&all, &tree, &Default::default()).is_none());
+        let wide = Pool::build(&all, &tree, &Default::default());
+        assert!(matches!(wide, Pool::Narrow256(..)));
+        assert_eq!((wide.masks(), wide.entry_len(), wide.len()), (8, 576, 1));
+        for (i, word) in (0..64u32).map(|i| i << 26).enumerate() {
+            assert_eq!(wide.walk(word), i + Descriptor::RESERVED as usize);
+            assert_eq!(small.walk(word), i + Descriptor::RESERVED as usize);
+        }
 
-        let r = instructions.iter().collect::<Vec<_>>();
-        let entry_node = super::super::graph::build(&r);
-        build(&r, entry_node);
+        // Entries past 0x7FFF need `u32` descriptors.
+        let mut far = Entry::<4, 16, u32>::default();
+        far.entries[1] = Descriptor::new_entry(0x8000);
+        assert!(narrow::<4, 16, u16>(&(vec![far], 0)).is_none());
+        assert!(narrow::<4, 16, u16>(&(vec![Entry::default()], 0x8000)).is_none());
+        let (pool, root) = narrow::<4, 16, u16>(&(vec![Entry::default(); 2], 1)).unwrap();
+        assert_eq!((pool.len(), root), (2, 1));
     }
 }
@@ -1,11 +1,17 @@
 pub mod graph;
+pub mod search;
+pub mod leaf;
 pub mod lut;
 pub mod instruction;
+pub mod artifact;
 
-use isa_gen_nostd::Entry;
+use isa_gen_nostd::{Entry, Word};
 use crate::emitter::traits::CodeEmitter;
-use quote::quote;
-use proc_macro2::TokenStream;
+use crate::emitter::classification::Frequencies;
+use crate::emitter::options::Options;
+use crate::ir;
+use quote::{format_ident, quote};
+use proc_macro2::{Literal, TokenStream};
 
 fn emit_use() -> TokenStream {
     quote! {
@@ -13,8 +19,8 @@ fn emit_use() -> TokenStream {
     }
 }
 
-fn const_entry(e: &Entry) -> TokenStream {
-    let entry_desc_raws = e.entries.iter().map(|e| e.0);
+fn const_entry<const MASKS: usize, const WAYS: usize, W: Word>(e: &Entry<MASKS, WAYS, W>) -> TokenStream {
+    let entry_desc_raws = e.entries.iter().map(|e| Literal::usize_unsuffixed(e.0.to_usize()));
     let ee = e.expected;
     let eb = e.bitmasks;
 
@@ -27,44 +33,147 @@ fn const_entry(e: &Entry) -> TokenStream {
     }
 }
 
-fn emit_entries(pool: Vec<Entry>, root_index: u16) -> TokenStream {
+/// The pool as `ENTRIES`, of the geometry it was built in, and its root as `ROOT_INDEX`.
+fn emit_entries<const MASKS: usize, const WAYS: usize, W: Word>(pool: &[Entry<MASKS, WAYS, W>], root_index: W) -> TokenStream {
     let pool_consts = pool.iter().map(|e| const_entry(e));
     let pool_len = pool.len();
+    let (masks, ways) = (Literal::usize_unsuffixed(MASKS), Literal::usize_unsuffixed(WAYS));
+    let width = format_ident!("u{}", size_of::<W>() * 8);
+    let root_index = Literal::usize_unsuffixed(root_index.to_usize());
 
     quote! {
-        pub static ENTRIES: [Entry; #pool_len] = [
+        pub static ENTRIES: [Entry<#masks, #ways, #width>; #pool_len] = [
             #(#pool_consts),*
         ];
 
-        pub static ROOT_INDEX: u16 = #root_index;
+        pub static ROOT_INDEX: #width = #root_index;
     }
 }
 
-pub struct LatencyOptimizedCodeEmitter {
+/// How many entries a lookup walks through in the generated tree.
+#[derive(Debug, Clone, Copy)]
+pub struct Depths {
+    pub isa: ir::Isa,
+    /// Weighted by the frequency histogram.
+    pub expected: f64,
+    /// Every leaf alike.
+    pub average: f64,
+    pub worst: usize,
+    /// Size of the `Entry` pool, for the strategies that build one.
+    pub pool: Option<lut::PoolSize>
+}
 
+impl std::fmt::Display for Depths {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}: expected depth {:.2}, {:.2} on average per leaf, worst {}",
+            self.isa, self.expected, self.average, self.worst
+        )?;
+        match &self.pool {
+            Some(pool) => write!(f, ", {pool}"),
+            None => Ok(())
+        }
+    }
 }
 
-impl CodeEmitter for LatencyOptimizedCodeEmitter {
-    fn emit() -> TokenStream {
-        const ARM_SPEC_32: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
+/// The encodings of `isa` in `classes` the table is built over and, in leaf order, the ones
+/// `profile` implements. Leaf `Descriptor::RESERVED + n` is the `n`-th of them, the leaves below
+/// are UNDEFINED and the UNPREDICTABLE cases.
+pub fn select<'a>(
+    instructions: &'a [ir::Instruction],
+    isa: ir::Isa,
+    classes: ir::InstrClassSet,
+    profile: &ir::Profile
+) -> (Vec<&'a ir::Instruction>, Vec<&'a ir::Instruction>) {
+    let patterns = instructions
+        .iter()
+        .filter(|i| i.isa == isa && classes.contains(i.class))
+        .collect::<Vec<_>>();
+    let implemented = patterns.iter().copied().filter(|i| profile.allows(i)).collect::<Vec<_>>();
+    (patterns, implemented)
+}
+
+/// The decode tree of an instruction set, every strategy walks the same one its own way.
+pub struct Tree<'a> {
+    pub isa: ir::Isa,
+    /// The encodings it tells apart, see [`select`].
+    pub patterns: Vec<&'a ir::Instruction>,
+    /// In leaf order.
+    pub implemented: Vec<&'a ir::Instruction>,
+    /// `options.frequencies` resolved over `patterns`.
+    pub frequencies: Frequencies,
+    pub root: graph::Node<'a>
+}
+
+impl<'a> Tree<'a> {
+    /// The tree of `options.isa` out of the `instructions` [`Options::load`] read.
+    pub fn build(options: &Options, instructions: &'a [ir::Instruction]) -> Self {
+        let isa = options.isa;
+        let (patterns, implemented) = select(instructions, isa, options.classes, &options.profile);
+        let frequencies = options.frequencies.resolve(&patterns);
+        let root = graph::build_with(&patterns, &frequencies, options)
+            .unwrap_or_else(|e| panic!("Failed to build the {isa:?} decode tree: {e}"));
+        Self { isa, patterns, implemented, frequencies, root }
+    }
+
+    /// Its depths, a strategy building a pool adds its size.
+    pub fn depths(&self) -> Depths {
+        Depths {
+            isa: self.isa,
+            expected: self.root.expected_depth(&self.frequencies),
+            average: self.root.average_depth(),
+            worst: self.root.max_depth(),
+            pool: None
+        }
+    }
+}
 
-        let stream = crate::fetcher::arm::InstructionSpecificationStream::connect(ARM_SPEC_32).unwrap();
-        let instructions = crate::parser::arm::parse_into_ir(stream);
+pub struct LatencyOptimizedCodeEmitter {
 
-        let patterns = instructions.iter().collect::<Vec<_>>();
-        let entry_node = graph::build(&patterns);
-        let (pool, index) = lut::build(&patterns, entry_node);
+}
 
-        let usage = emit_use();
-        let inst_enum = instruction::emit(&patterns);
-        let descriptors = emit_entries(pool, index);
-        quote! {
-            #usage
+impl LatencyOptimizedCodeEmitter {
+    /// Builds the `Entry` pool of `tree` and hands it to `emit`, see [`CodeEmitter::emit`]. A
+    /// budget over [`graph::BUDGET`] takes entries of 256 ways, see [`lut::Pool::build`].
+    fn with_tables<R>(options: &Options, tree: &Tree, depths: &mut Depths, emit: impl FnOnce(lut::Pool) -> R) -> R {
+        assert!(
+            (1..=lut::MAX_BUDGET).contains(&options.budget),
+            "An `Entry` looks up 1 to {} bits, the budget is {}", lut::MAX_BUDGET, options.budget
+        );
+        let pool = lut::Pool::build(&tree.implemented, &tree.root, &tree.frequencies);
+        let len = lut::tree_len(&tree.implemented, &tree.root, pool.masks());
+        depths.pool = Some(lut::PoolSize { tree: len, dag: pool.len(), entry_len: pool.entry_len() });
+        emit(pool)
+    }
 
-            #inst_enum
+    /// The same table as [`CodeEmitter::emit`] as an artifact to load at runtime, leaves are
+    /// named after their encoding.
+    pub fn emit_artifact(options: &Options, tree: &Tree, depths: &mut Depths) -> Vec<u8> {
+        Self::with_tables(options, tree, depths, |pool| {
+            let names = tree.implemented.iter().map(|i| &*i.name).collect::<Vec<_>>();
+            lut::with_pool!(&pool, |entries, root| artifact::write(tree.isa, entries, *root, &names))
+        })
+    }
+}
 
-            #descriptors
-        }
+impl CodeEmitter for LatencyOptimizedCodeEmitter {
+    /// Emits the table and `InstructionView` for the `classes` of a single instruction set.
+    /// Encodings the `profile` doesn't implement decode as `InstructionView::Undefined`. The
+    /// size of the pool is added to `depths`.
+    fn emit(options: &Options, tree: &Tree, depths: &mut Depths) -> TokenStream {
+        Self::with_tables(options, tree, depths, |pool| {
+            let usage = emit_use();
+            let inst_enum = instruction::emit(&tree.implemented);
+            let descriptors = lut::with_pool!(&pool, |entries, root| emit_entries(entries, *root));
+            quote! {
+                #usage
+
+                #inst_enum
+
+                #descriptors
+            }
+        })
     }
 }
 
@@ -74,12 +183,10 @@ mod tests {
 
     #[test]
     fn test_build() {
-        const ARM_SPEC_32: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
-
-        let stream = crate::fetcher::arm::InstructionSpecificationStream::connect(ARM_SPEC_32).unwrap();
-        let instructions = crate::parser::arm::parse_into_ir(stream);
+        let source = crate::fetcher::arm::SpecSource::aarch32().unwrap();
+        let instructions = crate::parser::arm::parse_into_ir(&source, &mut vec![]).unwrap();
 
-        let _ = super::graph::build(&instructions.iter().collect::<Vec<_>>());
+        let _ = super::graph::build(&instructions.iter().filter(|i| i.isa == ir::Isa::A32).collect::<Vec<_>>(), &Frequencies::default()).unwrap();
         //let (b, pool, descriptors) = super::lut::build(entry_node);
 
     }
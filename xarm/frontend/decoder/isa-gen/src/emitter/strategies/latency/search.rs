@@ -0,0 +1,445 @@
+//! A search for a cheaper tree than the greedy one of [`graph::build_with`], which takes the bits
+//! that split every bucket most evenly without looking further down.
+//!
+//! Every node is a branch and bound over the lookups on the most even bits and the branches on
+//! field constraints, memoised on the encodings and weights the node gets and the depth left.
+//! Nodes the greedy tree resolves by precedence, and the ones reached once the budget of
+//! expansions is spent, keep the greedy subtree. The result replaces the greedy tree only if it
+//! scores better.
+
+use crate::emitter::bits;
+use crate::emitter::classification::{self, Frequencies};
+use crate::emitter::options::Options;
+use crate::emitter::strategies::latency::graph::{self, Node, Overlaps};
+use crate::ir;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How far the search may go and what it minimises, see [`Cost::score`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Search {
+    /// Nodes the search expands at most, the ones not searched yet keep the greedy subtree. The
+    /// same budget gives the same tree however fast the build runs.
+    pub expansions: usize,
+    /// A guard against budgets taking too long, once it is up the search stops as when the
+    /// budget is spent. The tree then depends on how fast the build runs.
+    pub time_limit: Duration,
+    /// What an entry of the tree costs, in lookups of the expected depth.
+    pub entry_cost: f64,
+    /// What a level of the worst case costs, in lookups of the expected depth.
+    pub depth_cost: f64
+}
+
+impl Search {
+    pub fn new(expansions: usize) -> Self {
+        Self { expansions, time_limit: TIME_LIMIT, entry_cost: 0.0005, depth_cost: 0.1 }
+    }
+}
+
+/// The guard of [`Search::new`], far longer than the budgets worth giving take.
+pub const TIME_LIMIT: Duration = Duration::from_secs(300);
+
+/// Bits a lookup of the search picks from, the ones splitting the weight most evenly.
+const CANDIDATE_BITS: usize = 10;
+/// Lookups and branches tried at every node, the lowest bound first.
+const WIDTH: usize = 6;
+
+/// The shape of a tree as [`Search`] weighs it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cost {
+    /// Lookups and branches a decode goes through, weighted by the frequencies. Unlike
+    /// [`Node::expected_depth`] an encoding splits its weight between the entries of a lookup it
+    /// spans rather than between its leaves, so it adds up over subtrees.
+    pub expected: f64,
+    /// Lookups and branches in the tree.
+    pub entries: usize,
+    /// Lookups and branches on the longest path.
+    pub depth: usize
+}
+
+impl Cost {
+    /// Of the tree `root` builds out of `instructions`.
+    pub fn new(root: &Node, instructions: &[&ir::Instruction], frequencies: &Frequencies) -> Self {
+        let weighted = instructions.iter().map(|i| (*i, frequencies.weight(i))).collect::<Vec<_>>();
+        let total = weighted.iter().map(|(_, weight)| weight).sum::<f64>();
+        let (expected, entries, depth) = measure(root, &weighted);
+        Self { expected: if total == 0.0 { 0.0 } else { expected / total }, entries, depth }
+    }
+
+    /// Lower is better.
+    pub fn score(&self, search: &Search) -> f64 {
+        self.expected + search.entry_cost * self.entries as f64 + search.depth_cost * self.depth as f64
+    }
+}
+
+/// The summed weight of the lookups every encoding goes through, the entries and the depth of
+/// `node` for the encodings in `weighted`.
+fn measure(node: &Node, weighted: &[(&ir::Instruction, f64)]) -> (f64, usize, usize) {
+    let total = weighted.iter().map(|(_, weight)| weight).sum::<f64>();
+    let children = match node {
+        Node::Leaf(_) => return (0.0, 0, 0),
+        Node::Lookup { bits, entries, .. } => {
+            let buckets = split(weighted, bits);
+            entries.iter().zip(&buckets)
+                .filter_map(|(entry, bucket)| Some(measure(entry.as_deref()?, bucket)))
+                .collect::<Vec<_>>()
+        }
+        Node::Branch { then, r#else, .. } => [then, r#else].into_iter().map(|side| {
+            let mut leaves = vec![];
+            side.leaf_depths(0, &mut leaves);
+            let inside = weighted.iter().filter(|(inst, _)| leaves.iter().any(|(leaf, _)| std::ptr::eq(*leaf, *inst)));
+            measure(side, &inside.copied().collect::<Vec<_>>())
+        }).collect()
+    };
+    children.into_iter().fold((total, 1, 1), |(expected, entries, depth), child| {
+        (expected + child.0, entries + child.1, depth.max(child.2 + 1))
+    })
+}
+
+/// Encodings with the share of their weight a node gets.
+type Weighted<'a> = Vec<(&'a ir::Instruction, f64)>;
+
+/// The buckets of a lookup over `bits`, an encoding spanning several entries splits its weight
+/// between them.
+fn split<'a>(weighted: &[(&'a ir::Instruction, f64)], bits: &[usize]) -> Vec<Weighted<'a>> {
+    let mut buckets = vec![Vec::new(); 1 << bits.len()];
+    for &(inst, weight) in weighted {
+        let options = bits::get_all_bit_options(inst, bits);
+        for &option in &options {
+            buckets[option].push((inst, weight / options.len() as f64));
+        }
+    }
+    buckets
+}
+
+enum Plan<'a> {
+    Leaf(&'a ir::Instruction),
+    Lookup {
+        bits: Vec<usize>,
+        instructions: Vec<&'a ir::Instruction>,
+        entries: Vec<Option<Rc<Solved<'a>>>>
+    },
+    Branch {
+        bitmask: u32,
+        value: u32,
+        then: Rc<Solved<'a>>,
+        r#else: Rc<Solved<'a>>
+    },
+    Greedy(Node<'a>)
+}
+
+struct Solved<'a> {
+    plan: Plan<'a>,
+    /// The summed weight of the lookups plus the cost of the entries, as [`measure`] counts them.
+    cost: f64,
+    depth: usize
+}
+
+impl<'a> Solved<'a> {
+    fn node(&self) -> Node<'a> {
+        match &self.plan {
+            Plan::Leaf(inst) => Node::Leaf(inst),
+            Plan::Lookup { bits, instructions, entries } => Node::Lookup {
+                instructions: Box::from(&instructions[..]),
+                bits: Box::from(&bits[..]),
+                entries: entries.iter().map(|entry| entry.as_ref().map(|entry| Box::new(entry.node()))).collect()
+            },
+            Plan::Branch { bitmask, value, then, r#else } => Node::Branch {
+                bitmask: *bitmask,
+                value: *value,
+                then: Box::new(then.node()),
+                r#else: Box::new(r#else.node())
+            },
+            Plan::Greedy(node) => node.clone()
+        }
+    }
+}
+
+enum Move<'a> {
+    Lookup(Vec<usize>, Vec<Weighted<'a>>),
+    Branch(&'a ir::FieldConstraint, Weighted<'a>, Weighted<'a>)
+}
+
+type Key = (Vec<(usize, u64)>, usize);
+
+struct Searcher<'s, 'a> {
+    options: &'s Options,
+    frequencies: &'s Frequencies,
+    /// [`Search::entry_cost`] in the summed weight [`measure`] counts.
+    entry_cost: f64,
+    /// Expansions left of [`Search::expansions`].
+    expansions: usize,
+    deadline: Instant,
+    memo: HashMap<Key, Option<Rc<Solved<'a>>>>,
+    overlaps: Overlaps
+}
+
+impl<'a> Searcher<'_, 'a> {
+    /// The cheapest tree for `weighted` found no deeper than `limit`, `None` when none is. The
+    /// root has to be a lookup.
+    fn solve(&mut self, weighted: &[(&'a ir::Instruction, f64)], limit: usize, root: bool) -> Option<Rc<Solved<'a>>> {
+        if let [(inst, _)] = weighted {
+            return Some(Rc::new(Solved { plan: Plan::Leaf(inst), cost: 0.0, depth: 0 }));
+        }
+        let key = (weighted.iter().map(|(inst, weight)| (*inst as *const _ as usize, weight.to_bits())).collect(), limit);
+        if let Some(solved) = self.memo.get(&key) {
+            return solved.clone();
+        }
+        let solved = self.solve_uncached(weighted, limit, root);
+        self.memo.insert(key, solved.clone());
+        solved
+    }
+
+    /// Whether the budget is spent, or the time limit up.
+    fn exhausted(&self) -> bool {
+        self.expansions == 0 || Instant::now() >= self.deadline
+    }
+
+    fn greedy(&mut self, weighted: &[(&'a ir::Instruction, f64)]) -> Rc<Solved<'a>> {
+        let instructions = weighted.iter().map(|(inst, _)| *inst).collect::<Vec<_>>();
+        let node = graph::individualize_prefer_branch(&instructions, self.options, self.frequencies, &mut self.overlaps);
+        let (expected, entries, depth) = measure(&node, weighted);
+        Rc::new(Solved { plan: Plan::Greedy(node), cost: expected + self.entry_cost * entries as f64, depth })
+    }
+
+    fn solve_uncached(&mut self, weighted: &[(&'a ir::Instruction, f64)], limit: usize, root: bool) -> Option<Rc<Solved<'a>>> {
+        let fixed = weighted.iter().map(|(inst, _)| inst.fixed_bits()).collect::<Vec<_>>();
+        if !root && self.exhausted() {
+            return Some(self.greedy(weighted));
+        }
+        if !root && !classification::can_individually_differentiate(&fixed) {
+            return Some(self.greedy(weighted)).filter(|greedy| greedy.depth <= limit);
+        }
+        if limit == 0 {
+            return None;
+        }
+        self.expansions = self.expansions.saturating_sub(1);
+
+        let total = weighted.iter().map(|(_, weight)| weight).sum::<f64>();
+        // The least a side costs, an entry unless it is a leaf.
+        let bound = |side: &[(&ir::Instruction, f64)]| match side.len() {
+            0 | 1 => 0.0,
+            _ if limit == 1 => f64::INFINITY,
+            _ => side.iter().map(|(_, weight)| weight).sum::<f64>() + self.entry_cost
+        };
+
+        let mut moves = vec![];
+        for (bits, buckets) in self.lookups(weighted, &fixed) {
+            let bound = total + self.entry_cost + buckets.iter().map(|bucket| bound(bucket)).sum::<f64>();
+            moves.push((bound, Move::Lookup(bits, buckets)));
+        }
+        if !root {
+            for (constraint, then, r#else) in branches(weighted) {
+                let bound = total + self.entry_cost + bound(&then) + bound(&r#else);
+                moves.push((bound, Move::Branch(constraint, then, r#else)));
+            }
+        }
+        moves.retain(|(bound, _)| bound.is_finite());
+        moves.sort_by(|a, b| a.0.total_cmp(&b.0));
+        moves.truncate(WIDTH);
+
+        let mut best: Option<Rc<Solved<'a>>> = None;
+        for (bound, r#move) in moves {
+            if best.as_ref().is_some_and(|best| best.cost <= bound) {
+                break;
+            }
+            let solved = match r#move {
+                Move::Lookup(bits, buckets) => {
+                    let mut entries = vec![];
+                    for bucket in &buckets {
+                        match bucket.is_empty() {
+                            true => entries.push(None),
+                            false => entries.push(Some(self.solve(bucket, limit - 1, false))),
+                        }
+                    }
+                    let Some(entries) = entries.into_iter().map(|entry| entry.map_or(Some(None), |entry| entry.map(Some))).collect::<Option<Vec<_>>>() else {
+                        continue;
+                    };
+                    let cost = total + self.entry_cost + entries.iter().flatten().map(|entry| entry.cost).sum::<f64>();
+                    let depth = 1 + entries.iter().flatten().map(|entry| entry.depth).max().unwrap_or(0);
+                    let instructions = weighted.iter().map(|(inst, _)| *inst).collect();
+                    Solved { plan: Plan::Lookup { bits, instructions, entries }, cost, depth }
+                }
+                Move::Branch(constraint, then, r#else) => {
+                    let (Some(then), Some(r#else)) = (self.solve(&then, limit - 1, false), self.solve(&r#else, limit - 1, false)) else {
+                        continue;
+                    };
+                    let (bitmask, value) = constraint.mask();
+                    let cost = total + self.entry_cost + then.cost + r#else.cost;
+                    let depth = 1 + then.depth.max(r#else.depth);
+                    Solved { plan: Plan::Branch { bitmask, value, then, r#else }, cost, depth }
+                }
+            };
+            if best.as_ref().is_none_or(|best| solved.cost < best.cost) {
+                best = Some(Rc::new(solved));
+            }
+        }
+        best
+    }
+
+    /// Every lookup over up to `options.budget` of the most evenly splitting bits, the ones
+    /// leaving every encoding in one entry aside.
+    fn lookups(&self, weighted: &[(&'a ir::Instruction, f64)], fixed: &[(u32, u32)]) -> Vec<(Vec<usize>, Vec<Weighted<'a>>)> {
+        let weights = weighted.iter().map(|(_, weight)| *weight).collect::<Vec<_>>();
+        let mut candidates = (0..32)
+            .filter(|bit| fixed.iter().any(|(mask, _)| mask >> bit & 1 == 1))
+            .map(|bit| (classification::score_split(fixed, &weights, bit, true), bit))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        candidates.truncate(CANDIDATE_BITS);
+
+        let mut lookups = vec![];
+        for subset in 1usize..1 << candidates.len() {
+            if subset.count_ones() as usize > self.options.budget {
+                continue;
+            }
+            let mut bits = candidates.iter().enumerate().filter(|(i, _)| subset >> i & 1 == 1).map(|(_, (_, bit))| *bit).collect::<Vec<_>>();
+            bits.sort_by(|a, b| b.cmp(a));
+            let buckets = split(weighted, &bits);
+            if buckets.iter().all(|bucket| bucket.len() < weighted.len()) {
+                lookups.push((bits, buckets));
+            }
+        }
+        lookups
+    }
+}
+
+/// Every branch on a field constraint of `weighted`, sending the encodings living in the value it
+/// rules out to `then`. Only taken when no encoding left in `else` has words there.
+fn branches<'a>(weighted: &[(&'a ir::Instruction, f64)]) -> Vec<(&'a ir::FieldConstraint, Weighted<'a>, Weighted<'a>)> {
+    let mut constraints = weighted.iter().flat_map(|(inst, _)| inst.field_constraints.iter()).collect::<Vec<_>>();
+    constraints.sort_by_key(|c| (*c.range.start(), *c.range.end(), c.value));
+    constraints.dedup();
+
+    constraints.into_iter().filter_map(|constraint| {
+        let (then, r#else): (Vec<_>, Vec<_>) = weighted.iter().copied().partition(|(inst, _)| {
+            let (mask, value) = inst.fixed_bits();
+            constraint.excludes(mask, value)
+        });
+        let outside = r#else.iter().all(|(inst, _)| {
+            let (mask, value) = inst.fixed_bits();
+            inst.field_constraints.contains(constraint) || constraint.implied_by(mask, value)
+        });
+        (!then.is_empty() && !r#else.is_empty() && outside).then_some((constraint, then, r#else))
+    }).collect()
+}
+
+/// `greedy` or a cheaper tree over `instructions` found within the budget of `search`, with a
+/// lookup at the root as well.
+pub fn improve<'a>(
+    greedy: Node<'a>,
+    instructions: &[&'a ir::Instruction],
+    frequencies: &Frequencies,
+    options: &Options,
+    search: &Search
+) -> Node<'a> {
+    let weighted = instructions.iter().map(|i| (*i, frequencies.weight(i))).collect::<Vec<_>>();
+    let total = weighted.iter().map(|(_, weight)| weight).sum::<f64>();
+    let mut searcher = Searcher {
+        options,
+        frequencies,
+        entry_cost: search.entry_cost * total,
+        expansions: search.expansions,
+        deadline: Instant::now() + search.time_limit,
+        memo: HashMap::new(),
+        overlaps: Overlaps::default()
+    };
+
+    let cost = Cost::new(&greedy, instructions, frequencies);
+    let mut best = (cost.score(search), greedy);
+    // Each pass allows a level less than the best tree so far, trading expected depth for the
+    // worst case.
+    let mut limit = cost.depth;
+    while limit > 0 && !searcher.exhausted() {
+        let Some(solved) = searcher.solve(&weighted, limit, true) else { break };
+        // A greedy subtree met encodings without a precedence the greedy tree never had to order.
+        if !searcher.overlaps.0.is_empty() {
+            break;
+        }
+        let node = solved.node();
+        let score = Cost::new(&node, instructions, frequencies).score(search);
+        if score < best.0 {
+            best = (score, node);
+        }
+        limit = solved.depth - 1;
+    }
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::fixtures::instruction;
+
+    fn decode<'a>(node: &Node<'a>, word: u32) -> &'a ir::Instruction {
+        match node {
+            Node::Lookup { bits, entries, .. } => decode(entries[bits::gather(word, bits)].as_ref().unwrap(), word),
+            Node::Branch { bitmask, value, then, r#else } => decode(if word & bitmask == *value { then } else { r#else }, word),
+            Node::Leaf(inst) => inst
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let mut instructions = vec![
+            instruction("HOT", "0000 0000 xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("UNCOND", "1111 1111 xxxx xxxx xxxx xxxx xxxx xxxx"),
+        ];
+        for i in 0..23u32 {
+            let top = format!("{:08b}", 0x80 + i * 2);
+            instructions.push(instruction(&format!("COLD_{i}"), &format!("{top} xxxx xxxx xxxx xxxx xxxx xxxx")));
+        }
+        for i in 0..3u32 {
+            instructions.push(instruction(&format!("COND_{i}"), &format!("NNNN 01{:02b} xxxx xxxx xxxx xxxx xxxx xxxx", i)));
+        }
+        let refs = instructions.iter().collect::<Vec<_>>();
+        let frequencies = Frequencies::parse("HOT 1000\nCOND_0 200").unwrap().resolve(&refs);
+        let search = Search::new(100_000);
+        let options = Options { search: Some(search), ..Options::default() };
+
+        let greedy = graph::build_with(&refs, &frequencies, &Options::default()).unwrap();
+        let searched = graph::build_with(&refs, &frequencies, &options).unwrap();
+        let (before, after) = (Cost::new(&greedy, &refs, &frequencies), Cost::new(&searched, &refs, &frequencies));
+        assert!(after.score(&search) <= before.score(&search), "{before:?} {after:?}");
+        assert!(matches!(searched, Node::Lookup { .. }));
+        for inst in &instructions {
+            let (_, value) = inst.fixed_bits();
+            for word in [value, value | 0x00FF_FFFF, value | 0x0F00_0000] {
+                if inst.matches(word) {
+                    assert_eq!(decode(&searched, word).name, inst.name, "{word:#010x}");
+                }
+            }
+        }
+
+        // The same budget gives the same tree, one spent early as well.
+        let short = Options { search: Some(Search::new(20)), ..Options::default() };
+        let again = graph::build_with(&refs, &frequencies, &short).unwrap();
+        assert_eq!(format!("{again:?}"), format!("{:?}", graph::build_with(&refs, &frequencies, &short).unwrap()));
+
+        // Without a budget, or out of time, every node keeps the greedy subtree.
+        let spent = Options { search: Some(Search::new(0)), ..Options::default() };
+        let fallback = graph::build_with(&refs, &frequencies, &spent).unwrap();
+        assert_eq!(format!("{fallback:?}"), format!("{greedy:?}"));
+        let expired = Options { search: Some(Search { time_limit: Duration::ZERO, ..search }), ..Options::default() };
+        let fallback = graph::build_with(&refs, &frequencies, &expired).unwrap();
+        assert_eq!(format!("{fallback:?}"), format!("{greedy:?}"));
+    }
+
+    #[test]
+    fn test_cost() {
+        let instructions = [
+            instruction("A", "0xxx xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("B", "10xx xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("C", "11xx xxxx xxxx xxxx xxxx xxxx xxxx xxxx"),
+        ];
+        let refs = instructions.iter().collect::<Vec<_>>();
+        let leaf = |i: usize| Some(Box::new(Node::Leaf(refs[i])));
+        let inner = Node::Lookup { instructions: Box::from(&refs[1..]), bits: Box::from([30]), entries: Box::from([leaf(1), leaf(2)]) };
+        let root = Node::Lookup { instructions: Box::from(&refs[..]), bits: Box::from([31]), entries: Box::from([leaf(0), Some(Box::new(inner))]) };
+
+        let cost = Cost::new(&root, &refs, &Frequencies::default());
+        assert_eq!(cost, Cost { expected: 5.0 / 3.0, entries: 2, depth: 2 });
+        assert_eq!(cost.score(&Search { entry_cost: 0.5, depth_cost: 1.0, ..Search::new(0) }), 5.0 / 3.0 + 3.0);
+    }
+}
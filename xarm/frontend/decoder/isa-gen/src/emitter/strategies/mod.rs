@@ -1 +1,3 @@
 pub mod latency;
+pub mod pext;
+pub mod portable;
@@ -0,0 +1,199 @@
+//! Flat tables for BMI2: every node extracts the bits it looks at with a single PEXT and indexes
+//! a child table as wide as it needs, instead of the fixed 16 ways of an `Entry`.
+
+use isa_gen_nostd::{Descriptor, PextNode};
+use crate::emitter::traits::CodeEmitter;
+use crate::emitter::options::Options;
+use crate::emitter::strategies::latency::{graph::Node, instruction, leaf::{self, Path}, Depths, Tree};
+use crate::ir;
+use quote::quote;
+use proc_macro2::TokenStream;
+
+/// Bits a lookup extracts at most, 256 children.
+pub const BUDGET: usize = 8;
+
+/// The descriptor of node `index`, a tree of more nodes than an entry descriptor addresses doesn't
+/// fit the tables.
+fn entry(index: usize) -> Descriptor {
+    assert!(
+        index <= Descriptor::MASK_DATA as usize,
+        "the tree takes more than {} PEXT nodes", Descriptor::MASK_DATA as usize + 1
+    );
+    Descriptor::new_entry(index as u16)
+}
+
+fn add_node(insts: &[&ir::Instruction], node: &Node, path: &Path, nodes: &mut Vec<PextNode>, children: &mut Vec<Descriptor>) -> Descriptor {
+    match node {
+        Node::Lookup { bits, entries, .. } => {
+            let index = nodes.len();
+            nodes.push(PextNode::default());
+
+            // PEXT packs the lowest bit of the mask into the lowest bit of the index, the graph
+            // orders `bits` highest first and numbers its entries the same way.
+            let mask = bits.iter().fold(0, |mask, bit| mask | 1 << bit);
+            let base = children.len();
+            children.resize(base + entries.len(), Descriptor::new_invalid());
+            for (ndx, entry) in entries.iter().enumerate() {
+                if let Some(entry) = entry {
+                    children[base + ndx] = add_node(insts, entry, &path.lookup(bits, ndx), nodes, children);
+                }
+            }
+
+            nodes[index] = PextNode { mask, value: 0, base: base as u32, compare: false };
+            entry(index)
+        }
+        Node::Branch { bitmask, value, then, r#else } => {
+            let index = nodes.len();
+            nodes.push(PextNode::default());
+
+            let base = children.len();
+            children.resize(base + 2, Descriptor::new_invalid());
+            children[base] = add_node(insts, r#else, &path.branch(*bitmask, *value, false), nodes, children);
+            children[base + 1] = add_node(insts, then, &path.branch(*bitmask, *value, true), nodes, children);
+
+            nodes[index] = PextNode { mask: *bitmask, value: *value, base: base as u32, compare: true };
+            entry(index)
+        }
+        Node::Leaf(inst) => match leaf::number(insts, inst) {
+            Some(leaf) => {
+                // A compare per check of the leaf, the first deciding for the word gives its leaf.
+                let mut descriptor = Descriptor::new_leaf(leaf);
+                for check in leaf::checks(inst, path).iter().rev() {
+                    let base = children.len();
+                    let decided = Descriptor::new_leaf(check.leaf);
+                    children.extend(match check.matching {
+                        true => [descriptor, decided],
+                        false => [decided, descriptor]
+                    });
+                    nodes.push(PextNode { mask: check.mask, value: check.value, base: base as u32, compare: true });
+                    descriptor = entry(nodes.len() - 1);
+                }
+                descriptor
+            }
+            None => Descriptor::new_invalid()
+        }
+    }
+}
+
+/// The nodes and children of the tree, leaves numbered after `instructions`, and the root node.
+pub fn build(instructions: &[&ir::Instruction], entry_node: &Node) -> (Vec<PextNode>, Vec<Descriptor>, u16) {
+    let mut nodes = vec![];
+    let mut children = vec![];
+    let root = add_node(instructions, entry_node, &Path::default(), &mut nodes, &mut children);
+    (nodes, children, root.0 & Descriptor::MASK_DATA)
+}
+
+fn emit_tables(nodes: &[PextNode], children: &[Descriptor], root_index: u16) -> TokenStream {
+    let node_consts = nodes.iter().map(|n| {
+        let (mask, value, base, compare) = (n.mask, n.value, n.base, n.compare);
+        quote! { PextNode { mask: #mask, value: #value, base: #base, compare: #compare } }
+    });
+    let child_raws = children.iter().map(|c| c.0);
+    let (nodes_len, children_len) = (nodes.len(), children.len());
+
+    quote! {
+        use isa_gen_nostd::{Descriptor, PextNode};
+
+        pub static NODES: [PextNode; #nodes_len] = [
+            #(#node_consts),*
+        ];
+
+        pub static CHILDREN: [Descriptor; #children_len] = [
+            #(Descriptor(#child_raws)),*
+        ];
+
+        pub static ROOT_INDEX: u16 = #root_index;
+    }
+}
+
+pub struct PextCodeEmitter {
+
+}
+
+impl CodeEmitter for PextCodeEmitter {
+    /// Same as `LatencyOptimizedCodeEmitter`, with `NODES`/`CHILDREN` in place of `ENTRIES`.
+    /// Lookups take up to [`BUDGET`] bits, set `options.budget` to use them all.
+    fn emit(options: &Options, tree: &Tree, _depths: &mut Depths) -> TokenStream {
+        assert!(
+            (1..=BUDGET).contains(&options.budget),
+            "A PEXT node extracts 1 to {BUDGET} bits, the budget is {}", options.budget
+        );
+        let (nodes, children, root) = build(&tree.implemented, &tree.root);
+
+        let inst_enum = instruction::emit(&tree.implemented);
+        let tables = emit_tables(&nodes, &children, root);
+        quote! {
+            #inst_enum
+
+            #tables
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::classification::Frequencies;
+    use crate::emitter::strategies::latency::graph;
+    use crate::ir::fixtures::instruction;
+    use crate::verify::{self, Reference, Words};
+
+    /// What `_pext_u32` does, one bit at a time.
+    fn pext(word: u32, mask: u32) -> u32 {
+        (0..32).filter(|i| mask & 1 << i != 0).enumerate().fold(0, |index, (n, i)| {
+            index | ((word >> i) & 1) << n
+        })
+    }
+
+    fn walk(nodes: &[PextNode], children: &[Descriptor], root: u16, word: u32) -> u16 {
+        let mut node = &nodes[root as usize];
+        loop {
+            let index = match node.compare {
+                true => (word & node.mask == node.value) as u32,
+                false => pext(word, node.mask)
+            };
+            let descriptor = children[(node.base + index) as usize];
+            if descriptor.0 & Descriptor::TAG_ENTRY == 0 {
+                return descriptor.0;
+            }
+            node = &nodes[(descriptor.0 & Descriptor::MASK_DATA) as usize];
+        }
+    }
+
+    #[test]
+    fn test_pext_tables() {
+        let mut instructions = vec![
+            instruction("GENERAL", "1110 1110 xxxx xxxx xxxx 1011 xxx1 0000"),
+            instruction("SPECIAL", "1110 1110 000x xxxx xxxx 1011 0001 0000"),
+            instruction("NARROWER", "1110 1110 0001 xxxx xxxx 1011 0001 0000"),
+            instruction("GATED", "1111 0011 xxxx xxxx xxxx 1000 xxx0 xxxx"),
+        ];
+        // More than an `Entry` can tell apart in a single lookup.
+        for i in 0..40u32 {
+            let top = format!("{:08b}", 0x10 + i);
+            instructions.push(instruction(&format!("FILL_{i}"), &format!("{top} xxxx xxxx xxxx 0000 xxxx xxxx")));
+        }
+        let all = instructions.iter().collect::<Vec<_>>();
+        let implemented = all.iter().copied().filter(|i| &*i.name != "GATED").collect::<Vec<_>>();
+
+        let options = Options { budget: BUDGET, ..Options::default() };
+        let tree = graph::build_with(&all, &Frequencies::default(), &options).unwrap();
+        let Node::Lookup { ref bits, .. } = tree else { panic!() };
+        assert!(bits.len() > 4);
+        let (nodes, children, root) = build(&implemented, &tree);
+
+        let reference = Reference::new(&all, &implemented);
+        let decode = |word| walk(&nodes, &children, root, word);
+        let report = verify::verify(&reference, Words::Sampled { per_stratum: 64, seed: 7 }, decode);
+        assert!(report.disagreements.is_empty(), "{report}");
+
+        assert_eq!(decode(0xEE100B10), Descriptor::RESERVED + 2);
+        assert_eq!(decode(0xF3000800), Descriptor::UNDEFINED);
+        let fill = implemented.iter().position(|i| &*i.name == "FILL_26").unwrap() as u16;
+        assert_eq!(decode(0x2A00_0000), fill + Descriptor::RESERVED);
+
+        // Past the last node an entry descriptor addresses the tree doesn't fit.
+        assert_eq!(entry(0x7FFF), Descriptor::new_entry(0x7FFF));
+        assert!(std::panic::catch_unwind(|| entry(0x8000)).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! The tree as plain Rust, nested `match`es on the bits of lookups and `if`s for branches. Slower
+//! than the tables but it compiles anywhere, no intrinsics or assembly involved.
+
+use crate::emitter::traits::CodeEmitter;
+use crate::emitter::options::Options;
+use crate::emitter::strategies::latency::{graph::Node, instruction, leaf::{self, Path}, Depths, Tree};
+use crate::ir;
+use quote::quote;
+use proc_macro2::TokenStream;
+
+/// `word` to the index of a lookup over `bits`, the first of them being the highest bit. That's
+/// a field split into single bits.
+fn lookup_index(bits: &[usize]) -> TokenStream {
+    instruction::extraction(&bits.iter().map(|&bit| bit..bit + 1).collect::<Vec<_>>())
+}
+
+fn emit_node(insts: &[&ir::Instruction], node: &Node, path: &Path) -> TokenStream {
+    match node {
+        Node::Lookup { bits, entries, .. } => {
+            let index = lookup_index(bits);
+            // Unallocated slots fall through to UNDEFINED with the indices past the lookup.
+            let arms = entries.iter().enumerate().filter_map(|(ndx, entry)| {
+                entry.as_ref().map(|entry| {
+                    let child = emit_node(insts, entry, &path.lookup(bits, ndx));
+                    let ndx = ndx as u32;
+                    quote! { #ndx => #child, }
+                })
+            });
+            quote! {
+                match #index {
+                    #(#arms)*
+                    _ => 0,
+                }
+            }
+        }
+        Node::Branch { bitmask, value, then, r#else } => {
+            let then = emit_node(insts, then, &path.branch(*bitmask, *value, true));
+            let r#else = emit_node(insts, r#else, &path.branch(*bitmask, *value, false));
+            quote! {
+                if word & #bitmask == #value { #then } else { #r#else }
+            }
+        }
+        Node::Leaf(inst) => match leaf::number(insts, inst) {
+            Some(leaf) => {
+                // The first check deciding for the word gives its leaf.
+                let checks = leaf::checks(inst, path);
+                let compares = checks.iter().map(|check| {
+                    let (mask, value, leaf) = (check.mask, check.value, check.leaf);
+                    match check.matching {
+                        true => quote! { if word & #mask == #value { #leaf } else },
+                        false => quote! { if word & #mask != #value { #leaf } else }
+                    }
+                });
+                match checks.is_empty() {
+                    true => quote! { #leaf },
+                    false => quote! { #(#compares)* { #leaf } }
+                }
+            }
+            None => quote! { 0 }
+        }
+    }
+}
+
+/// `decode(word)`, the leaf of `word` in the tree with leaves numbered after `instructions`.
+pub fn emit_decode(instructions: &[&ir::Instruction], entry_node: &Node) -> TokenStream {
+    let body = emit_node(instructions, entry_node, &Path::default());
+    quote! {
+        /// The leaf of `word`, one of the reserved `Descriptor` leaves below `Descriptor::RESERVED`
+        /// or `Descriptor::RESERVED + n` for the `n`-th encoding.
+        pub const fn decode(word: u32) -> u16 {
+            #body
+        }
+    }
+}
+
+pub struct PortableCodeEmitter {
+
+}
+
+impl CodeEmitter for PortableCodeEmitter {
+    /// Same as `LatencyOptimizedCodeEmitter`, with a `decode` function in place of the tables.
+    /// Given the tree of another strategy it decodes every word as its tables do.
+    fn emit(_options: &Options, tree: &Tree, _depths: &mut Depths) -> TokenStream {
+        let inst_enum = instruction::emit(&tree.implemented);
+        let decode = emit_decode(&tree.implemented, &tree.root);
+        quote! {
+            #inst_enum
+
+            #decode
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::classification::Frequencies;
+    use crate::emitter::strategies::latency::graph;
+    use crate::ir::fixtures::instruction;
+
+    #[test]
+    fn test_emit_decode() {
+        let instructions = [
+            instruction("GENERAL", "1110 1110 xxxx xxxx xxxx 1011 xxx1 0000"),
+            instruction("SPECIAL", "1110 1110 000x xxxx xxxx 1011 0001 0000"),
+            instruction("OTHER", "1111 0010 xxxx xxxx xxxx 1000 xxx0 xxxx"),
+        ];
+        let all = instructions.iter().collect::<Vec<_>>();
+        let tree = graph::build(&all, &Frequencies::default()).unwrap();
+        let Node::Lookup { ref bits, .. } = tree else { panic!() };
+
+        let code = emit_decode(&all[..2], &tree).to_string();
+        assert!(code.starts_with("# [doc"), "{code}");
+        assert!(code.contains(&lookup_index(bits).to_string()));
+        // SPECIAL is carved out of GENERAL with a branch, OTHER is left out.
+        assert!(code.contains("if word &"));
+        assert!(code.contains("3u16") && code.contains("4u16") && !code.contains("5u16"));
+        // The fixed bits the tree left open are compared at the leaves.
+        assert!(code.contains("!=") && code.contains("{ 0u16 }"), "{code}");
+
+        let mut special = instructions[1].clone();
+        special.unpredictable = Box::new([ir::Unpredictable { mask: 0xF000, value: 0xF000, constraints: Box::new([]) }]);
+        let code = emit_decode(&[&instructions[0], &special], &graph::build(&[&instructions[0], &special], &Frequencies::default()).unwrap());
+        assert!(code.to_string().contains("if word & 61440u32 == 61440u32 { 1u16 } else { 4u16 }"), "{code}");
+        assert_eq!(lookup_index(&[5, 0]).to_string(), quote! { (((word >> 5u32) & 1u32) << 1u32) | (word & 1u32) }.to_string());
+        assert_eq!(lookup_index(&[27]).to_string(), quote! { (word >> 27u32) & 1u32 }.to_string());
+    }
+}
@@ -1,5 +1,10 @@
 use proc_macro2::TokenStream;
 
+use crate::emitter::options::Options;
+use crate::emitter::strategies::latency::{Depths, Tree};
+
 pub trait CodeEmitter {
-    fn emit() -> TokenStream;
+    /// The code walking `tree`, built with `options`. `depths` are the ones of the tree, what the
+    /// strategy adds to it goes there.
+    fn emit(options: &Options, tree: &Tree, depths: &mut Depths) -> TokenStream;
 }
@@ -1,91 +1,307 @@
-use ureq;
+use super::cache::{self, Sha256Digest, SpecLock};
+
 use tar::Archive;
+use flate2::read::GzDecoder;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Environment variable pointing at the AArch32 specification.
+/// Accepts a `.tar.gz` (or plain `.tar`) archive, an unpacked XML directory or,
+/// with the `network` feature, an URL.
+pub const SPEC_ENV: &str = "XARM_ISA_SPEC";
 
-use std::path::PathBuf;
-use std::sync::Arc;
+/// Environment variable pointing at the A64 specification, same format as [`SPEC_ENV`].
+pub const SPEC_A64_ENV: &str = "XARM_ISA_SPEC_A64";
 
-#[derive(Debug, PartialEq)]
-enum ContentEncodingMirror {
-    None,
-    Gzip,
-    _Brotli,
-    _Unknown,
+pub const AARCH32_SPEC_URL: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
+pub const A64_SPEC_URL: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-A64-ISA/ISA_A64/ISA_A64_xml_A_profile-2025-12.tar.gz";
+
+#[derive(Debug)]
+pub enum FetchError {
+    Io(PathBuf, io::Error),
+    #[cfg(feature = "network")]
+    Http(ureq::Error),
+    /// The source is an URL but isa-gen was built without the `network` feature.
+    NetworkDisabled(Box<str>),
+    /// Nothing was configured and there is no way to fall back to the network.
+    Unconfigured(&'static str),
+    /// The response used a content encoding we don't know how to undo.
+    UnsupportedEncoding(Box<str>),
+    /// A download didn't match the digest pinned for its release.
+    PinMismatch { release: Box<str>, expected: Sha256Digest, actual: Sha256Digest },
+    /// A file on disk no longer matches the digest it is stored or pinned under.
+    Corrupted { path: PathBuf, expected: Sha256Digest, actual: Sha256Digest },
+    InvalidLock { line: usize, reason: Box<str> },
+    /// The digest recorded in the cache for an unpinned release isn't one.
+    MalformedRecord(PathBuf),
 }
 
-#[repr(C)]
-struct ResponseInfoMirror {
-    _skip: [u64; 8],
-    pub number: ContentEncodingMirror
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            #[cfg(feature = "network")]
+            FetchError::Http(e) => write!(f, "{e}"),
+            FetchError::NetworkDisabled(url) => {
+                write!(f, "cannot fetch {url}: isa-gen was built without the `network` feature")
+            }
+            FetchError::Unconfigured(var) => {
+                write!(f, "{var} is not set and isa-gen was built without the `network` feature")
+            }
+            FetchError::UnsupportedEncoding(encoding) => write!(f, "unsupported content encoding `{encoding}`"),
+            FetchError::PinMismatch { release, expected, actual } => {
+                write!(f, "{release} does not match its pin in spec.lock (expected sha256 {expected}, got {actual})")
+            }
+            FetchError::Corrupted { path, expected, actual } => {
+                write!(f, "{} is corrupted (expected sha256 {expected}, got {actual}), delete it to refetch", path.display())
+            }
+            FetchError::InvalidLock { line, reason } => write!(f, "spec.lock:{line}: {reason}"),
+            FetchError::MalformedRecord(path) => {
+                write!(f, "{} is not a sha256 digest, delete it to refetch", path.display())
+            }
+        }
+    }
 }
 
-struct BodyMirror {
-    _skip_source: [u64; 2],
-    pub info: Arc<ResponseInfoMirror>
+impl std::error::Error for FetchError {}
+
+/// Where the XML specification is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecSource {
+    Archive(PathBuf),
+    Directory(PathBuf),
+    #[cfg(feature = "network")]
+    Url(Box<str>),
 }
 
-pub struct InstructionSpecificationStream {
-    archive: Archive<ureq::BodyReader<'static>>
+impl SpecSource {
+    pub fn parse(value: &str) -> Result<Self, FetchError> {
+        if value.starts_with("http://") || value.starts_with("https://") {
+            #[cfg(feature = "network")]
+            return Ok(SpecSource::Url(Box::from(value)));
+            #[cfg(not(feature = "network"))]
+            return Err(FetchError::NetworkDisabled(Box::from(value)));
+        }
+
+        let path = PathBuf::from(value);
+        if path.is_dir() {
+            Ok(SpecSource::Directory(path))
+        } else {
+            Ok(SpecSource::Archive(path))
+        }
+    }
+
+    /// Reads `var`, falling back to `default_url` when it is unset.
+    pub fn from_env_or(var: &'static str, default_url: &str) -> Result<Self, FetchError> {
+        match std::env::var(var) {
+            Ok(value) if !value.is_empty() => Self::parse(&value),
+            _ => {
+                #[cfg(feature = "network")]
+                return Ok(SpecSource::Url(Box::from(default_url)));
+                #[cfg(not(feature = "network"))]
+                {
+                    let _ = default_url;
+                    Err(FetchError::Unconfigured(var))
+                }
+            }
+        }
+    }
+
+    pub fn aarch32() -> Result<Self, FetchError> {
+        Self::from_env_or(SPEC_ENV, AARCH32_SPEC_URL)
+    }
+
+    pub fn a64() -> Result<Self, FetchError> {
+        Self::from_env_or(SPEC_A64_ENV, A64_SPEC_URL)
+    }
+
+    /// The release `isa` is described in, A32 and T32 share the AArch32 one.
+    pub fn for_isa(isa: crate::ir::Isa) -> Result<Self, FetchError> {
+        match isa {
+            crate::ir::Isa::A32 | crate::ir::Isa::T32 => Self::aarch32(),
+            crate::ir::Isa::A64 => Self::a64(),
+        }
+    }
+
+    /// Local path backing this source, if any. Build scripts use it for `rerun-if-changed`.
+    pub fn local_path(&self) -> Option<&Path> {
+        match self {
+            SpecSource::Archive(path) | SpecSource::Directory(path) => Some(path),
+            #[cfg(feature = "network")]
+            SpecSource::Url(_) => None,
+        }
+    }
+
+    /// Digest `spec.lock` pins this release to. Unpacked directories can't be pinned.
+    pub fn pin(&self) -> Option<Sha256Digest> {
+        let release = match self {
+            SpecSource::Archive(path) => path.to_string_lossy(),
+            SpecSource::Directory(_) => return None,
+            #[cfg(feature = "network")]
+            SpecSource::Url(url) => std::borrow::Cow::Borrowed(url.as_ref()),
+        };
+
+        SpecLock::bundled().pin(cache::release_name(&release))
+    }
+
+    pub fn open(&self) -> Result<InstructionSpecificationStream, FetchError> {
+        match self {
+            SpecSource::Archive(path) => {
+                cache::verify_pinned(path, &SpecLock::bundled())?;
+                let reader = open_archive(path).map_err(|e| FetchError::Io(path.clone(), e))?;
+                Ok(InstructionSpecificationStream::Archive(Archive::new(reader)))
+            }
+            SpecSource::Directory(path) => {
+                let root = find_xml_root(path).map_err(|e| FetchError::Io(path.clone(), e))?;
+                Ok(InstructionSpecificationStream::Directory(root))
+            }
+            #[cfg(feature = "network")]
+            SpecSource::Url(url) => {
+                let path = cache::SpecCache::from_env().fetch(url, &SpecLock::bundled())?;
+                let reader = open_archive(&path).map_err(|e| FetchError::Io(path.clone(), e))?;
+                Ok(InstructionSpecificationStream::Archive(Archive::new(reader)))
+            }
+        }
+    }
 }
 
-// This pattern would have been more efficient in C++
-// There are inefficiencies here
-pub struct InstructionSpecificationIter<'archive> {
-    iter: tar::Entries<'archive, ureq::BodyReader<'static>>,
-    first_folder: PathBuf,
+fn open_archive(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    // Sniff the gzip magic instead of trusting the extension.
+    let gzipped = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    if gzipped {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
 }
 
-impl InstructionSpecificationStream {
-    pub fn connect(url: &str) -> Result<Self, ureq::Error> {
-        let mut body = ureq::get(url)
-            .header("User-Agent", "Python-urllib")
-            .call()?
-            .into_body();
-
-        // Make ureq decode as gzip
-        unsafe {
-            let mirrored: &mut BodyMirror = std::mem::transmute(&mut body);
-            let data_ptr = std::sync::Arc::as_ptr(&mirrored.info) as *mut ResponseInfoMirror;
-            if (*data_ptr).number != ContentEncodingMirror::None {
-                return Err(ureq::Error::ConnectProxyFailed("Expected content encoding to be none".to_string()));
-            }
-            (*data_ptr).number = ContentEncodingMirror::Gzip;
+fn is_xml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "xml")
+}
+
+// An unpacked release keeps everything under a single top level folder, accept both layouts.
+fn find_xml_root(path: &Path) -> io::Result<PathBuf> {
+    let mut dirs = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if is_xml(&entry_path) {
+            return Ok(path.to_path_buf());
         }
+        if entry_path.is_dir() {
+            dirs.push(entry_path);
+        }
+    }
 
-        Ok(Self { 
-            archive: Archive::new(body.into_reader())
-        })
+    match dirs.as_slice() {
+        [single] => find_xml_root(single),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "no XML specification files found")),
     }
+}
 
-    pub fn make_iter(&mut self) -> Option<InstructionSpecificationIter<'_>> {
-        let mut iter = self.archive.entries().ok()?;
+pub enum InstructionSpecificationStream {
+    Archive(Archive<Box<dyn Read>>),
+    Directory(PathBuf),
+}
+
+// This pattern would have been more efficient in C++
+// There are inefficiencies here
+pub enum InstructionSpecificationIter<'archive> {
+    Archive {
+        iter: tar::Entries<'archive, Box<dyn Read>>,
+        first_folder: PathBuf,
+    },
+    Directory(std::vec::IntoIter<PathBuf>),
+}
 
-        let first_folder_entry = iter.next()?.ok()?;
-        // Heap allocation
-        let first_folder = first_folder_entry.header().path().ok()?.to_path_buf();
+/// A single XML file of the specification, regardless of where it lives.
+pub struct SpecificationFile<'archive> {
+    path: PathBuf,
+    reader: Box<dyn Read + 'archive>,
+}
+
+impl SpecificationFile<'_> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
 
-        Some(InstructionSpecificationIter {
-            iter,
-            first_folder
-        })
+impl Read for SpecificationFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
     }
 }
 
-impl<'archive> InstructionSpecificationIter<'archive>{
-    pub fn next(&mut self) -> Option<tar::Entry<'archive, ureq::BodyReader<'static>>> { 
-        while let Some(Ok(entry)) = self.iter.next() {
-            let path = entry.path().ok()?;
-            let parent = path.parent()?;
+impl InstructionSpecificationStream {
+    pub fn make_iter(&mut self) -> Option<InstructionSpecificationIter<'_>> {
+        match self {
+            Self::Archive(archive) => {
+                let mut iter = archive.entries().ok()?;
+
+                let first_folder_entry = iter.next()?.ok()?;
+                // Heap allocation
+                let first_folder = first_folder_entry.header().path().ok()?.to_path_buf();
 
-            if self.first_folder != parent {
-                return None;
+                Some(InstructionSpecificationIter::Archive {
+                    iter,
+                    first_folder
+                })
             }
+            Self::Directory(root) => {
+                let mut files = std::fs::read_dir(root)
+                    .ok()?
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| is_xml(p))
+                    .collect::<Vec<_>>();
+                // Keep the order stable, read_dir makes no promises.
+                files.sort();
 
-            if path.extension().map_or(false, |ext| ext == "xml") {
-                return Some(entry);
+                Some(InstructionSpecificationIter::Directory(files.into_iter()))
             }
         }
+    }
+}
 
-        None
+impl<'archive> Iterator for InstructionSpecificationIter<'archive> {
+    type Item = io::Result<SpecificationFile<'archive>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Archive { iter, first_folder } => {
+                for entry in iter.by_ref() {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(e) => return Some(Err(e)),
+                    };
+
+                    let path = entry.path().ok()?.to_path_buf();
+                    let parent = path.parent()?;
+
+                    if first_folder != parent {
+                        return None;
+                    }
+
+                    if is_xml(&path) {
+                        return Some(Ok(SpecificationFile {
+                            path,
+                            reader: Box::new(entry)
+                        }));
+                    }
+                }
+
+                None
+            }
+            Self::Directory(files) => {
+                let path = files.next()?;
+                Some(File::open(&path).map(|file| SpecificationFile {
+                    path,
+                    reader: Box::new(BufReader::new(file))
+                }))
+            }
+        }
     }
 }
 
@@ -93,11 +309,86 @@ impl<'archive> InstructionSpecificationIter<'archive>{
 mod tests {
     use super::*;
 
+    use std::io::Write;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("isa-gen-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn collect_names(source: &SpecSource) -> Vec<String> {
+        let mut stream = source.open().unwrap();
+        stream
+            .make_iter()
+            .unwrap()
+            .map(|f| {
+                let mut f = f.unwrap();
+                let mut contents = String::new();
+                f.read_to_string(&mut contents).unwrap();
+                assert_eq!(contents, "<x/>");
+                f.path().file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_directory_source() {
+        let dir = scratch_dir("dir");
+        let release = dir.join("ISA_AArch32_xml_A_profile-test");
+        std::fs::create_dir(&release).unwrap();
+        std::fs::write(release.join("b.xml"), "<x/>").unwrap();
+        std::fs::write(release.join("a.xml"), "<x/>").unwrap();
+        std::fs::write(release.join("notice.txt"), "ignored").unwrap();
+
+        let source = SpecSource::parse(dir.to_str().unwrap()).unwrap();
+        assert_eq!(source, SpecSource::Directory(dir.clone()));
+        assert_eq!(collect_names(&source), ["a.xml", "b.xml"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_archive_source() {
+        let dir = scratch_dir("archive");
+        let path = dir.join("spec.tar.gz");
+
+        let encoder = flate2::write::GzEncoder::new(File::create(&path).unwrap(), flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        let mut append = |name: &str, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_entry_type(if data.is_empty() { tar::EntryType::Directory } else { tar::EntryType::Regular });
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, data).unwrap();
+        };
+        append("release/", b"");
+        append("release/a.xml", b"<x/>");
+        append("release/readme.txt", b"nope");
+        append("release/nested/c.xml", b"<x/>");
+        builder.into_inner().unwrap().finish().unwrap().flush().unwrap();
+
+        let source = SpecSource::parse(path.to_str().unwrap()).unwrap();
+        assert_eq!(source, SpecSource::Archive(path));
+        assert_eq!(collect_names(&source), ["a.xml"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_archive() {
+        let source = SpecSource::Archive(PathBuf::from("/nonexistent/spec.tar.gz"));
+        assert!(matches!(source.open(), Err(FetchError::Io(..))));
+    }
+
+    #[cfg(feature = "network")]
     #[test]
     fn test_instruction_specification_iter() {
         const ARM_SPEC: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-A64-ISA/ISA_A64/ISA_A64_xml_A_profile-2025-06.tar.gz";
 
-        let mut stream = InstructionSpecificationStream::connect(ARM_SPEC)
+        let mut stream = SpecSource::Url(Box::from(ARM_SPEC))
+            .open()
             .unwrap();
 
         stream
@@ -0,0 +1,327 @@
+// Content addressed storage for downloaded specification archives.
+//
+// Archives are stored by their SHA-256 and releases are pinned in `spec.lock`, so a build either
+// reads the exact bytes it was pinned to or fails loudly.
+use super::arm::FetchError;
+
+use sha2::{Digest, Sha256};
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Overrides where downloaded archives are kept.
+pub const CACHE_ENV: &str = "XARM_ISA_CACHE";
+
+/// Pins shipped with isa-gen, in `sha256sum` format.
+const SPEC_LOCK: &str = include_str!("../../spec.lock");
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Sha256Digest(pub [u8; 32]);
+
+impl Sha256Digest {
+    pub fn of_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut reader, &mut hasher)?;
+        Ok(Self(hasher.finalize().into()))
+    }
+}
+
+impl std::fmt::Display for Sha256Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl std::str::FromStr for Sha256Digest {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(());
+        }
+
+        let mut digest = [0u8; 32];
+        for (ndx, byte) in digest.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[ndx * 2..ndx * 2 + 2], 16).map_err(|_| ())?;
+        }
+        Ok(Self(digest))
+    }
+}
+
+/// Release archive name (`ISA_AArch32_xml_A_profile-2025-12.tar.gz`) to its pinned digest.
+#[derive(Debug, Default)]
+pub struct SpecLock {
+    pins: Vec<(Box<str>, Sha256Digest)>,
+}
+
+impl SpecLock {
+    pub fn parse(contents: &str) -> Result<Self, FetchError> {
+        let mut pins = vec![];
+
+        for (ndx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: &str| FetchError::InvalidLock { line: ndx + 1, reason: Box::from(reason) };
+
+            let (digest, release) = line.split_once(char::is_whitespace).ok_or_else(|| invalid("expected `<sha256> <release>`"))?;
+            let digest = digest.parse::<Sha256Digest>().map_err(|_| invalid("malformed sha256"))?;
+            // sha256sum marks binary mode with a leading '*'
+            let release = release.trim_start().trim_start_matches('*');
+
+            pins.push((Box::from(release), digest));
+        }
+
+        Ok(Self { pins })
+    }
+
+    /// The lock shipped with isa-gen.
+    pub fn bundled() -> Self {
+        Self::parse(SPEC_LOCK).expect("spec.lock is malformed")
+    }
+
+    pub fn pin(&self, release: &str) -> Option<Sha256Digest> {
+        self.pins.iter().find(|(r, _)| r.as_ref() == release).map(|(_, d)| *d)
+    }
+}
+
+/// File name of the release an URL or path points at.
+pub fn release_name(url_or_path: &str) -> &str {
+    let trimmed = url_or_path.split(['?', '#']).next().unwrap_or(url_or_path);
+    trimmed.rsplit(['/', '\\']).next().unwrap_or(trimmed)
+}
+
+/// Checks a local archive against its pin, if the release is pinned.
+pub fn verify_pinned(path: &Path, lock: &SpecLock) -> Result<(), FetchError> {
+    let Some(expected) = lock.pin(release_name(&path.to_string_lossy())) else {
+        return Ok(());
+    };
+
+    let file = File::open(path).map_err(|e| FetchError::Io(path.to_path_buf(), e))?;
+    let actual = Sha256Digest::of_reader(io::BufReader::new(file)).map_err(|e| FetchError::Io(path.to_path_buf(), e))?;
+
+    if actual != expected {
+        return Err(FetchError::Corrupted { path: path.to_path_buf(), expected, actual });
+    }
+    Ok(())
+}
+
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct SpecCache {
+    root: PathBuf,
+}
+
+impl SpecCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// `XARM_ISA_CACHE`, then the user cache directory, then the temp directory.
+    pub fn from_env() -> Self {
+        let root = std::env::var_os(CACHE_ENV)
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("XDG_CACHE_HOME").map(|p| PathBuf::from(p).join("xarm/isa-spec")))
+            .or_else(|| std::env::var_os("HOME").map(|p| PathBuf::from(p).join(".cache/xarm/isa-spec")))
+            .unwrap_or_else(|| std::env::temp_dir().join("xarm-isa-spec"));
+
+        Self::new(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn blob_path(&self, digest: &Sha256Digest) -> PathBuf {
+        self.root.join("sha256").join(digest.to_string())
+    }
+
+    // Digest recorded the first time an unpinned release was downloaded.
+    fn recorded_path(&self, release: &str) -> PathBuf {
+        self.root.join("releases").join(release)
+    }
+
+    fn io<T>(path: &Path, result: io::Result<T>) -> Result<T, FetchError> {
+        result.map_err(|e| FetchError::Io(path.to_path_buf(), e))
+    }
+
+    /// Looks up a release, preferring `pin` over a digest recorded by an earlier download.
+    /// A cached blob that no longer hashes to its name is rejected.
+    pub fn lookup(&self, release: &str, pin: Option<Sha256Digest>) -> Result<Option<PathBuf>, FetchError> {
+        let digest = match pin {
+            Some(pin) => pin,
+            None => {
+                let recorded = self.recorded_path(release);
+                match std::fs::read_to_string(&recorded) {
+                    Ok(hex) => hex.trim().parse().map_err(|_| FetchError::MalformedRecord(recorded))?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(FetchError::Io(recorded, e)),
+                }
+            }
+        };
+
+        let blob = self.blob_path(&digest);
+        let file = match File::open(&blob) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(FetchError::Io(blob, e)),
+        };
+
+        let actual = Self::io(&blob, Sha256Digest::of_reader(io::BufReader::new(file)))?;
+        if actual != digest {
+            return Err(FetchError::Corrupted { path: blob, expected: digest, actual });
+        }
+
+        Ok(Some(blob))
+    }
+
+    /// Stores `reader` under its digest. Fails without touching the cache when it doesn't match `pin`.
+    pub fn store(&self, release: &str, reader: impl Read, pin: Option<Sha256Digest>) -> Result<PathBuf, FetchError> {
+        let blobs = self.root.join("sha256");
+        Self::io(&blobs, std::fs::create_dir_all(&blobs))?;
+
+        let partial = blobs.join(format!(".partial-{}", std::process::id()));
+        let mut writer = HashingWriter {
+            inner: io::BufWriter::new(Self::io(&partial, File::create(&partial))?),
+            hasher: Sha256::new(),
+        };
+
+        let copied = io::copy(&mut io::BufReader::new(reader), &mut writer).and_then(|_| writer.flush());
+        if let Err(e) = copied {
+            let _ = std::fs::remove_file(&partial);
+            return Err(FetchError::Io(partial, e));
+        }
+
+        let actual = Sha256Digest(writer.hasher.finalize().into());
+        if let Some(expected) = pin.filter(|&expected| expected != actual) {
+            let _ = std::fs::remove_file(&partial);
+            return Err(FetchError::PinMismatch { release: Box::from(release), expected, actual });
+        }
+
+        let blob = self.blob_path(&actual);
+        Self::io(&blob, std::fs::rename(&partial, &blob))?;
+
+        if pin.is_none() {
+            let recorded = self.recorded_path(release);
+            Self::io(&recorded, std::fs::create_dir_all(recorded.parent().unwrap()))?;
+            Self::io(&recorded, std::fs::write(&recorded, actual.to_string()))?;
+        }
+
+        Ok(blob)
+    }
+
+    /// Returns the cached archive for `url`, downloading it on a miss.
+    #[cfg(feature = "network")]
+    pub fn fetch(&self, url: &str, lock: &SpecLock) -> Result<PathBuf, FetchError> {
+        let release = release_name(url);
+        let pin = lock.pin(release);
+
+        if let Some(path) = self.lookup(release, pin)? {
+            return Ok(path);
+        }
+
+        let response = ureq::get(url)
+            .header("User-Agent", "Python-urllib")
+            .call()
+            .map_err(FetchError::Http)?;
+
+        let encoding = response
+            .headers()
+            .get("content-encoding")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_ascii_lowercase());
+
+        let body = response.into_body().into_reader();
+
+        // The archive itself is gzipped, a gzip content-encoding would be a second layer on top.
+        match encoding.as_deref() {
+            None | Some("identity") => self.store(release, body, pin),
+            Some("gzip") | Some("x-gzip") => self.store(release, flate2::read::GzDecoder::new(body), pin),
+            Some(other) => Err(FetchError::UnsupportedEncoding(Box::from(other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn scratch_cache(name: &str) -> SpecCache {
+        let dir = std::env::temp_dir().join(format!("isa-gen-cache-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        SpecCache::new(dir)
+    }
+
+    #[test]
+    fn test_lock() {
+        let lock = SpecLock::parse(&format!("# comment\n\n{HELLO_SHA256}  *hello.tar.gz\n")).unwrap();
+        assert_eq!(lock.pin("hello.tar.gz").unwrap().to_string(), HELLO_SHA256);
+        assert!(lock.pin("other.tar.gz").is_none());
+
+        assert!(matches!(SpecLock::parse("abcd hello.tar.gz"), Err(FetchError::InvalidLock { line: 1, .. })));
+        SpecLock::bundled();
+    }
+
+    #[test]
+    fn test_release_name() {
+        assert_eq!(release_name("https://host/a/b/ISA_A64_xml-2025-06.tar.gz?x=1"), "ISA_A64_xml-2025-06.tar.gz");
+        assert_eq!(release_name("/tmp/spec.tar.gz"), "spec.tar.gz");
+    }
+
+    #[test]
+    fn test_store_and_lookup() {
+        let cache = scratch_cache("store");
+        let pin = HELLO_SHA256.parse().ok();
+
+        assert!(cache.lookup("hello.tar.gz", pin).unwrap().is_none());
+
+        let blob = cache.store("hello.tar.gz", &b"hello"[..], pin).unwrap();
+        assert!(blob.ends_with(HELLO_SHA256));
+        assert_eq!(cache.lookup("hello.tar.gz", pin).unwrap(), Some(blob.clone()));
+
+        // A changed archive is rejected and nothing is written.
+        assert!(matches!(
+            cache.store("hello.tar.gz", &b"hellO"[..], pin),
+            Err(FetchError::PinMismatch { .. })
+        ));
+
+        // Tampering with the cached copy is caught on the next lookup.
+        std::fs::write(&blob, "corrupted").unwrap();
+        assert!(matches!(cache.lookup("hello.tar.gz", pin), Err(FetchError::Corrupted { .. })));
+
+        std::fs::remove_dir_all(cache.root()).unwrap();
+    }
+
+    #[test]
+    fn test_unpinned_records_digest() {
+        let cache = scratch_cache("unpinned");
+
+        let blob = cache.store("hello.tar.gz", &b"hello"[..], None).unwrap();
+        assert_eq!(cache.lookup("hello.tar.gz", None).unwrap(), Some(blob));
+
+        std::fs::write(cache.recorded_path("hello.tar.gz"), "not a digest").unwrap();
+        assert!(matches!(cache.lookup("hello.tar.gz", None), Err(FetchError::MalformedRecord(_))));
+
+        std::fs::remove_dir_all(cache.root()).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+//! What the `isa-gen` binary prints about a decode tree: the walk of a word, the shape of the
+//! tree and its Graphviz rendering.
+//!
+//! Leaves are numbered as `LatencyOptimizedCodeEmitter` numbers them, after the implemented
+//! encodings from `Descriptor::RESERVED`, every other encoding the tree tells apart decodes as
+//! UNDEFINED.
+
+use crate::emitter::bits;
+use crate::emitter::classification::Frequencies;
+use crate::emitter::strategies::latency::{graph::Node, leaf, lut};
+use crate::ir;
+use isa_gen_nostd::Descriptor;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Every node `word` visits from `root`, one line each, down to the encoding it decodes as.
+pub fn explain(root: &Node, implemented: &[&ir::Instruction], word: u32) -> String {
+    let mut out = String::new();
+    let mut node = root;
+    loop {
+        match node {
+            Node::Lookup { bits, entries, instructions } => {
+                let index = bits::gather(word, bits);
+                let bits = bits.iter().map(usize::to_string).collect::<Vec<_>>().join(", ");
+                let width = entries.len().trailing_zeros() as usize;
+                write!(out, "lookup of {} encodings on bits {bits} = {index:#0w$b}", instructions.len(), w = width + 2).unwrap();
+                match &entries[index] {
+                    Some(entry) => {
+                        writeln!(out).unwrap();
+                        node = entry;
+                    }
+                    None => {
+                        writeln!(out, ", unallocated: UNDEFINED").unwrap();
+                        return out;
+                    }
+                }
+            }
+            Node::Branch { bitmask, value, then, r#else } => {
+                let taken = word & bitmask == *value;
+                let verdict = if taken { "then" } else { "else" };
+                writeln!(out, "branch on word & {bitmask:#010x} == {value:#010x}: {verdict}").unwrap();
+                node = if taken { then } else { r#else };
+            }
+            Node::Leaf(inst) => {
+                match (leaf::number(implemented, inst), inst.unpredictable_case(word)) {
+                    (None, _) => writeln!(out, "encoding {}, not implemented: UNDEFINED", inst.name).unwrap(),
+                    // The leaf compares the bits the tree didn't need, the word may not be an instance.
+                    (Some(_), _) if !inst.matches(word) => {
+                        writeln!(out, "encoding {}, {word:#010x} doesn't match its pattern: UNDEFINED", inst.name).unwrap()
+                    }
+                    (Some(leaf), None) => writeln!(out, "encoding {} (leaf {leaf})", inst.name).unwrap(),
+                    (Some(_), Some(case)) => {
+                        let kind = Descriptor::reserved_name(case.leaf()).unwrap();
+                        writeln!(out, "encoding {}, the word is a case: {kind} (leaf {})", inst.name, case.leaf()).unwrap();
+                        for constraint in &case.constraints {
+                            writeln!(out, "    {}: {}", constraint.id, constraint.text).unwrap();
+                        }
+                    }
+                }
+                return out;
+            }
+        }
+    }
+}
+
+/// The pattern of `inst`, highest bit first, `N`/`Z` for bits that must not be one/zero.
+pub fn pattern(inst: &ir::Instruction) -> String {
+    bits::get_key_for_pattern_nz(inst, &(0..32).rev().collect::<Vec<_>>())
+}
+
+/// The node `path` leads to, each step the entry of a lookup or `1`/`0` for the then/else of a
+/// branch, as in the `Entry` the node becomes.
+pub fn subtree<'n, 'a>(root: &'n Node<'a>, path: &[usize]) -> Option<&'n Node<'a>> {
+    path.iter().try_fold(root, |node, &step| match node {
+        Node::Lookup { entries, .. } => entries.get(step)?.as_deref(),
+        Node::Branch { then, .. } if step == 1 => Some(&**then),
+        Node::Branch { r#else, .. } if step == 0 => Some(&**r#else),
+        _ => None
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Leaves at every depth, the root being 1.
+    pub depths: BTreeMap<usize, usize>,
+    pub entries: usize,
+    /// Bytes of an entry of the geometry the pool was built in.
+    pub entry_len: usize,
+    /// Entries before identical subtrees were merged.
+    pub tree: usize
+}
+
+impl Stats {
+    pub fn new(root: &Node, implemented: &[&ir::Instruction]) -> Self {
+        let mut leaves = vec![];
+        root.leaf_depths(1, &mut leaves);
+        let mut depths = BTreeMap::new();
+        for (_, depth) in leaves {
+            *depths.entry(depth).or_default() += 1;
+        }
+
+        let pool = lut::Pool::build(implemented, root, &Frequencies::default());
+        let tree = lut::tree_len(implemented, root, pool.masks());
+        Self { depths, entries: pool.len(), entry_len: pool.entry_len(), tree }
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let most = self.depths.values().copied().max().unwrap_or(0).max(1);
+        for (depth, count) in &self.depths {
+            // At most 60 columns wide, every non-empty row gets one.
+            let bar = "#".repeat((count * 60).div_ceil(most));
+            writeln!(f, "depth {depth:>2}: {count:>6} {bar}")?;
+        }
+        let bytes = self.entries * self.entry_len;
+        write!(f, "entry pool: {} entries, {} bytes, {} before deduplication", self.entries, bytes, self.tree)
+    }
+}
+
+fn dot_node(node: &Node, implemented: &[&ir::Instruction], next: &mut usize, out: &mut String) -> usize {
+    let id = *next;
+    *next += 1;
+    match node {
+        Node::Lookup { bits, entries, .. } => {
+            let bits = bits.iter().map(usize::to_string).collect::<Vec<_>>().join(" ");
+            writeln!(out, "    n{id} [shape=box, label=\"lookup {bits}\"];").unwrap();
+            let width = entries.len().trailing_zeros() as usize;
+            for (index, entry) in entries.iter().enumerate() {
+                if let Some(entry) = entry {
+                    let child = dot_node(entry, implemented, next, out);
+                    writeln!(out, "    n{id} -> n{child} [label=\"{index:0width$b}\"];").unwrap();
+                }
+            }
+        }
+        Node::Branch { bitmask, value, then, r#else } => {
+            writeln!(out, "    n{id} [shape=diamond, label=\"& {bitmask:#010x}\\n== {value:#010x}\"];").unwrap();
+            let then = dot_node(then, implemented, next, out);
+            writeln!(out, "    n{id} -> n{then} [label=\"then\"];").unwrap();
+            let r#else = dot_node(r#else, implemented, next, out);
+            writeln!(out, "    n{id} -> n{else} [label=\"else\", style=dashed];").unwrap();
+        }
+        Node::Leaf(inst) => match leaf::number(implemented, inst) {
+            Some(leaf) => writeln!(out, "    n{id} [label=\"{}\\nleaf {leaf}\"];", inst.name).unwrap(),
+            None => writeln!(out, "    n{id} [label=\"{}\\nUNDEFINED\", style=dashed];", inst.name).unwrap()
+        }
+    }
+    id
+}
+
+/// `root` and everything below it as a Graphviz digraph.
+pub fn dot(root: &Node, implemented: &[&ir::Instruction]) -> String {
+    let mut out = String::from("digraph decode {\n    node [fontname=\"monospace\"];\n");
+    dot_node(root, implemented, &mut 0, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::strategies::latency::graph;
+    use crate::ir::fixtures::instruction;
+
+    #[test]
+    fn test_inspect() {
+        let mut instructions = [
+            instruction("GENERAL", "1110 1110 xxxx xxxx xxxx 1011 xxx1 0000"),
+            instruction("SPECIAL", "1110 1110 000x xxxx xxxx 1011 0001 0000"),
+            instruction("OTHER", "1111 0010 xxxx xxxx xxxx 1000 xxx0 xxxx"),
+        ];
+        let constraint = ir::Constraint { id: "Constraint_NOP".into(), text: "The instruction executes as a NOP.".into() };
+        instructions[0].unpredictable = Box::new([ir::Unpredictable { mask: 1 << 16, value: 1 << 16, constraints: Box::new([constraint]) }]);
+        let all = instructions.iter().collect::<Vec<_>>();
+        let implemented = &all[..2];
+        let tree = graph::build(&all, &Frequencies::default()).unwrap();
+
+        let special = explain(&tree, implemented, 0xEE00_0B10);
+        let lines = special.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("lookup of 3 encodings on bits "), "{special}");
+        assert!(lines.iter().any(|line| line.starts_with("branch on word & ") && line.ends_with(": then")), "{special}");
+        assert_eq!(lines.last(), Some(&"encoding SPECIAL (leaf 4)"));
+        let constrained = explain(&tree, implemented, 0xEE21_0B10);
+        assert!(constrained.ends_with(concat!(
+            "encoding GENERAL, the word is a case: CONSTRAINED UNPREDICTABLE (leaf 2)\n",
+            "    Constraint_NOP: The instruction executes as a NOP.\n"
+        )), "{constrained}");
+        assert!(explain(&tree, implemented, 0xF200_0800).ends_with("encoding OTHER, not implemented: UNDEFINED\n"));
+        let unallocated = explain(&tree, implemented, 0xEE00_0B11);
+        assert!(unallocated.ends_with("encoding SPECIAL, 0xee000b11 doesn't match its pattern: UNDEFINED\n"), "{unallocated}");
+        assert_eq!(pattern(&instructions[1]), "11101110000XXXXXXXXX101100010000");
+
+        let stats = Stats::new(&tree, implemented);
+        assert_eq!(stats.depths.values().sum::<usize>(), 3);
+        assert!(stats.entries >= 2);
+        assert!(stats.entries <= stats.tree);
+        assert_eq!(stats.entry_len, 64);
+        let pool = format!("entry pool: {} entries, {} bytes, {} before deduplication", stats.entries, stats.entries * 64, stats.tree);
+        assert!(stats.to_string().ends_with(&pool));
+
+        let Node::Lookup { ref bits, ref entries, .. } = tree else { panic!() };
+        let index = bits::gather(0xEE00_0B10, bits);
+        let branch = subtree(&tree, &[index]).unwrap();
+        assert!(matches!(branch, Node::Branch { .. }));
+        assert!(matches!(subtree(&tree, &[index, 1]), Some(Node::Leaf(inst)) if &*inst.name == "SPECIAL"));
+        assert!(subtree(&tree, &[index, 2]).is_none());
+        assert!(subtree(&tree, &[entries.len()]).is_none());
+
+        let graph = dot(branch, implemented);
+        assert!(graph.starts_with("digraph decode {\n"), "{graph}");
+        assert!(graph.contains("n0 [shape=diamond") && graph.contains("n0 -> n1 [label=\"then\"]"), "{graph}");
+        assert!(graph.contains("SPECIAL\\nleaf 4") && graph.contains("GENERAL\\nleaf 3"), "{graph}");
+    }
+}
@@ -0,0 +1,44 @@
+//! Encodings for tests, built from a pattern rather than parsed from the specification.
+
+use super::*;
+
+/// An A32 encoding of `pattern`, 32 of `1`, `0`, `x` for any bit and `N` for the bits of a field
+/// that must not be all ones, highest first. Whitespace is ignored.
+pub fn instruction(name: &str, pattern: &str) -> Instruction {
+    let pattern = pattern.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+    assert_eq!(pattern.len(), 32);
+
+    let mut bits = [None; 32];
+    for (i, c) in pattern.iter().rev().enumerate() {
+        bits[i] = match c {
+            '1' => Some(Bit::One),
+            '0' => Some(Bit::Zero),
+            _ => None
+        };
+    }
+    // `N` bits are a field that must not be all ones, like `cond != 1111`.
+    let not_one = pattern.iter().rev().enumerate().filter(|(_, c)| **c == 'N').map(|(i, _)| i).collect::<Vec<_>>();
+    let field_constraints = match (not_one.first(), not_one.last()) {
+        (Some(&low), Some(&high)) => {
+            let value = (1 << (high - low + 1)) - 1;
+            Box::from([FieldConstraint { range: low..=high, op: FieldOp::Ne, value }])
+        }
+        _ => Box::from([])
+    };
+
+    Instruction {
+        pattern: bits,
+        regions: Box::new([]),
+        field_constraints,
+        name: Box::from(name),
+        isa: Isa::A32,
+        width: Width::Word,
+        class: InstrClass::General,
+        docvars: Docvars::default(),
+        arch_variants: Box::new([]),
+        see: Box::new([]),
+        unpredictable: Box::new([]),
+        index: None,
+        pseudocode: Pseudocode::default()
+    }
+}
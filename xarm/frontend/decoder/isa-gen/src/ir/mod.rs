@@ -1,26 +1,475 @@
+#[cfg(test)]
+pub(crate) mod fixtures;
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Bit {
     One,
-    Zero,
-    NotOne,
-    NotZero
+    Zero
 }
 
 pub type BitPattern = [Option<Bit>; 32];
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Isa {
+    A32,
+    T32,
+    A64
+}
+
+impl Isa {
+    pub fn from_docvar(value: &str) -> Option<Self> {
+        match value {
+            "A32" => Some(Isa::A32),
+            "T32" => Some(Isa::T32),
+            "A64" => Some(Isa::A64),
+            _ => None
+        }
+    }
+}
+
+/// The `instr-class` docvar of an iclass.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InstrClass {
+    General,
+    Float,
+    Fpsimd,
+    Advsimd,
+    System
+}
+
+impl InstrClass {
+    pub const ALL: [InstrClass; 5] = [
+        InstrClass::General,
+        InstrClass::Float,
+        InstrClass::Fpsimd,
+        InstrClass::Advsimd,
+        InstrClass::System
+    ];
+
+    pub fn from_docvar(value: &str) -> Option<Self> {
+        match value {
+            "general" => Some(InstrClass::General),
+            "float" => Some(InstrClass::Float),
+            "fpsimd" => Some(InstrClass::Fpsimd),
+            "advsimd" => Some(InstrClass::Advsimd),
+            "system" => Some(InstrClass::System),
+            _ => None
+        }
+    }
+}
+
+/// Set of instruction classes a table is generated for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InstrClassSet(u8);
+
+impl InstrClassSet {
+    pub const EMPTY: InstrClassSet = InstrClassSet(0);
+    pub const ALL: InstrClassSet = InstrClassSet((1 << InstrClass::ALL.len()) - 1);
+
+    pub const fn with(self, class: InstrClass) -> Self {
+        InstrClassSet(self.0 | 1 << class as u8)
+    }
+
+    pub const fn contains(self, class: InstrClass) -> bool {
+        self.0 & (1 << class as u8) != 0
+    }
+
+    /// Parses a comma separated list of docvar values, e.g. `general,float`.
+    pub fn parse(value: &str) -> Option<Self> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .try_fold(InstrClassSet::EMPTY, |set, v| Some(set.with(InstrClass::from_docvar(v)?)))
+    }
+}
+
+impl Default for InstrClassSet {
+    fn default() -> Self {
+        InstrClassSet::ALL
+    }
+}
+
+/// The extensions a version name carries as a suffix, `T2` of `ARMv6T2` (Thumb-2) or `K` of
+/// `ARMv6K`. Every later version includes them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Extensions(u8);
+
+impl Extensions {
+    pub const T2: Self = Self(1);
+    pub const K: Self = Self(2);
+    const NAMES: [(&str, Self); 2] = [("T2", Self::T2), ("K", Self::K)];
+
+    /// Parses a suffix such as `T2` or `K`, `None` for one it doesn't know.
+    fn parse(mut suffix: &str) -> Option<Self> {
+        let mut extensions = Self::default();
+        while !suffix.is_empty() {
+            let (name, extension) = Self::NAMES.into_iter().find(|(name, _)| suffix.starts_with(name))?;
+            extensions.0 |= extension.0;
+            suffix = &suffix[name.len()..];
+        }
+        Some(extensions)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::fmt::Display for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Self::NAMES.iter().filter(|(_, extension)| self.contains(*extension)).try_for_each(|(name, _)| f.write_str(name))
+    }
+}
+
+/// An architecture version, `ARMv8.1` is `8.1` and `ARMv6T2` is `6.0` with Thumb-2.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ArchVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub extensions: Extensions
+}
+
+impl ArchVersion {
+    /// Parses `ARMv8.1`, `ARMv7`, `ARMv6T2` or `v8.2`. Suffixes other than `T2` and `K` aren't
+    /// known.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let value = value.strip_prefix("ARM").unwrap_or(value).strip_prefix('v')?;
+        fn number(s: &str) -> Option<(u8, &str)> {
+            let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            Some((s[..len].parse().ok()?, &s[len..]))
+        }
+
+        let (major, rest) = number(value)?;
+        let (minor, rest) = match rest.strip_prefix('.') {
+            Some(rest) => number(rest)?,
+            None => (0, rest)
+        };
+        Some(ArchVersion { major, minor, extensions: Extensions::parse(rest)? })
+    }
+
+    /// Whether a core of this version implements `need`, a later version has every extension
+    /// of an earlier one.
+    pub fn implements(&self, need: &ArchVersion) -> bool {
+        match (self.major, self.minor).cmp(&(need.major, need.minor)) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Equal => self.extensions.contains(need.extensions),
+            std::cmp::Ordering::Less => false
+        }
+    }
+}
+
+impl std::fmt::Display for ArchVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.extensions == Extensions::default() {
+            true => write!(f, "ARMv{}.{}", self.major, self.minor),
+            false if self.minor == 0 => write!(f, "ARMv{}{}", self.major, self.extensions),
+            false => write!(f, "ARMv{}.{}{}", self.major, self.minor, self.extensions)
+        }
+    }
+}
+
+/// One `arch_variant` of an encoding, the version and every feature are required.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ArchVariant {
+    pub version: Option<ArchVersion>,
+    pub features: Box<[Box<str>]>
+}
+
+impl ArchVariant {
+    /// The alternatives an `arch_variant` element stands for. `name` may list several versions
+    /// (`ARMv6T2, ARMv7`), any of them will do, and `feature` may be an expression
+    /// (`FEAT_A && FEAT_B || FEAT_C`), without nesting.
+    pub fn parse(name: Option<&str>, feature: Option<&str>) -> Vec<ArchVariant> {
+        let mut versions = name
+            .map(|name| name.split(',').filter_map(ArchVersion::parse).map(Some).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if versions.is_empty() {
+            versions.push(None);
+        }
+
+        let features = match feature.filter(|f| !f.trim().is_empty()) {
+            Some(feature) => feature
+                .split("||")
+                .map(|all| {
+                    all.split("&&")
+                        .map(|f| f.trim_matches(|c: char| c.is_whitespace() || c == '(' || c == ')'))
+                        .filter(|f| !f.is_empty())
+                        .map(Box::from)
+                        .collect::<Box<[Box<str>]>>()
+                })
+                .collect(),
+            None => vec![Box::from([])]
+        };
+        versions
+            .iter()
+            .flat_map(|&version| features.iter().map(move |features| ArchVariant { version, features: features.clone() }))
+            .collect()
+    }
+}
+
+/// The core tables are generated for: an architecture version and the `FEAT_*` it implements.
+/// Features are not implied by the version, a core has to list every one it implements.
+/// `None` leaves that part unrestricted, the default profile allows every encoding.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub version: Option<ArchVersion>,
+    pub features: Option<Box<[Box<str>]>>
+}
+
+impl Profile {
+    /// Parses a comma separated version and feature list, e.g. `ARMv7` or `ARMv8.1,FEAT_PAN`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut profile = Profile::default();
+        let mut features = vec![];
+        for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if part.starts_with("FEAT_") {
+                features.push(Box::from(part));
+            } else if profile.version.is_none() {
+                profile.version = Some(ArchVersion::parse(part)?);
+            } else {
+                return None;
+            }
+        }
+
+        if profile.version.is_some() || !features.is_empty() {
+            profile.features = Some(features.into());
+        }
+        Some(profile)
+    }
+
+    fn satisfies(&self, variant: &ArchVariant) -> bool {
+        let version = match (self.version, variant.version) {
+            (Some(have), Some(need)) => have.implements(&need),
+            _ => true
+        };
+        let features = match &self.features {
+            Some(have) => variant.features.iter().all(|f| have.contains(f)),
+            None => true
+        };
+        version && features
+    }
+
+    /// Whether a core with this profile implements `inst`.
+    pub fn allows(&self, inst: &Instruction) -> bool {
+        inst.arch_variants.is_empty() || inst.arch_variants.iter().any(|v| self.satisfies(v))
+    }
+}
+
+/// Size of an encoding. Patterns are always 32 bits wide, narrower encodings are left aligned
+/// so a T32 halfword sits in bits 31..16, exactly where the first halfword of a wide one does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Width {
+    Halfword,
+    Word
+}
+
+impl Width {
+    pub const fn bits(self) -> usize {
+        match self {
+            Width::Halfword => 16,
+            Width::Word => 32
+        }
+    }
+
+    /// How far the encoding's own bit numbering is shifted up inside a `BitPattern`.
+    pub const fn shift(self) -> usize {
+        32 - self.bits()
+    }
+}
+
+/// A named field of an encoding (`Rd`, `imm12`, ...), `range` indexes the `BitPattern`.
+/// Fields split over several boxes show up once per box, highest bits first.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BitRegion {
     pub label: Box<str>,
     pub range: std::ops::Range<usize>
 }
 
+/// The ASL of an encoding as written in the specification, entities already resolved.
+/// Decode is shared by the encodings of an iclass, execute by the whole section.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Pseudocode {
+    pub decode: Option<Box<str>>,
+    pub execute: Option<Box<str>>
+}
+
+/// Every `docvar` of an encoding (`mnemonic`, `cond-setting`, `address-form`, ...) in document order.
+/// The section sets the defaults, the iclass and then the encoding override them.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Docvars(Vec<(Box<str>, Box<str>)>);
+
+impl Docvars {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| &**k == key).map(|(_, v)| &**v)
+    }
+
+    /// Sets `key`, keeping its position if it was already there.
+    pub fn insert(&mut self, key: impl Into<Box<str>>, value: impl Into<Box<str>>) {
+        let (key, value) = (key.into(), value.into());
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key, value))
+        }
+    }
+
+    /// `self` with the docvars of `other` on top.
+    pub fn merged(&self, other: &Docvars) -> Docvars {
+        let mut merged = self.clone();
+        for (key, value) in other.iter() {
+            merged.insert(key, value);
+        }
+        merged
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (&**k, &**v))
+    }
+
+    pub fn mnemonic(&self) -> Option<&str> {
+        self.get("mnemonic")
+    }
+}
+
+/// A `SEE` in the decode pseudocode, words with `word & mask == value` belong to another encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct See {
+    pub mask: u32,
+    pub value: u32,
+    /// As written, usually a title like `LDR (literal)`.
+    pub target: Box<str>
+}
+
+impl See {
+    /// Whether every word with `word & mask == value` is redirected.
+    pub fn covers(&self, mask: u32, value: u32) -> bool {
+        self.mask & !mask == 0 && value & self.mask == self.value
+    }
+}
+
+/// A behaviour the architecture constrains an UNPREDICTABLE case to, e.g. `Constraint_UNDEF`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Constraint {
+    pub id: Box<str>,
+    /// What it means, from `constraint_text_mappings.xml`.
+    pub text: Box<str>
+}
+
+/// An `UNPREDICTABLE` in the decode pseudocode, words of the encoding with `word & mask == value`
+/// don't behave as it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Unpredictable {
+    pub mask: u32,
+    pub value: u32,
+    /// The choices the architecture leaves, empty when anything may happen.
+    pub constraints: Box<[Constraint]>
+}
+
+impl Unpredictable {
+    pub fn covers(&self, word: u32) -> bool {
+        word & self.mask == self.value
+    }
+
+    /// `Descriptor::UNPREDICTABLE` or `Descriptor::CONSTRAINED_UNPREDICTABLE`.
+    pub fn leaf(&self) -> u16 {
+        match self.constraints.is_empty() {
+            true => isa_gen_nostd::Descriptor::UNPREDICTABLE,
+            false => isa_gen_nostd::Descriptor::CONSTRAINED_UNPREDICTABLE
+        }
+    }
+}
+
+/// How a `FieldConstraint` compares its field, the diagrams only have `!=` boxes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FieldOp {
+    Ne
+}
+
+/// A box such as `cond != 1111`. It constrains the field as a whole, `!= 1111` only rules out
+/// `1111` and not every word with a one in the field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldConstraint {
+    pub range: std::ops::RangeInclusive<usize>,
+    pub op: FieldOp,
+    /// The field value, its lowest bit is the start of `range`.
+    pub value: u32
+}
+
+impl FieldConstraint {
+    /// The field and the value it is compared to, in place in the word.
+    pub fn mask(&self) -> (u32, u32) {
+        let mask = u32::MAX >> (31 - self.range.end()) & u32::MAX << self.range.start();
+        (mask, self.value << self.range.start() & mask)
+    }
+
+    pub fn holds(&self, word: u32) -> bool {
+        let (mask, value) = self.mask();
+        match self.op {
+            FieldOp::Ne => word & mask != value
+        }
+    }
+
+    /// Whether it holds for none of the words with `word & mask == value`.
+    pub fn excludes(&self, mask: u32, value: u32) -> bool {
+        let (field, field_value) = self.mask();
+        match self.op {
+            FieldOp::Ne => mask & field == field && value & field == field_value
+        }
+    }
+
+    /// Whether it holds for every word with `word & mask == value`.
+    pub fn implied_by(&self, mask: u32, value: u32) -> bool {
+        let (field, field_value) = self.mask();
+        match self.op {
+            FieldOp::Ne => mask & field & (value ^ field_value) != 0
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Instruction {
     pub pattern: BitPattern,
     pub regions: Box<[BitRegion]>,
-    pub filters: Box<[std::ops::RangeInclusive<usize>]>,
-    pub name: Box<str>
+    /// On top of `pattern`, a word has to satisfy every one of them.
+    pub field_constraints: Box<[FieldConstraint]>,
+    pub name: Box<str>,
+    pub isa: Isa,
+    pub width: Width,
+    pub class: InstrClass,
+    pub docvars: Docvars,
+    /// Any one of them is enough, an encoding without variants is always there.
+    pub arch_variants: Box<[ArchVariant]>,
+    pub see: Box<[See]>,
+    /// In the order the pseudocode checks them, the first covering a word applies.
+    pub unpredictable: Box<[Unpredictable]>,
+    /// Position in the encoding index (`a32_encindex.xml`, ...), overlaps fall back to its order.
+    pub index: Option<u32>,
+    pub pseudocode: Pseudocode
+}
+
+impl Instruction {
+    /// The bits `pattern` fixes as a mask and value.
+    pub fn fixed_bits(&self) -> (u32, u32) {
+        self.pattern.iter().enumerate().fold((0, 0), |(mask, value), (i, bit)| match bit {
+            Some(Bit::One) => (mask | 1 << i, value | 1 << i),
+            Some(Bit::Zero) => (mask | 1 << i, value),
+            None => (mask, value)
+        })
+    }
+
+    /// Whether `word` is an instance of the encoding, every fixed bit matches and no `!=`
+    /// constraint rules it out. Narrow encodings only look at the top halfword.
+    pub fn matches(&self, word: u32) -> bool {
+        let (mask, value) = self.fixed_bits();
+        word & mask == value && self.field_constraints.iter().all(|constraint| constraint.holds(word))
+    }
+
+    /// The UNPREDICTABLE case of the encoding `word` falls in, if any.
+    pub fn unpredictable_case(&self, word: u32) -> Option<&Unpredictable> {
+        self.unpredictable.iter().find(|case| case.covers(word))
+    }
 }
 
 impl std::hash::Hash for Instruction {
@@ -28,3 +477,72 @@ impl std::hash::Hash for Instruction {
         self.pattern.hash(state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arch_variants() {
+        assert_eq!(ArchVersion::parse("ARMv8.1"), Some(ArchVersion { major: 8, minor: 1, ..Default::default() }));
+        let t2 = ArchVersion { major: 6, minor: 0, extensions: Extensions::T2 };
+        assert_eq!(ArchVersion::parse("ARMv6T2"), Some(t2));
+        assert_eq!(t2.to_string(), "ARMv6T2");
+        assert_eq!(ArchVersion::parse("ARMv6KT2").map(|v| v.extensions.contains(Extensions::K)), Some(true));
+        assert_eq!(ArchVersion::parse("FEAT_SB"), None);
+        assert_eq!(ArchVersion::parse("ARMv6Q"), None);
+
+        let variants = ArchVariant::parse(Some("ARMv8.2, ARMv8.0"), Some("FEAT_A && FEAT_B || FEAT_C"));
+        assert_eq!(variants.len(), 4);
+        assert_eq!(variants[0].version, ArchVersion::parse("ARMv8.2"));
+        assert_eq!(variants[2].version, ArchVersion::parse("ARMv8.0"));
+        assert_eq!(&*variants[0].features, [Box::from("FEAT_A"), Box::from("FEAT_B")]);
+        assert_eq!(&*variants[1].features, [Box::from("FEAT_C")]);
+    }
+
+    #[test]
+    fn test_profile() {
+        let variant = |name, feature| ArchVariant::parse(name, feature);
+        let armv7 = Profile::parse("ARMv7").unwrap();
+        let armv81 = Profile::parse("ARMv8.1, FEAT_PAN").unwrap();
+        assert_eq!(Profile::parse(""), Some(Profile::default()));
+        assert_eq!(Profile::parse("ARMv7,ARMv8"), None);
+
+        let armv6 = Profile::parse("ARMv6").unwrap();
+        let armv6t2 = Profile::parse("ARMv6T2").unwrap();
+
+        let encoding = |variants: Vec<ArchVariant>| Instruction { arch_variants: variants.into(), ..fixtures::instruction("E", &"x".repeat(32)) };
+        let pan = encoding(variant(Some("ARMv8.1"), Some("FEAT_PAN")));
+        let sb = encoding(variant(None, Some("FEAT_SB")));
+        // MOVW and the 32-bit T32 encodings, an ARMv6 core such as the ARM11 has no Thumb-2.
+        let movw = encoding([variant(Some("ARMv6T2"), None), variant(Some("ARMv7"), None)].concat());
+        let profiles = [&armv6, &armv6t2, &armv7, &armv81, &Profile::default()];
+        for (inst, allowed) in [
+            (&pan, [false, false, false, true, true]),
+            (&sb, [false, false, false, false, true]),
+            (&movw, [false, true, true, true, true])
+        ] {
+            assert_eq!(profiles.map(|p| p.allows(inst)), allowed, "{}", inst.name);
+        }
+        assert!(Profile::parse("ARMv6K").unwrap().allows(&encoding(variant(Some("ARMv6K"), None))));
+        assert!(!armv6t2.allows(&encoding(variant(Some("ARMv6K"), None))));
+    }
+
+    #[test]
+    fn test_field_constraints() {
+        // `cond != 1111`, the unconditional space is the one value it rules out.
+        let cond = FieldConstraint { range: 28..=31, op: FieldOp::Ne, value: 0b1111 };
+        assert_eq!(cond.mask(), (0xF000_0000, 0xF000_0000));
+        assert!(cond.holds(0xE000_0000) && cond.holds(0x7000_0000));
+        assert!(!cond.holds(0xF123_4567));
+
+        assert!(cond.excludes(0xFF00_0000, 0xF500_0000));
+        assert!(!cond.excludes(0x7000_0000, 0x7000_0000));
+        assert!(cond.implied_by(0x1000_0000, 0));
+        assert!(!cond.implied_by(0x3000_0000, 0x3000_0000));
+
+        let size = FieldConstraint { range: 0..=1, op: FieldOp::Ne, value: 0b00 };
+        assert_eq!(size.mask(), (0b11, 0));
+        assert!(size.holds(0b10) && !size.holds(0b100));
+    }
+}
@@ -2,4 +2,7 @@ pub mod fetcher;
 pub mod parser;
 pub mod ir;
 pub mod emitter;
+pub mod asl;
+pub mod verify;
+pub mod inspect;
 
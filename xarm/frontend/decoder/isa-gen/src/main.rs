@@ -0,0 +1,119 @@
+//! Looks inside the decode tree of an instruction set.
+//!
+//! `isa-gen [options] <a32|t32|a64> <command>`, the tree is built the way the `decoder` build
+//! builds it, from the same `XARM_ISA_*` variables.
+//!
+//! - `explain <word>`: every lookup and branch `word` goes through and the encoding it ends at.
+//! - `match <word>`: every encoding of the specification `word` is an instance of.
+//! - `stats`: leaves per depth and the size of the entry pool, with a budget of up to 8.
+//! - `dot [path]`: the tree, or the subtree at `path` (e.g. `3.1`), as Graphviz.
+//! - `time [runs]`: how long building the tree takes, the mean of `runs` (10 by default).
+//!
+//! Words are hexadecimal with `0x`, decimal without, T32 ones are `(hw1 << 16) | hw2`.
+//!
+//! - `--spec <path or url>`: the specification to read instead of `XARM_ISA_SPEC(_A64)`.
+//! - `--budget <bits>`: bits a lookup takes at most, 4 by default as in an `Entry`.
+//! - `--branch-ratio <start..end>`: share of the weight a filter has to split off to become a
+//!   branch, instead of `XARM_ISA_BRANCH_RATIO` or `0.3..0.7`.
+//! - `--search <nodes>`: nodes the search for a cheaper tree than the greedy one may expand,
+//!   instead of `XARM_ISA_SEARCH`.
+
+use isa_gen::emitter::options::{parse_ratio, parse_search, Options};
+use isa_gen::emitter::strategies::latency::{self, graph, lut};
+use isa_gen::fetcher::arm::SpecSource;
+use isa_gen::inspect::{self, Stats};
+use isa_gen::ir::Isa;
+use isa_gen::verify::Reference;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: isa-gen [--spec <path>] [--budget <bits>] [--branch-ratio <start..end>] [--search <nodes>] \
+         <a32|t32|a64> <explain <word> | match <word> | stats | dot [path] | time [runs]>"
+    );
+    std::process::exit(2)
+}
+
+fn parse_word(arg: Option<&String>) -> u32 {
+    let arg = arg.unwrap_or_else(|| usage());
+    let word = match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(&hex.replace('_', ""), 16),
+        None => arg.replace('_', "").parse()
+    };
+    word.unwrap_or_else(|_| usage())
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut options = Options::from_env().unwrap_or_else(|e| panic!("{e}"));
+    while args.first().is_some_and(|arg| arg.starts_with("--")) {
+        let flag = args.remove(0);
+        let value = if args.is_empty() { usage() } else { args.remove(0) };
+        match flag.as_str() {
+            "--spec" => options.source = Some(SpecSource::parse(&value).unwrap_or_else(|e| panic!("--spec: {e}"))),
+            "--budget" => options.budget = value.parse().ok().filter(|budget| *budget > 0).unwrap_or_else(|| usage()),
+            "--branch-ratio" => options.branch_ratio = parse_ratio(&value).unwrap_or_else(|| usage()),
+            "--search" => options.search = Some(parse_search(&value).unwrap_or_else(|| usage())),
+            _ => usage(),
+        }
+    }
+    options.isa = match args.first().map(String::as_str) {
+        Some("a32") => Isa::A32,
+        Some("t32") => Isa::T32,
+        Some("a64") => Isa::A64,
+        _ => usage(),
+    };
+    let isa = options.isa;
+
+    let mut warnings = vec![];
+    let instructions = options.load(&mut warnings);
+    for warning in &warnings {
+        eprintln!("warning: {warning}");
+    }
+    let (all, implemented) = latency::select(&instructions, isa, options.classes, &options.profile);
+    let frequencies = options.frequencies.resolve(&all);
+    let tree = graph::build_with(&all, &frequencies, &options).unwrap_or_else(|e| panic!("Failed to build the {isa:?} decode tree: {e}"));
+
+    match args.get(1).map(String::as_str) {
+        Some("explain") => print!("{}", inspect::explain(&tree, &implemented, parse_word(args.get(2)))),
+        Some("match") => {
+            let word = parse_word(args.get(2));
+            let reference = Reference::new(&all, &implemented);
+            let mut matching = reference.matching(word).peekable();
+            if matching.peek().is_none() {
+                println!("{word:#010x} matches no encoding");
+            }
+            for inst in matching {
+                let gated = if implemented.iter().any(|i| std::ptr::eq(*i, inst)) { "" } else { " (not implemented)" };
+                println!("{} {}{gated}", inspect::pattern(inst), inst.name);
+            }
+        }
+        Some("stats") if options.budget > lut::MAX_BUDGET => {
+            eprintln!("an `Entry` looks up {} bits at most, the entry pool needs a smaller budget", lut::MAX_BUDGET);
+            std::process::exit(1)
+        }
+        Some("stats") => println!("{}", Stats::new(&tree, &implemented)),
+        Some("dot") => {
+            let path = match args.get(2) {
+                Some(path) => path.split('.').map(|step| step.parse().unwrap_or_else(|_| usage())).collect(),
+                None => vec![],
+            };
+            let subtree = inspect::subtree(&tree, &path).unwrap_or_else(|| {
+                eprintln!("no node at {}", args[2]);
+                std::process::exit(1)
+            });
+            print!("{}", inspect::dot(subtree, &implemented));
+        }
+        Some("time") => {
+            let runs = match args.get(2) {
+                Some(runs) => runs.parse().ok().filter(|runs| *runs > 0).unwrap_or_else(|| usage()),
+                None => 10,
+            };
+            let start = std::time::Instant::now();
+            for _ in 0..runs {
+                std::hint::black_box(graph::build_with(&all, &frequencies, &options).unwrap());
+            }
+            println!("{} encodings, {:?} per build over {runs} runs", all.len(), start.elapsed() / runs);
+        }
+        _ => usage(),
+    }
+}
@@ -1,9 +1,11 @@
 // Good luck debugging this shitshow
-use crate::{fetcher, ir};
+use crate::{asl, fetcher, ir};
+use crate::asl::ast::{BinOp, Expr, Slice, Stmt};
 
 use std::io::Read;
+use std::path::{Path, PathBuf};
 use quick_xml::reader::Reader;
-use quick_xml::events::Event;
+use quick_xml::events::{BytesStart, Event};
 
 /*
  * We save:
@@ -13,58 +15,145 @@ use quick_xml::events::Event;
  *
  * Nice to have:
  * - Explanation, as comments
+ *
+ * Anything unexpected is a `ParseError`. Where it is can be skipped over (a box, a docvar) it only
+ * drops the encoding it belongs to, otherwise the whole file. Either way it ends up a `Warning`.
  */
 
-#[derive(Debug, Default)]
-struct Docvars {
-    mnemonic: Option<Box<str>>,
-    instr_class: Option<Box<str>>,
-    isa: Option<Box<str>>,
+/// Where in the release the parser gave up and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub file: PathBuf,
+    /// Byte offset into `file`, just past the element that failed.
+    pub position: u64,
+    /// The open elements, e.g. `instructionsection/classes/iclass/regdiagram/box`.
+    pub path: Box<str>,
+    pub message: Box<str>
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}: {}", self.file.display(), self.position, self.path, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Something left out of the IR, `dropped` names the encoding (or the file name when all of it is).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub dropped: Box<str>,
+    pub error: ParseError
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dropped {}: {}", self.dropped, self.error)
+    }
+}
+
+/// Every file of a release is parsed, so a warning is kept once however often it is seen.
+fn warn(warnings: &mut Vec<Warning>, warning: Warning) {
+    if !warnings.contains(&warning) {
+        warnings.push(warning);
+    }
+}
+
+/// A `Reader` keeping track of the file and the open elements for errors.
+struct XmlReader<'a> {
+    reader: Reader<&'a [u8]>,
+    file: &'a Path,
+    path: Vec<Box<str>>
+}
+
+impl<'a> XmlReader<'a> {
+    fn new(contents: &'a [u8], file: &'a Path) -> Self {
+        XmlReader { reader: Reader::from_reader(contents), file, path: vec![] }
+    }
+
+    fn error(&self, message: impl Into<Box<str>>) -> ParseError {
+        ParseError {
+            file: self.file.to_path_buf(),
+            position: self.reader.buffer_position(),
+            path: self.path.join("/").into(),
+            message: message.into()
+        }
+    }
+
+    /// Next event, the end of the file included.
+    fn event<'b>(&mut self, buf: &'b mut Vec<u8>) -> Result<Event<'b>, ParseError> {
+        buf.clear();
+        let event = match self.reader.read_event_into(buf) {
+            Ok(event) => event,
+            Err(e) => return Err(self.error(e.to_string()))
+        };
+        match &event {
+            Event::Start(e) => self.path.push(String::from_utf8_lossy(e.name().as_ref()).into()),
+            Event::End(_) => {
+                self.path.pop();
+            }
+            _ => {}
+        }
+        Ok(event)
+    }
+
+    /// Next event inside an element, the file ending there is an error.
+    fn next<'b>(&mut self, buf: &'b mut Vec<u8>) -> Result<Event<'b>, ParseError> {
+        match self.event(buf)? {
+            Event::Eof => Err(self.error("unexpected end of file")),
+            event => Ok(event)
+        }
+    }
+
+    fn text(&self, e: &quick_xml::events::BytesText) -> Result<String, ParseError> {
+        e.decode().map(|t| t.into_owned()).map_err(|e| self.error(e.to_string()))
+    }
 }
 
-fn parse_docvar(reader: &mut Reader<&[u8]>) -> Docvars {
-    let mut docvars = Docvars::default();
+fn attribute(e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == key)
+        .map(|a| String::from_utf8_lossy(a.value.as_ref()).into_owned())
+}
+
+fn parse_docvar(reader: &mut XmlReader, problems: &mut Vec<ParseError>) -> Result<ir::Docvars, ParseError> {
+    let mut docvars = ir::Docvars::default();
     let mut buf = Vec::with_capacity(512);
 
     let mut docvars_found = false;
 
     loop {
-        buf.clear();
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+        match reader.next(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) => {
                 let is_docvars_start = e.name().as_ref() == b"docvars";
-                assert!(is_docvars_start == !docvars_found);
+                if is_docvars_start == docvars_found {
+                    return Err(reader.error(format!(
+                        "expected {}, found `{}`",
+                        if docvars_found { "a `docvar`" } else { "`docvars`" },
+                        String::from_utf8_lossy(e.name().as_ref())
+                    )));
+                }
                 if is_docvars_start {
                     docvars_found = true;
                     continue;
                 } 
 
-                assert!(e.name().as_ref() == b"docvar");
-                let mut attrs = e.attributes().flatten();
-                let key = attrs.find(|a| a.key.as_ref() == b"key").unwrap().value;
-                let value = attrs.find(|a| a.key.as_ref() == b"value").unwrap().value;
-                let boxed = Box::from(String::from_utf8_lossy(value.as_ref()));
-
-                match key.as_ref() {
-                    b"mnemonic" => {
-                        docvars.mnemonic = Some(boxed);
-                    },
-                    b"instr-class" => {
-                        docvars.instr_class = Some(boxed);
-                    },
-                    b"isa" => {
-                        docvars.isa = Some(boxed);
-                    }
-                    _ => {}
+                if e.name().as_ref() != b"docvar" {
+                    problems.push(reader.error("expected a `docvar`"));
+                    continue;
+                }
+                match (attribute(e, b"key"), attribute(e, b"value")) {
+                    (Some(key), Some(value)) => docvars.insert(key, value),
+                    _ => problems.push(reader.error("`docvar` without a key or value"))
                 }
             },
-            Ok(Event::End(ref e)) if e.name().as_ref() == b"docvars" => break,
-            Ok(Event::Eof) => panic!(),
+            Event::End(ref e) if e.name().as_ref() == b"docvars" => break,
             _ => {}
         }
     }
 
-    docvars
+    Ok(docvars)
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -77,17 +166,16 @@ enum Bit {
     Inherited
 }
 
-impl From<u8> for Bit {
-    fn from(value: u8) -> Self {
+impl Bit {
+    fn parse(value: u8) -> Option<Self> {
         match value {
-            b'1' => Bit::One,
-            b'0' => Bit::Zero,
-            b'x' => Bit::Variable,
-            b'N' => Bit::NotOne,
-            b'Z' => Bit::NotZero,
-            _ => { println!("{}", value as char); panic!() }
+            b'1' => Some(Bit::One),
+            b'0' => Some(Bit::Zero),
+            b'x' => Some(Bit::Variable),
+            b'N' => Some(Bit::NotOne),
+            b'Z' => Some(Bit::NotZero),
+            _ => None
         }
-
     }
 }
 
@@ -95,10 +183,46 @@ impl From<u8> for Bit {
 struct BitBox {
     bits: Vec<Bit>,
     hibit: Option<usize>,
+    /// Cells don't always add up to it, a single variable bit is an empty `<c></c>`.
+    width: usize,
     name: Option<Box<str>>
 }
 
-fn parse_boxes(reader: &mut Reader<&[u8]>, start_box: Option<BitBox>) -> Vec<BitBox> {
+/// `bits` is how wide the diagram is, 16 for narrow T32 encodings.
+fn parse_box_start(reader: &XmlReader, e: &BytesStart, bits: usize, problems: &mut Vec<ParseError>) -> BitBox {
+    let mut number = |key: &[u8]| {
+        let value = attribute(e, key)?;
+        let number = value.parse::<usize>().ok();
+        if number.is_none() {
+            problems.push(reader.error(format!("box {} `{value}` is not a number", String::from_utf8_lossy(key))));
+        }
+        number
+    };
+
+    let hibit = number(b"hibit");
+    let width = number(b"width").unwrap_or(1);
+    match hibit {
+        Some(hibit) if hibit >= bits || width == 0 || width > hibit + 1 => {
+            problems.push(reader.error(format!("box {hibit}:{width} doesn't fit a {bits} bit diagram")));
+        }
+        None => problems.push(reader.error("box without a hibit")),
+        _ => {}
+    }
+
+    BitBox {
+        bits: vec![],
+        hibit,
+        width,
+        name: attribute(e, b"name").map(Box::from)
+    }
+}
+
+fn parse_boxes(
+    reader: &mut XmlReader,
+    start_box: Option<BitBox>,
+    bits: usize,
+    problems: &mut Vec<ParseError>
+) -> Result<Vec<BitBox>, ParseError> {
     let mut res = vec![];
     let mut buf = Vec::with_capacity(512);
 
@@ -106,27 +230,11 @@ fn parse_boxes(reader: &mut Reader<&[u8]>, start_box: Option<BitBox>) -> Vec<Bit
     let mut current_box = start_box;
 
     loop {
-        buf.clear();
-
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(ref e)) => {
+        match reader.next(&mut buf)? {
+            Event::Start(ref e) => {
                 match e.name().as_ref() {
                     b"box" => {
-                        let mut attrs = e.attributes().flatten();
-
-                        let hibit = attrs
-                            .find(|a| a.key.as_ref() == b"hibit")
-                            .map(|v| std::str::from_utf8(v.value.as_ref()).unwrap().parse::<usize>().unwrap());
-
-                        let name = attrs
-                            .find(|a| a.key.as_ref() == b"name")
-                            .map(|v| Box::from(String::from_utf8_lossy(v.value.as_ref())));
-
-                        current_box = Some(BitBox {
-                            bits: vec![],
-                            name,
-                            hibit
-                        });
+                        current_box = Some(parse_box_start(reader, e, bits, problems));
                     },
                     b"c" => {
                         current_c = true;
@@ -136,58 +244,75 @@ fn parse_boxes(reader: &mut Reader<&[u8]>, start_box: Option<BitBox>) -> Vec<Bit
                     }
                 }
             },
-            Ok(Event::Text(ref e)) => {
+            Event::Text(ref e) => {
                 if current_c {
-                    let boxed = e.decode().unwrap();
-                    let c = boxed.as_ref();
+                    let c = reader.text(e)?;
+                    let Some(current) = current_box.as_mut() else {
+                        problems.push(reader.error("cell outside of a box"));
+                        continue;
+                    };
+
                     let bit = if c.len() == 1 {
                         c.as_bytes()[0]
                     } else if c.len() == 3 {
                         c.as_bytes()[1]
                     } else {
-                        if c.starts_with("!= ") {
-                            for character in c[3..].chars() {
-                                // TODO: this box is not the size of the NZN, is this fine?
+                        if let Some(value) = c.strip_prefix("!= ") {
+                            // The value the field must not have, a `FieldConstraint` once the box is placed.
+                            // Bits it doesn't compare (`!= 111x`) have to be at either end.
+                            if value.trim_matches('x').contains('x') {
+                                problems.push(reader.error(format!("free bit between the compared ones of `{c}`")));
+                                continue;
+                            }
+                            for character in value.chars() {
                                 let p = match character {
-                                    '1' => Bit::NotOne,
-                                    '0' => Bit::NotZero,
-                                    ch => (ch as u8).into()
+                                    '1' => Some(Bit::NotOne),
+                                    '0' => Some(Bit::NotZero),
+                                    'x' => Some(Bit::Variable),
+                                    _ => None
                                 };
-                                current_box.as_mut().unwrap().bits.push(p);
+                                match p {
+                                    Some(p) => current.bits.push(p),
+                                    None => problems.push(reader.error(format!("unexpected bit `{character}` in `{c}`")))
+                                }
                             }
                             continue;
                         }
 
-                        panic!("Unexpected length")
+                        problems.push(reader.error(format!("unexpected cell `{c}`")));
+                        continue;
                     };
 
-                    current_box.as_mut().unwrap().bits.push(bit.into());
+                    match Bit::parse(bit) {
+                        Some(bit) => current.bits.push(bit),
+                        None => problems.push(reader.error(format!("unexpected bit `{}` in `{c}`", bit as char)))
+                    }
                 }
             }
-            Ok(Event::Empty(ref e)) => {
+            Event::Empty(ref e) => {
                 if e.name().as_ref() != b"c" {
                     continue;
                 }
 
-                let mut attrs = e.attributes().flatten();
-
-                let colspan = attrs
-                    .find(|a| a.key.as_ref() == b"colspan")
-                    .map(|v| std::str::from_utf8(v.value.as_ref()).unwrap().parse::<usize>().unwrap());
+                let Some(current) = current_box.as_mut() else {
+                    problems.push(reader.error("cell outside of a box"));
+                    continue;
+                };
 
-                match colspan {
-                    Some(v) => {
+                match attribute(e, b"colspan").map(|v| v.parse::<usize>()) {
+                    Some(Ok(v)) => {
                         for _ in 0..v {
-                            current_box.as_mut().unwrap().bits.push(Bit::Variable);
+                            current.bits.push(Bit::Variable);
                         }
                     }
+                    Some(Err(_)) => problems.push(reader.error("colspan is not a number")),
                     None => {
                         // TODO: at least i fucking think it is
-                        current_box.as_mut().unwrap().bits.push(Bit::Inherited);
+                        current.bits.push(Bit::Inherited);
                     }
                 }
             }
-            Ok(Event::End(ref e)) => {
+            Event::End(ref e) => {
                 if current_c { 
                     if e.name().as_ref() == b"c" {
                         current_c = false;
@@ -199,182 +324,588 @@ fn parse_boxes(reader: &mut Reader<&[u8]>, start_box: Option<BitBox>) -> Vec<Bit
                     break;
                 }
 
-                res.push(current_box.clone().unwrap());
+                match current_box.clone() {
+                    Some(current) => res.push(current),
+                    None => problems.push(reader.error("`box` closed without being opened"))
+                }
             },
-            Ok(Event::Eof) => panic!(),
             _ => {}
         }
     }
 
-    res
+    Ok(res)
+}
+
+/// The `section` attribute of a `<pstext>`, e.g. `Decode` or `Execute`.
+fn pstext_section(e: &BytesStart) -> Option<Box<str>> {
+    attribute(e, b"section").map(Box::from)
+}
+
+/// Reads the ASL up to `</pstext>`. Links (`<a>`, `<anchor>`) only wrap identifiers, their text is kept.
+fn parse_pstext(reader: &mut XmlReader) -> Result<String, ParseError> {
+    parse_text(reader, b"pstext")
+}
+
+/// The text up to the end of `element`, with the text of the elements inside it.
+fn parse_text(reader: &mut XmlReader, element: &[u8]) -> Result<String, ParseError> {
+    let mut text = String::new();
+    let mut buf = Vec::with_capacity(512);
+
+    loop {
+        match reader.next(&mut buf)? {
+            Event::Text(ref e) => text.push_str(&reader.text(e)?),
+            Event::CData(ref e) => {
+                text.push_str(&e.decode().map_err(|e| reader.error(e.to_string()))?)
+            }
+            Event::GeneralRef(ref e) => {
+                if let Ok(Some(c)) = e.resolve_char_ref() {
+                    text.push(c);
+                } else {
+                    let name = e.decode().map_err(|e| reader.error(e.to_string()))?;
+                    match quick_xml::escape::resolve_predefined_entity(&name) {
+                        Some(entity) => text.push_str(entity),
+                        None => return Err(reader.error(format!("unknown entity `&{name};`")))
+                    }
+                }
+            }
+            Event::End(ref e) if e.name().as_ref() == element => break,
+            _ => {}
+        }
+    }
+
+    Ok(text)
+}
+
+/// The alternatives of one `<arch_variant name=".." feature=".." />`.
+fn parse_arch_variant(e: &BytesStart) -> Vec<ir::ArchVariant> {
+    ir::ArchVariant::parse(attribute(e, b"name").as_deref(), attribute(e, b"feature").as_deref())
+}
+
+/// Appends a section of pseudocode, `Postdecode` follows `Decode` for example.
+fn append_pseudocode(target: &mut Option<Box<str>>, text: String) {
+    *target = Some(match target.take() {
+        Some(previous) => format!("{previous}\n{text}").into(),
+        None => text.into()
+    });
 }
 
 #[derive(Debug)]
 struct Encoding {
     name: Option<Box<str>>,
-    docvar: Docvars,
-    boxes: Vec<BitBox>
+    docvar: ir::Docvars,
+    /// Replaces the ones of the iclass when there are any.
+    arch_variants: Vec<ir::ArchVariant>,
+    boxes: Vec<BitBox>,
+    /// Drop the encoding, it can't be trusted.
+    problems: Vec<ParseError>
 }
 
-fn parse_encoding(reader: &mut Reader<&[u8]>, name: Option<Box<str>>) -> Encoding {
+fn parse_encoding(reader: &mut XmlReader, name: Option<Box<str>>, bits: usize) -> Result<Encoding, ParseError> {
     let mut buf = Vec::new();
 
-    let docvar = parse_docvar(reader);
+    let mut problems = vec![];
+    if name.is_none() {
+        problems.push(reader.error("encoding without a name"));
+    }
+    let docvar = parse_docvar(reader, &mut problems)?;
+    let mut arch_variants = vec![];
     let mut boxes = vec![];
 
     loop {
-        buf.clear();
-        let e = reader.read_event_into(&mut buf);
-
-        match e {
-            Ok(Event::Start(ref e)) => {
+        match reader.next(&mut buf)? {
+            Event::Empty(ref e) if e.name().as_ref() == b"arch_variant" => {
+                arch_variants.extend(parse_arch_variant(e));
+            }
+            Event::Start(ref e) => {
                 if e.name().as_ref() != b"box" {
                     continue
                 }
 
-                let mut attrs = e.attributes().flatten();
-
-                let hibit = attrs
-                    .find(|a| a.key.as_ref() == b"hibit")
-                    .map(|v| std::str::from_utf8(v.value.as_ref()).unwrap().parse::<usize>().unwrap());
-
-                let name = attrs
-                    .find(|a| a.key.as_ref() == b"name")
-                    .map(|v| Box::from(String::from_utf8_lossy(v.value.as_ref())));
-
-                boxes.extend(parse_boxes(reader, Some(BitBox {
-                    bits: vec![],
-                    name,
-                    hibit
-                })));
+                let start = parse_box_start(reader, e, bits, &mut problems);
+                boxes.extend(parse_boxes(reader, Some(start), bits, &mut problems)?);
 
                 break;
             }
-            Ok(Event::End(ref e)) if e.name().as_ref() == b"encoding" => break,
+            Event::End(ref e) if e.name().as_ref() == b"encoding" => break,
             _ => {}
         }
     }
 
-    Encoding {
+    Ok(Encoding {
         name,
         docvar,
-        boxes
-    }
+        arch_variants,
+        boxes,
+        problems
+    })
 }
 
 #[derive(Debug)]
 struct IClass {
-    docvar: Docvars,
+    docvar: ir::Docvars,
+    arch_variants: Vec<ir::ArchVariant>,
+    width: ir::Width,
     base_boxes: Vec<BitBox>,
-    encodings: Vec<Encoding>
+    encodings: Vec<Encoding>,
+    decode: Option<Box<str>>,
+    /// Drop every encoding of the iclass.
+    problems: Vec<ParseError>
 }
 
-fn parse_iclass(reader: &mut Reader<&[u8]>) -> IClass {
+fn parse_iclass(reader: &mut XmlReader) -> Result<IClass, ParseError> {
     let mut buf = Vec::new();
 
     let mut boxes = None;
+    let mut width = ir::Width::Word;
     let mut encodings = Vec::new();
-    let docvar = parse_docvar(reader);
+    let mut decode = None;
+    let mut arch_variants = vec![];
+    let mut problems = vec![];
+    let docvar = parse_docvar(reader, &mut problems)?;
 
     loop {
-        buf.clear();
-        let e = reader.read_event_into(&mut buf);
-        match e {
-            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+        match reader.next(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) => {
                 match e.name().as_ref() {
                     b"regdiagram" => {
-                        boxes = Some(parse_boxes(reader, None));
+                        // "32" for A32 and wide T32 ("16x2"), "16" for narrow T32.
+                        if attribute(e, b"form").as_deref() == Some("16") {
+                            width = ir::Width::Halfword;
+                        }
+
+                        boxes = Some(parse_boxes(reader, None, width.bits(), &mut problems)?);
                     }
                     b"encoding" => {
-                        let mut attrs = e.attributes().flatten();
-
-                        let name = attrs
-                            .find(|a| a.key.as_ref() == b"name")
-                            .map(|v| Box::from(String::from_utf8_lossy(v.value.as_ref())));
-
-                        encodings.push(parse_encoding(reader, name));
+                        let name = attribute(e, b"name").map(Box::from);
+                        encodings.push(parse_encoding(reader, name, width.bits())?);
+                    }
+                    // The iclass lists its variants before the diagram, encodings read their own.
+                    b"arch_variant" if boxes.is_none() => {
+                        arch_variants.extend(parse_arch_variant(e));
+                    }
+                    b"pstext" => {
+                        let section = pstext_section(e);
+                        let text = parse_pstext(reader)?;
+                        if matches!(section.as_deref(), Some("Decode" | "Postdecode")) {
+                            append_pseudocode(&mut decode, text);
+                        }
                     }
                     _ => {}
                 }
             },
-            Ok(Event::End(ref e)) if e.name().as_ref() == b"iclass" => break,
-            Ok(Event::Eof) => panic!(),
+            Event::End(ref e) if e.name().as_ref() == b"iclass" => break,
             _ => {}
         }
     }
 
-    IClass {
-        docvar,
-        base_boxes: boxes.unwrap(),
-        encodings
+    if boxes.is_none() {
+        problems.push(reader.error("iclass without a regdiagram"));
     }
+
+    Ok(IClass {
+        docvar,
+        arch_variants,
+        width,
+        base_boxes: boxes.unwrap_or_default(),
+        encodings,
+        decode,
+        problems
+    })
 }
 
 #[derive(Debug)]
 struct Specification {
-    global_docvar: Docvars,
-    iclasses: Vec<IClass>
+    global_docvar: ir::Docvars,
+    /// Aliases only restate encodings of another section with a preferred disassembly.
+    alias: bool,
+    iclasses: Vec<IClass>,
+    execute: Option<Box<str>>,
+    constrained: Vec<ConstrainedCase>
+}
+
+/// A `<cu_case>`, the behaviours an UNPREDICTABLE case of the decode pseudocode is constrained to.
+#[derive(Debug)]
+struct ConstrainedCase {
+    /// The encoding it applies to, every encoding of the section when `None`.
+    encoding: Option<Box<str>>,
+    /// The condition of the case, as written in the pseudocode.
+    cause: Box<str>,
+    /// `Constraint_*` names, see `constraint_text_mappings.xml`.
+    constraints: Vec<Box<str>>
+}
+
+fn parse_constrained_unpredictables(reader: &mut XmlReader, encoding: Option<Box<str>>) -> Result<Vec<ConstrainedCase>, ParseError> {
+    let mut buf = Vec::new();
+    let mut cases = vec![];
+    loop {
+        match reader.next(&mut buf)? {
+            Event::Start(ref e) if e.name().as_ref() == b"cu_case" => {
+                cases.push(ConstrainedCase { encoding: encoding.clone(), cause: Box::from(""), constraints: vec![] });
+            }
+            Event::Start(ref e) if e.name().as_ref() == b"pstext" => {
+                let cause = parse_pstext(reader)?;
+                let case = cases.last_mut().ok_or_else(|| reader.error("`pstext` outside of a `cu_case`"))?;
+                case.cause = cause.into();
+            }
+            Event::Start(ref e) | Event::Empty(ref e) if e.name().as_ref() == b"cu_type" => {
+                let constraint = attribute(e, b"constraint");
+                let case = cases.last_mut().ok_or_else(|| reader.error("`cu_type` outside of a `cu_case`"))?;
+                case.constraints.extend(constraint.map(Box::from));
+            }
+            Event::End(ref e) if e.name().as_ref() == b"constrained_unpredictables" => break,
+            _ => {}
+        }
+    }
+    Ok(cases)
 }
 
-fn parse_spec(reader: &mut Reader<&[u8]>) -> Specification {
+fn parse_spec(reader: &mut XmlReader) -> Result<Specification, ParseError> {
     let mut event_buf = Vec::new();
 
     let mut global_docvar = None;
+    let mut alias = false;
     let mut iclasses = Vec::new();
+    let mut execute = None;
+    let mut constrained = Vec::new();
 
     loop {
-        event_buf.clear();
-        match reader.read_event_into(&mut event_buf) {
-            Ok(Event::Start(ref e)) => {
+        match reader.event(&mut event_buf)? {
+            Event::Start(ref e) => {
                 match e.name().as_ref() {
                 b"instructionsection" => {
-                    global_docvar = Some(parse_docvar(reader));
+                    alias = attribute(e, b"type").as_deref() == Some("alias");
+                    // A broken section docvar would break every encoding, it is as bad as a broken file.
+                    let mut problems = vec![];
+                    global_docvar = Some(parse_docvar(reader, &mut problems)?);
+                    if let Some(problem) = problems.into_iter().next() {
+                        return Err(problem);
+                    }
                 },
                 b"iclass" => {
-                    iclasses.push(parse_iclass(reader));
+                    iclasses.push(parse_iclass(reader)?);
+                },
+                b"constrained_unpredictables" => {
+                    let encoding = attribute(e, b"encoding").map(Box::from);
+                    constrained.extend(parse_constrained_unpredictables(reader, encoding)?);
+                },
+                // The iclasses read their own decode, what is left belongs to the section.
+                b"pstext" => {
+                    let section = pstext_section(e);
+                    let text = parse_pstext(reader)?;
+                    if section.as_deref() == Some("Execute") {
+                        append_pseudocode(&mut execute, text);
+                    }
                 },
                 _ => {}
                 }
             }
-            Ok(Event::Eof) => break,
+            Event::Eof => break,
             _ => (),
         }
     }
 
-    Specification {
-        global_docvar: global_docvar.unwrap(),
-        iclasses
+    Ok(Specification {
+        global_docvar: global_docvar.ok_or_else(|| reader.error("no `instructionsection`"))?,
+        alias,
+        iclasses,
+        execute,
+        constrained
+    })
+}
+
+/// Named boxes as bit ranges of the (left aligned) pattern.
+fn box_regions(boxes: &[BitBox], shift: usize) -> impl Iterator<Item = ir::BitRegion> + '_ {
+    boxes.iter().filter_map(move |b| {
+        let hibit = b.hibit? + shift;
+        Some(ir::BitRegion {
+            label: b.name.clone()?,
+            range: (hibit + 1 - b.width)..(hibit + 1)
+        })
+    })
+}
+
+/// The `!= ` cell of a box as a constraint on the bits it covers, which need not be the whole box.
+fn box_constraint(b: &BitBox, shift: usize) -> Option<ir::FieldConstraint> {
+    let hibit = b.hibit? + shift;
+    let compared = b.bits.iter().enumerate().filter_map(|(k, bit)| match bit {
+        Bit::NotOne => Some((hibit.checked_sub(k)?, 1)),
+        Bit::NotZero => Some((hibit.checked_sub(k)?, 0)),
+        _ => None
+    }).collect::<Vec<_>>();
+
+    let (&(high, _), &(low, _)) = (compared.first()?, compared.last()?);
+    let value = compared.iter().fold(0, |value, &(bit, one)| value | one << (bit - low));
+    Some(ir::FieldConstraint { range: low..=high, op: ir::FieldOp::Ne, value })
+}
+
+/// Integer variables of the decode pseudocode holding a field, `t = UInt(Rt);`.
+type Bindings = std::collections::HashMap<Box<str>, Vec<std::ops::Range<usize>>>;
+
+/// Bits of the fields `expr` names (`Rn`, `P:W`, `Rt<0>`), highest first.
+fn field_parts(expr: &Expr, regions: &[ir::BitRegion]) -> Option<Vec<std::ops::Range<usize>>> {
+    match expr {
+        Expr::Var(name) => {
+            let parts = regions.iter().filter(|r| r.label == *name).map(|r| r.range.clone()).collect::<Vec<_>>();
+            (!parts.is_empty()).then_some(parts)
+        }
+        Expr::Binary(BinOp::Concat, high, low) => {
+            let mut parts = field_parts(high, regions)?;
+            parts.extend(field_parts(low, regions)?);
+            Some(parts)
+        }
+        Expr::Slice(field, slices) => {
+            let bits = field_parts(field, regions)?.into_iter().flat_map(|p| p.rev()).collect::<Vec<_>>();
+            let mut parts = vec![];
+            for slice in slices {
+                let (hi, lo) = match slice {
+                    Slice::Single(Expr::Int(bit)) => (*bit, *bit),
+                    Slice::Range(Expr::Int(hi), Expr::Int(lo)) => (*hi, *lo),
+                    _ => return None
+                };
+                // `bits` is highest first, slices count from the lowest bit.
+                let (hi, lo) = (usize::try_from(hi).ok()?, usize::try_from(lo).ok()?);
+                if lo > hi || hi >= bits.len() {
+                    return None;
+                }
+                parts.extend(bits[bits.len() - 1 - hi..bits.len() - lo].iter().map(|&bit| bit..bit + 1));
+            }
+            Some(parts)
+        }
+        _ => None
+    }
+}
+
+/// Bits of the field an integer expression is the value of, `UInt(Rt)` or a variable bound to one.
+fn uint_parts(expr: &Expr, bindings: &Bindings, regions: &[ir::BitRegion]) -> Option<Vec<std::ops::Range<usize>>> {
+    match expr {
+        Expr::Var(name) => bindings.get(name).cloned(),
+        Expr::Call(name, args) if &**name == "UInt" && args.len() == 1 => field_parts(&args[0], regions),
+        _ => None
+    }
+}
+
+/// `parts == literal` as a mask and value, `x` matches either bit.
+fn field_match(parts: &[std::ops::Range<usize>], literal: &Expr) -> Option<(u32, u32)> {
+    let Expr::Bits(literal) = literal else { return None };
+    if literal.len() != parts.iter().map(|p| p.len()).sum::<usize>() {
+        return None;
+    }
+
+    let positions = parts.iter().flat_map(|p| p.clone().rev());
+    positions.zip(literal.chars()).try_fold((0, 0), |(mask, value), (i, bit)| match bit {
+        '1' => Some((mask | 1 << i, value | 1 << i)),
+        '0' => Some((mask | 1 << i, value)),
+        'x' => Some((mask, value)),
+        _ => None
+    })
+}
+
+/// `parts` as an unsigned integer compared to `literal`, `!=` holds wherever any bit differs.
+fn int_matches(parts: &[std::ops::Range<usize>], literal: &Expr, equal: bool) -> Option<Vec<(u32, u32)>> {
+    let Expr::Int(literal) = *literal else { return None };
+    let width = parts.iter().map(|p| p.len()).sum::<usize>();
+    if !(0..1 << width).contains(&literal) {
+        // Out of range, `==` never holds and `!=` always does.
+        return Some(if equal { vec![] } else { vec![(0, 0)] });
+    }
+
+    let bits = (0..width).rev().map(|i| if literal >> i & 1 == 1 { '1' } else { '0' }).collect::<String>();
+    let (mask, value) = field_match(parts, &Expr::Bits(bits.into()))?;
+    match equal {
+        true => Some(vec![(mask, value)]),
+        false => Some((0..32).filter(|i| mask >> i & 1 == 1).map(|i| (1 << i, !value & 1 << i)).collect())
+    }
+}
+
+/// Alternatives of `a` and `b` holding at once, those fixing a bit both ways can't.
+fn both(a: &[(u32, u32)], b: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let both = a.iter().flat_map(|(ma, va)| b.iter().map(move |(mb, vb)| (ma, va, mb, vb)));
+    both.filter(|(ma, va, mb, vb)| *ma & *mb & (*va ^ *vb) == 0).map(|(ma, va, mb, vb)| (ma | mb, va | vb)).collect()
+}
+
+/// A condition on fields only as the mask and value pairs it holds for, any of them.
+fn condition_matches(condition: &Expr, bindings: &Bindings, regions: &[ir::BitRegion]) -> Option<Vec<(u32, u32)>> {
+    match condition {
+        Expr::Binary(op @ (BinOp::Eq | BinOp::Ne), lhs, literal) if matches!(**literal, Expr::Int(_)) => {
+            int_matches(&uint_parts(lhs, bindings, regions)?, literal, *op == BinOp::Eq)
+        }
+        Expr::Binary(BinOp::Eq, field, literal) => Some(vec![field_match(&field_parts(field, regions)?, literal)?]),
+        Expr::In(field, literals) => {
+            let parts = field_parts(field, regions)?;
+            literals.iter().map(|l| field_match(&parts, l)).collect()
+        }
+        Expr::Binary(BinOp::BoolAnd, a, b) => {
+            Some(both(&condition_matches(a, bindings, regions)?, &condition_matches(b, bindings, regions)?))
+        }
+        Expr::Binary(BinOp::BoolOr, a, b) => {
+            let mut matches = condition_matches(a, bindings, regions)?;
+            matches.extend(condition_matches(b, bindings, regions)?);
+            Some(matches)
+        }
+        _ => None
+    }
+}
+
+/// Words `condition` holds for as far as the fields tell, the sides of a `||` the word can't
+/// decide are left out, e.g. only `n == 15` of `n == 15 || n == t`.
+fn sufficient_matches(condition: &Expr, bindings: &Bindings, regions: &[ir::BitRegion]) -> Vec<(u32, u32)> {
+    match condition {
+        Expr::Binary(BinOp::BoolAnd, a, b) => {
+            both(&sufficient_matches(a, bindings, regions), &sufficient_matches(b, bindings, regions))
+        }
+        Expr::Binary(BinOp::BoolOr, a, b) => {
+            let mut matches = sufficient_matches(a, bindings, regions);
+            matches.extend(sufficient_matches(b, bindings, regions));
+            matches
+        }
+        _ => condition_matches(condition, bindings, regions).unwrap_or_default()
+    }
+}
+
+/// Variables an assignment to `target` writes, `t` of `t = ..`, `(t, -) = ..` or `t<3:0> = ..`.
+fn targets<'e>(target: &'e Expr, names: &mut Vec<&'e str>) {
+    match target {
+        Expr::Var(name) => names.push(name),
+        Expr::Tuple(items) => items.iter().for_each(|item| targets(item, names)),
+        Expr::Slice(base, _) | Expr::Index(base, _) | Expr::Field(base, _) => targets(base, names),
+        _ => {}
+    }
+}
+
+/// Variables `statements` assign to, in nested blocks as well.
+fn assigned<'s>(statements: &'s [Stmt], names: &mut Vec<&'s str>) {
+    for statement in statements {
+        match statement {
+            Stmt::Assign(target, _) => targets(target, names),
+            Stmt::Decl { vars, .. } => names.extend(vars.iter().map(|(name, _)| &**name)),
+            Stmt::If(arms, otherwise) => {
+                arms.iter().for_each(|(_, body)| assigned(body, names));
+                assigned(otherwise.as_deref().unwrap_or_default(), names);
+            }
+            Stmt::Case(_, arms, otherwise) => {
+                arms.iter().for_each(|(_, body)| assigned(body, names));
+                assigned(otherwise.as_deref().unwrap_or_default(), names);
+            }
+            Stmt::For { var, body, .. } => {
+                names.push(var);
+                assigned(body, names);
+            }
+            Stmt::While(_, body) | Stmt::Repeat(body, _) => assigned(body, names),
+            _ => {}
+        }
+    }
+}
+
+/// Keeps `bindings` up to date with an assignment of the decode pseudocode.
+fn bind(statement: &Stmt, bindings: &mut Bindings, regions: &[ir::BitRegion]) {
+    let assignments = match statement {
+        Stmt::Assign(Expr::Var(name), value) => vec![(name, Some(value))],
+        Stmt::Decl { vars, .. } => vars.iter().map(|(name, value)| (name, value.as_ref())).collect(),
+        // Whether a nested assignment runs isn't known, the variable may no longer hold the field.
+        _ => {
+            let mut names = vec![];
+            assigned(std::slice::from_ref(statement), &mut names);
+            for name in names {
+                bindings.remove(name);
+            }
+            return;
+        }
+    };
+    for (name, value) in assignments {
+        match value.and_then(|value| uint_parts(value, bindings, regions)) {
+            Some(parts) => bindings.insert(name.clone(), parts),
+            None => bindings.remove(name)
+        };
+    }
+}
+
+/// The single arm `if <cond> then <body>;` statements of the decode pseudocode, with the
+/// bindings holding there.
+fn guarded<'d>(decode: &'d [Stmt], regions: &[ir::BitRegion]) -> Vec<(&'d Expr, &'d Stmt, Bindings)> {
+    let mut bindings = Bindings::new();
+    let mut guarded = vec![];
+    for statement in decode {
+        bind(statement, &mut bindings, regions);
+        let Stmt::If(arms, None) = statement else { continue };
+        let [(condition, body)] = arms.as_slice() else { continue };
+        let [body] = body.as_slice() else { continue };
+        guarded.push((condition, body, bindings.clone()));
+    }
+    guarded
+}
+
+/// The `if <fields> then SEE "..";` of the decode pseudocode. Conditions on anything but the
+/// fields of the encoding can't be decided from the word and are left out.
+fn see_redirects(decode: &[Stmt], regions: &[ir::BitRegion]) -> Vec<ir::See> {
+    let mut redirects = vec![];
+    for (condition, body, bindings) in guarded(decode, regions) {
+        let Stmt::See(target) = body else { continue };
+        for (mask, value) in condition_matches(condition, &bindings, regions).unwrap_or_default() {
+            redirects.push(ir::See { mask, value, target: target.clone() });
+        }
+    }
+    redirects
+}
+
+/// The `if <cond> then UNPREDICTABLE;` of the decode pseudocode, as far as the fields decide
+/// `cond`. A case whose condition `constrained` lists as a `cu_cause` gets its constraints.
+fn unpredictable_cases(
+    decode: &[Stmt],
+    regions: &[ir::BitRegion],
+    constrained: &[(Expr, Box<[ir::Constraint]>)]
+) -> Vec<ir::Unpredictable> {
+    let mut cases = vec![];
+    for (condition, body, bindings) in guarded(decode, regions) {
+        let Stmt::Unpredictable = body else { continue };
+        let constraints = constrained.iter().find(|(cause, _)| cause == condition).map(|(_, c)| c.clone());
+        for (mask, value) in sufficient_matches(condition, &bindings, regions) {
+            let constraints = constraints.clone().unwrap_or_default();
+            cases.push(ir::Unpredictable { mask, value, constraints });
+        }
     }
+    cases
 }
 
-fn iclass_into_ir(_global_docvar: &Docvars, iclass: &IClass) -> Vec<ir::Instruction> {
+fn iclass_into_ir(
+    spec: &Specification,
+    iclass: &IClass,
+    constraints: &Constraints,
+    isa: ir::Isa,
+    class: ir::InstrClass,
+    warnings: &mut Vec<Warning>
+) -> Vec<ir::Instruction> {
+    // Boxes with problems may not even fit the pattern, leave them before it is built.
+    let mut encodings = vec![];
+    for (index, e) in iclass.encodings.iter().enumerate() {
+        match iclass.problems.first().or(e.problems.first()) {
+            Some(problem) => {
+                let dropped = e.name.clone().unwrap_or_else(|| format!("encoding #{index}").into());
+                warn(warnings, Warning { dropped, error: problem.clone() });
+            }
+            None => encodings.push(e)
+        }
+    }
+    if encodings.is_empty() {
+        return vec![];
+    }
+
     // TODO: will be way better to make this array safer.
     let mut base_bit_pattern = [None; 32];
-    let mut base_filter_ranges = Vec::new();
+    let mut base_field_constraints = Vec::new();
+
+    // Narrow encodings number their bits from 15, move them to the top of the pattern.
+    let shift = iclass.width.shift();
 
     for b in &iclass.base_boxes {
-        let mut cndx = b.hibit.unwrap();
-        let len = b.bits.len();
-        let mut added = false;
+        let hibit = b.hibit.unwrap() + shift;
+        let mut cndx = hibit;
+        base_field_constraints.extend(box_constraint(b, shift));
 
         for bit in &b.bits {
             let mapped_bit = match bit {
                 Bit::One => Some(ir::Bit::One),
                 Bit::Zero => Some(ir::Bit::Zero),
-                Bit::NotOne => {
-                    if !added {
-                        base_filter_ranges.push((b.hibit.unwrap()+1)-len..=b.hibit.unwrap());
-                        added = true
-                    }
-                    Some(ir::Bit::NotOne)
-                }
-                Bit::NotZero => {
-                    if !added {
-                        base_filter_ranges.push((b.hibit.unwrap()+1)-len..=b.hibit.unwrap());
-                        added = true
-                    }
-                    Some(ir::Bit::NotZero)
-                }
+                Bit::NotOne | Bit::NotZero => None,
                 Bit::Variable | Bit::Inherited => {
                     if cndx != 0 { cndx -= 1; }
                     continue
@@ -390,36 +921,26 @@ fn iclass_into_ir(_global_docvar: &Docvars, iclass: &IClass) -> Vec<ir::Instruct
         }
     }
 
-    iclass
-        .encodings
-        .iter()
+    let docvars = spec.global_docvar.merged(&iclass.docvar);
+    // Pseudocode the ASL parser can't read has no redirects, overlaps it would settle are reported later.
+    let decode = iclass.decode.as_deref().and_then(|d| asl::parse_statements(d).ok()).unwrap_or_default();
+
+    encodings
+        .into_iter()
         .map(|e| {
             let mut bit_pattern = base_bit_pattern.clone();
-            let mut filter_ranges = base_filter_ranges.clone();
+            let mut field_constraints = base_field_constraints.clone();
             
             for b in &e.boxes {
-                let mut cndx = b.hibit.unwrap();
-                let len = b.bits.len();
-                let mut added = false;
+                let hibit = b.hibit.unwrap() + shift;
+                let mut cndx = hibit;
+                field_constraints.extend(box_constraint(b, shift));
                 
                 for bit in &b.bits {
                     let mapped_bit = match bit {
                         Bit::One => Some(ir::Bit::One),
                         Bit::Zero => Some(ir::Bit::Zero),
-                        Bit::NotOne => {
-                            if !added {
-                                filter_ranges.push((b.hibit.unwrap()+1)-len..=b.hibit.unwrap());
-                                added = true;
-                            }
-                            Some(ir::Bit::NotOne)
-                        }
-                        Bit::NotZero => {
-                            if !added {
-                                filter_ranges.push((b.hibit.unwrap()+1)-len..=b.hibit.unwrap());
-                                added = true;
-                            }
-                            Some(ir::Bit::NotZero)
-                        }
+                        Bit::NotOne | Bit::NotZero => None,
                         Bit::Variable | Bit::Inherited => {
                             if cndx != 0 { cndx -= 1; }
                             continue
@@ -431,60 +952,177 @@ fn iclass_into_ir(_global_docvar: &Docvars, iclass: &IClass) -> Vec<ir::Instruct
                     if cndx != 0 { cndx -= 1; }
                 }
             }
-            /*
-
-            if e.docvar.mnemonic == Some(Box::from("PLDW")) {
-                println!("start {}", filter_ranges.len());
-                println!("{:?}", iclass.base_boxes);
-                println!("{:?}", e.boxes);
-            }*/
-
-            //if filter_ranges.len() == 0 {
-            if true {
-                return Some(ir::Instruction {
-                filters: Box::from(filter_ranges),
-                pattern: bit_pattern,
-                regions: Box::new([]),
-                name: e.name.clone().unwrap()
-            }); } else {
-                    return None;
+            // An iclass `!= 00` is often settled by its encodings (VFP `size` is 01/10/11).
+            let fixed = bit_pattern.iter().enumerate().fold((0, 0), |(mask, value), (i, bit)| match bit {
+                Some(ir::Bit::One) => (mask | 1 << i, value | 1 << i),
+                Some(ir::Bit::Zero) => (mask | 1 << i, value),
+                None => (mask, value)
+            });
+            field_constraints.retain(|constraint| !constraint.implied_by(fixed.0, fixed.1));
+
+            // Encoding boxes restate iclass fields, keep each once. Fields the encoding fixes stay,
+            // the pseudocode still reads them.
+            let mut regions = Vec::<ir::BitRegion>::new();
+            for region in box_regions(&iclass.base_boxes, shift).chain(box_regions(&e.boxes, shift)) {
+                if !regions.contains(&region) {
+                    regions.push(region);
+                }
+            }
 
+            let see = see_redirects(&decode, &regions);
+            // Causes are matched to the pseudocode as ASL, constraints the mapping doesn't know are dropped.
+            let name = e.name.as_deref().unwrap_or_default();
+            let constrained = spec.constrained
+                .iter()
+                .filter(|case| case.encoding.as_deref().is_none_or(|encoding| encoding == name))
+                .filter_map(|case| {
+                    let cause = asl::parse_expression(&case.cause).ok()?;
+                    let known = case.constraints.iter().filter_map(|id| {
+                        constraints.get(id).map(|text| ir::Constraint { id: id.clone(), text: text.clone() })
+                    });
+                    Some((cause, known.collect()))
+                })
+                .collect::<Vec<_>>();
+            let unpredictable = unpredictable_cases(&decode, &regions, &constrained);
+
+            ir::Instruction {
+                field_constraints: Box::from(field_constraints),
+                pattern: bit_pattern,
+                regions: Box::from(regions),
+                name: e.name.clone().unwrap_or_default(),
+                isa,
+                width: iclass.width,
+                class,
+                docvars: docvars.merged(&e.docvar),
+                arch_variants: if e.arch_variants.is_empty() { &iclass.arch_variants } else { &e.arch_variants }
+                    .clone()
+                    .into(),
+                see: see.into(),
+                unpredictable: unpredictable.into(),
+                index: None,
+                pseudocode: ir::Pseudocode {
+                    decode: iclass.decode.clone(),
+                    execute: spec.execute.as_deref().map(Box::from)
+                }
             }
         })
-    .filter_map(|x| x)
     .collect::<Vec<ir::Instruction>>()
 }
 
 // Maybe spec view.. just to inherit easily
-fn specs_into_ir(specs: Vec<Specification>) -> Vec<ir::Instruction> {
+fn specs_into_ir(specs: Vec<Specification>, constraints: &Constraints, warnings: &mut Vec<Warning>) -> Vec<ir::Instruction> {
     // This basically is classification of what we need, filtering.
 
     let mut instructions = vec![];
 
     for s in specs {
-        for iclass in s.iclasses {
+        if s.alias {
+            continue;
+        }
+
+        for iclass in &s.iclasses {
             // TODO: handle empty isa and instr_class better
-            if iclass.docvar.isa == Some(Box::from("A32")) {
-                if iclass.docvar.instr_class == Some(Box::from("general")) {
-                    // We convert all encodings of this iclass into the IR.
-                    instructions.extend(iclass_into_ir(&s.global_docvar, &iclass));
+            let Some(isa) = iclass.docvar.get("isa").and_then(ir::Isa::from_docvar) else {
+                continue;
+            };
+
+            // Classes we don't model (sve, sme, ...) are left out, the rest is filtered by the emitter.
+            let Some(class) = iclass.docvar.get("instr-class").and_then(ir::InstrClass::from_docvar) else {
+                continue;
+            };
+
+            // We convert all encodings of this iclass into the IR.
+            instructions.extend(iclass_into_ir(&s, iclass, constraints, isa, class, warnings));
+        }
+    }
+
+    instructions
+}
+
+/// The encodings as the index lists them, every row of its tables names one with `encname`.
+fn parse_encoding_index(reader: &mut XmlReader) -> Result<Vec<Box<str>>, ParseError> {
+    let mut buf = Vec::new();
+    let mut names = vec![];
+    loop {
+        match reader.event(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.name().as_ref() == b"tr" => {
+                names.extend(attribute(e, b"encname").map(Box::from));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+/// What every `Constraint_*` means, by name.
+type Constraints = std::collections::HashMap<Box<str>, Box<str>>;
+
+/// `constraint_text_mappings.xml`. A mapping names its constraint with a `constraint` attribute
+/// or the first text that starts with `Constraint_`, the rest of its text describes it.
+fn parse_constraint_mappings(reader: &mut XmlReader) -> Result<Constraints, ParseError> {
+    let mut buf = Vec::new();
+    let mut constraints = Constraints::new();
+    let mut mapping: Option<(Option<String>, Vec<String>)> = None;
+    loop {
+        match reader.event(&mut buf)? {
+            Event::Start(ref e) if e.name().as_ref() == b"constraint_text_mapping" => {
+                mapping = Some((attribute(e, b"constraint"), vec![]));
+            }
+            Event::End(ref e) if e.name().as_ref() == b"constraint_text_mapping" => {
+                if let Some((Some(id), text)) = mapping.take() {
+                    constraints.insert(id.into(), text.join(" ").into());
+                }
+            }
+            Event::Start(ref e) if mapping.is_some() => {
+                let element = e.name().as_ref().to_vec();
+                let text = parse_text(reader, &element)?;
+                let (id, description) = mapping.as_mut().unwrap();
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                match text.starts_with("Constraint_") && id.is_none() {
+                    true => *id = Some(text),
+                    false if !text.is_empty() => description.push(text),
+                    false => {}
+                }
+            }
+            Event::Text(ref e) if mapping.is_some() => {
+                let text = reader.text(e)?;
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    mapping.as_mut().unwrap().1.push(text);
                 }
             }
+            Event::Eof => break,
+            _ => {}
         }
     }
+    Ok(constraints)
+}
 
-    instructions
+fn empty_release(path: PathBuf) -> fetcher::arm::FetchError {
+    let error = std::io::Error::new(std::io::ErrorKind::InvalidData, "the specification has no files");
+    fetcher::arm::FetchError::Io(path, error)
 }
 
-pub fn parse_into_ir(mut iss: fetcher::arm::InstructionSpecificationStream) -> Vec<ir::Instruction> {
-    let mut iterator = iss.make_iter().unwrap();
+/// Parses every instruction section of the release. Files and encodings that don't parse are
+/// left out and reported in `warnings`.
+pub fn parse_into_ir(
+    source: &fetcher::arm::SpecSource,
+    warnings: &mut Vec<Warning>
+) -> Result<Vec<ir::Instruction>, fetcher::arm::FetchError> {
+    let origin = || source.local_path().map(std::path::Path::to_path_buf).unwrap_or_default();
+
+    let mut iss = source.open()?;
+    let mut iterator = iss.make_iter().ok_or_else(|| empty_release(origin()))?;
 
     let mut file_contents = Vec::with_capacity(64 * 4096);
     let mut specifications = Vec::with_capacity(2200);
+    let mut index = std::collections::HashMap::new();
+    let mut constraints = Constraints::new();
 
+    let encoding_indexes = ["a32_encindex.xml", "t32_encindex.xml", "encodingindex.xml"];
     let ignore = vec![
         "shared_pseudocode.xml",
-        "constraint_text_mappings.xml",
         "encodingindex.xml",
         "fpsimdindex.xml",
         "index.xml",
@@ -496,8 +1134,38 @@ pub fn parse_into_ir(mut iss: fetcher::arm::InstructionSpecificationStream) -> V
         "t32_encindex.xml",
     ];
 
-    while let Some(mut entry) = iterator.next() {
-        let path = entry.header().path().unwrap();
+    while let Some(entry) = iterator.next() {
+        let mut entry = entry.map_err(|e| fetcher::arm::FetchError::Io(origin(), e))?;
+        let path = entry.path().to_path_buf();
+        if encoding_indexes.iter().any(|i| path.ends_with(i)) {
+            file_contents.clear();
+            entry.read_to_end(&mut file_contents).map_err(|e| fetcher::arm::FetchError::Io(path.clone(), e))?;
+
+            let mut reader = XmlReader::new(file_contents.as_slice(), &path);
+            match parse_encoding_index(&mut reader) {
+                Ok(names) => {
+                    for name in names {
+                        let position = index.len() as u32;
+                        index.entry(name).or_insert(position);
+                    }
+                }
+                // Only overlaps need the index, it failing to parse is left for them to report.
+                Err(error) => warn(warnings, Warning { dropped: "the encoding index".into(), error })
+            }
+            continue;
+        }
+        if path.ends_with("constraint_text_mappings.xml") {
+            file_contents.clear();
+            entry.read_to_end(&mut file_contents).map_err(|e| fetcher::arm::FetchError::Io(path.clone(), e))?;
+
+            let mut reader = XmlReader::new(file_contents.as_slice(), &path);
+            match parse_constraint_mappings(&mut reader) {
+                Ok(mappings) => constraints.extend(mappings),
+                // Without it every UNPREDICTABLE case is left unconstrained.
+                Err(error) => warn(warnings, Warning { dropped: "the constraint text mappings".into(), error })
+            }
+            continue;
+        }
         if ignore.iter().any(|i| path.ends_with(i)) {
             continue;
         }
@@ -505,26 +1173,362 @@ pub fn parse_into_ir(mut iss: fetcher::arm::InstructionSpecificationStream) -> V
 
         file_contents.clear();
 
-        entry.read_to_end(&mut file_contents).unwrap();
+        entry.read_to_end(&mut file_contents).map_err(|e| fetcher::arm::FetchError::Io(path.clone(), e))?;
+
+        let mut reader = XmlReader::new(file_contents.as_slice(), &path);
+        match parse_spec(&mut reader) {
+            Ok(specification) => specifications.push(specification),
+            Err(error) => {
+                let file = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+                warn(warnings, Warning { dropped: file.into(), error })
+            }
+        }
+    }
+
+    let mut instructions = specs_into_ir(specifications, &constraints, warnings);
+    for inst in &mut instructions {
+        inst.index = index.get(&inst.name).copied();
+    }
+    Ok(instructions)
+}
+
+/// The pseudocode blocks of `shared_pseudocode.xml`, the library the instructions call into.
+/// A malformed file keeps the blocks before the error, it is reported in `warnings`.
+pub fn parse_shared_pseudocode(
+    source: &fetcher::arm::SpecSource,
+    warnings: &mut Vec<Warning>
+) -> Result<Vec<Box<str>>, fetcher::arm::FetchError> {
+    let origin = || source.local_path().map(std::path::Path::to_path_buf).unwrap_or_default();
+
+    let mut iss = source.open()?;
+    let mut iterator = iss.make_iter().ok_or_else(|| empty_release(origin()))?;
+    let mut file_contents = Vec::with_capacity(64 * 4096);
+
+    while let Some(entry) = iterator.next() {
+        let mut entry = entry.map_err(|e| fetcher::arm::FetchError::Io(origin(), e))?;
+        let path = entry.path().to_path_buf();
+        if !path.ends_with("shared_pseudocode.xml") {
+            continue;
+        }
+
+        entry.read_to_end(&mut file_contents).map_err(|e| fetcher::arm::FetchError::Io(path.clone(), e))?;
 
-        let mut reader = Reader::from_reader(file_contents.as_slice());
-        specifications.push(parse_spec(&mut reader));
+        let mut reader = XmlReader::new(file_contents.as_slice(), &path);
+        let mut buf = Vec::new();
+        let mut blocks = vec![];
+        let result = loop {
+            match reader.event(&mut buf) {
+                Ok(Event::Start(ref e)) if e.name().as_ref() == b"pstext" => match parse_pstext(&mut reader) {
+                    Ok(text) => blocks.push(Box::from(text)),
+                    Err(error) => break Err(error)
+                },
+                Ok(Event::Eof) => break Ok(()),
+                Ok(_) => {}
+                Err(error) => break Err(error)
+            }
+        };
+        if let Err(error) = result {
+            warn(warnings, Warning { dropped: format!("the rest of {}", path.display()).into(), error });
+        }
+        return Ok(blocks);
     }
 
-    specs_into_ir(specifications)
+    Ok(vec![])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse(xml: &str) -> (Vec<ir::Instruction>, Vec<Warning>) {
+        let mut warnings = vec![];
+        let mut reader = XmlReader::new(xml.as_bytes(), Path::new("test.xml"));
+        let specification = parse_spec(&mut reader).unwrap();
+        (specs_into_ir(vec![specification], &Constraints::new(), &mut warnings), warnings)
+    }
+
     #[test]
     fn test_parser() {
         return;
-        const ARM_SPEC_32: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-AArch32-ISA/ISA_AArch32/ISA_AArch32_xml_A_profile-2025-12.tar.gz";
         //const ARM_SPEC: &str = "https://developer.arm.com/-/cdn-downloads/permalink/Exploration-Tools-A64-ISA/ISA_A64/ISA_A64_xml_A_profile-2025-06.tar.gz";
 
-        let stream = crate::fetcher::arm::InstructionSpecificationStream::connect(ARM_SPEC_32).unwrap();
-        parse_into_ir(stream);
+        parse_into_ir(&crate::fetcher::arm::SpecSource::aarch32().unwrap(), &mut vec![]).unwrap();
+    }
+
+    const T32_SPEC: &str = r#"<instructionsection id="ADC" title="ADC" type="instruction">
+  <docvars><docvar key="isa" value="T32" /></docvars>
+  <classes>
+    <iclass name="T1" oneof="2" id="t1" no_encodings="1" isa="T32">
+      <docvars><docvar key="instr-class" value="general" /><docvar key="isa" value="T32" /></docvars>
+      <regdiagram form="16" psname="ADC_r_T1_A.txt" tworows="1">
+        <box hibit="15" width="6" settings="6"><c>0</c><c>1</c><c>0</c><c>0</c><c>0</c><c>0</c></box>
+        <box hibit="9" width="4" name="op" settings="4"><c>0</c><c>1</c><c>0</c><c>1</c></box>
+        <box hibit="5" width="3" name="Rm" usename="1"><c colspan="3"></c></box>
+        <box hibit="2" width="3" name="Rdn" usename="1"><c colspan="3"></c></box>
+      </regdiagram>
+      <encoding name="ADC_r_T1" oneofinclass="1" oneof="2" label="T1">
+        <docvars><docvar key="mnemonic" value="ADC" /></docvars>
+        <asmtemplate><text>ADC</text></asmtemplate>
+      </encoding>
+      <ps_section howmany="1">
+        <ps name="aarch32/instrs/ADC_r/ADC_r_T1_A.txt" mylink="aarch32.instrs.ADC_r.ADC_r_T1_A.txt" enclabels="" sections="1" secttype="noheading">
+          <pstext mayhavelinks="1" section="Decode" rep_section="decode">d = <a link="impl-shared.UInt.1" file="shared_pseudocode.xml" hover="function: integer UInt(bits(N) x)">UInt</a>(Rdn);  setflags = !<a link="impl-aarch32.InITBlock.0" file="shared_pseudocode.xml" hover="function: boolean InITBlock()">InITBlock</a>();</pstext>
+        </ps>
+      </ps_section>
+    </iclass>
+    <iclass name="T2" oneof="2" id="t2" no_encodings="1" isa="T32">
+      <docvars><docvar key="instr-class" value="general" /><docvar key="isa" value="T32" /></docvars>
+      <iclassintro count="1"></iclassintro>
+      <arch_variants><arch_variant name="ARMv6T2" /><arch_variant name="ARMv7" /></arch_variants>
+      <regdiagram form="16x2" psname="ADC_r_T2_A.txt" tworows="1">
+        <box hibit="31" width="7" settings="7"><c>1</c><c>1</c><c>1</c><c>0</c><c>1</c><c>0</c><c>1</c></box>
+        <box hibit="24" width="4" name="op" settings="4"><c>1</c><c>0</c><c>1</c><c>0</c></box>
+        <box hibit="20" width="1" name="S" usename="1"><c colspan="1"></c></box>
+        <box hibit="15" width="1" settings="1"><c>(0)</c></box>
+      </regdiagram>
+      <encoding name="ADC_r_T2" oneofinclass="1" oneof="2" label="T2">
+        <docvars><docvar key="mnemonic" value="ADC" /></docvars>
+        <asmtemplate><text>ADC</text></asmtemplate>
+      </encoding>
+    </iclass>
+  </classes>
+  <ps_section howmany="1">
+    <ps name="aarch32/instrs/ADC_r/ADC_r_T1_A.txt" mylink="execute" enclabels="" sections="1" secttype="noheading">
+      <pstext mayhavelinks="1" section="Execute" rep_section="execute">if d &lt; 8 &amp;&amp; setflags then
+    EncodingSpecificOperations();</pstext>
+    </ps>
+  </ps_section>
+</instructionsection>"#;
+
+    #[test]
+    fn test_t32_widths() {
+        let (instructions, warnings) = parse(T32_SPEC);
+        assert!(warnings.is_empty());
+        assert_eq!(instructions.len(), 2);
+
+        let narrow = &instructions[0];
+        assert_eq!((narrow.isa, narrow.width), (ir::Isa::T32, ir::Width::Halfword));
+        // 010000 0101 is moved to the first halfword, the second one is free.
+        assert_eq!(narrow.pattern[31], Some(ir::Bit::Zero));
+        assert_eq!(narrow.pattern[30], Some(ir::Bit::One));
+        assert_eq!(narrow.pattern[22], Some(ir::Bit::One));
+        assert!(narrow.pattern[..16].iter().all(Option::is_none));
+        let regions = narrow.regions.iter().map(|r| (r.label.as_ref(), r.range.clone())).collect::<Vec<_>>();
+        assert_eq!(regions, [("op", 22..26), ("Rm", 19..22), ("Rdn", 16..19)]);
+
+        // Links keep their text and entities are resolved, execute is shared by the section.
+        assert_eq!(narrow.pseudocode.decode.as_deref(), Some("d = UInt(Rdn);  setflags = !InITBlock();"));
+        assert_eq!(instructions[1].pseudocode.decode, None);
+        let execute = "if d < 8 && setflags then\n    EncodingSpecificOperations();";
+        assert!(instructions.iter().all(|i| i.pseudocode.execute.as_deref() == Some(execute)));
+
+        let wide = &instructions[1];
+        assert_eq!(wide.width, ir::Width::Word);
+        assert!(narrow.arch_variants.is_empty());
+        let versions = wide.arch_variants.iter().map(|v| v.version.unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(versions, ["ARMv6T2", "ARMv7.0"]);
+        assert_eq!(wide.pattern[31], Some(ir::Bit::One));
+        assert_eq!(wide.pattern[15], Some(ir::Bit::Zero));
+    }
+
+    const A64_SPEC: &str = r#"<instructionsection id="ORR_log_imm" title="ORR (immediate)" type="instruction">
+  <docvars><docvar key="isa" value="A64" /><docvar key="mnemonic" value="ORR" /></docvars>
+  <classes>
+    <iclass name="log_imm" oneof="1" id="iclass_log_imm" no_encodings="2" isa="A64">
+      <docvars><docvar key="instr-class" value="general" /><docvar key="isa" value="A64" /></docvars>
+      <regdiagram form="32" psname="aarch64/instrs/integer/logical/immediate/ORR_log_imm" tworows="1">
+        <box hibit="31" name="sf" usename="1"><c></c></box>
+        <box hibit="30" width="2" name="opc" settings="2"><c>0</c><c>1</c></box>
+        <box hibit="28" width="6" settings="6"><c>1</c><c>0</c><c>0</c><c>1</c><c>0</c><c>0</c></box>
+        <box hibit="22" name="N" usename="1"><c></c></box>
+        <box hibit="21" width="6" name="immr" usename="1"><c colspan="6"></c></box>
+        <box hibit="15" width="6" name="imms" usename="1"><c colspan="6"></c></box>
+        <box hibit="9" width="5" name="Rn" usename="1"><c colspan="5"></c></box>
+        <box hibit="4" width="5" name="Rd" usename="1"><c colspan="5"></c></box>
+      </regdiagram>
+      <encoding name="ORR_32_log_imm" oneofinclass="2" oneof="2" label="32-bit">
+        <docvars><docvar key="mnemonic" value="ORR" /><docvar key="datatype" value="32" /></docvars>
+        <box hibit="31" width="1" name="sf"><c>0</c></box>
+        <box hibit="22" width="1" name="N"><c>0</c></box>
+        <asmtemplate><text>ORR</text></asmtemplate>
+      </encoding>
+      <encoding name="ORR_64_log_imm" oneofinclass="2" oneof="2" label="64-bit">
+        <docvars><docvar key="mnemonic" value="ORR" /><docvar key="datatype" value="64" /></docvars>
+        <box hibit="31" width="1" name="sf"><c>1</c></box>
+        <asmtemplate><text>ORR</text></asmtemplate>
+      </encoding>
+    </iclass>
+  </classes>
+</instructionsection>"#;
+
+    #[test]
+    fn test_a64_encodings() {
+        let (instructions, warnings) = parse(A64_SPEC);
+        assert!(warnings.is_empty());
+        let names = instructions.iter().map(|i| i.name.as_ref()).collect::<Vec<_>>();
+        assert_eq!(names, ["ORR_32_log_imm", "ORR_64_log_imm"]);
+
+        let orr32 = &instructions[0];
+        assert_eq!((orr32.isa, orr32.width), (ir::Isa::A64, ir::Width::Word));
+        assert_eq!(orr32.pattern[31], Some(ir::Bit::Zero));
+        assert_eq!(orr32.pattern[22], Some(ir::Bit::Zero));
+        assert_eq!(orr32.pattern[29], Some(ir::Bit::One));
+        assert_eq!(instructions[1].pattern[31], Some(ir::Bit::One));
+        assert_eq!(instructions[1].pattern[22], None);
+
+        // Restated boxes are kept once, fields the encoding fixes are kept for the pseudocode.
+        let labels = |i: &ir::Instruction| i.regions.iter().map(|r| r.label.to_string()).collect::<Vec<_>>();
+        assert_eq!(labels(orr32), ["sf", "opc", "N", "immr", "imms", "Rn", "Rd"]);
+        assert_eq!(labels(&instructions[1]), labels(orr32));
+        assert_eq!(orr32.regions[3].range, 16..22);
+
+        // Section docvars come first, the iclass and encoding ones follow or override them.
+        let docvars = orr32.docvars.iter().collect::<Vec<_>>();
+        assert_eq!(docvars, [("isa", "A64"), ("mnemonic", "ORR"), ("instr-class", "general"), ("datatype", "32")]);
+        assert_eq!(instructions[1].docvars.get("datatype"), Some("64"));
+
+        // The same section restated as an alias adds nothing.
+        let alias = A64_SPEC.replacen(r#"type="instruction""#, r#"type="alias""#, 1);
+        assert!(parse(&alias).0.is_empty());
+    }
+
+    #[test]
+    fn test_field_constraints() {
+        // `immr<5:4> != 11` in the iclass, the 32-bit encoding fixes `immr<5>` and settles it.
+        let constrained = A64_SPEC
+            .replacen(
+                r#"name="immr" usename="1"><c colspan="6"></c>"#,
+                r#"name="immr" usename="1"><c colspan="2">!= 11</c><c colspan="4"></c>"#,
+                1
+            )
+            .replacen(r#"<box hibit="22" width="1" name="N"><c>0</c></box>"#, r#"<box hibit="21" width="1"><c>0</c></box>"#, 1);
+        let (instructions, warnings) = parse(&constrained);
+        assert!(warnings.is_empty());
+        assert!(instructions[0].field_constraints.is_empty());
+        assert_eq!(&*instructions[1].field_constraints, [ir::FieldConstraint { range: 20..=21, op: ir::FieldOp::Ne, value: 0b11 }]);
+        assert_eq!(instructions[1].pattern[21], None);
+        assert!(instructions[1].matches(0xB220_0000) && !instructions[1].matches(0xB230_0000));
+
+        // Bits at the end the value leaves free aren't compared, like `cond != 111x`.
+        let (instructions, _) = parse(&constrained.replacen("!= 11", "!= 1x", 1));
+        assert_eq!(&*instructions[1].field_constraints, [ir::FieldConstraint { range: 21..=21, op: ir::FieldOp::Ne, value: 1 }]);
+        let (_, warnings) = parse(&constrained.replacen(r#"colspan="2">!= 11"#, r#"colspan="3">!= 1x1"#, 1));
+        assert_eq!(&*warnings[0].error.message, "free bit between the compared ones of `!= 1x1`");
+    }
+
+    #[test]
+    fn test_problem_encodings() {
+        // A bit the parser doesn't know only costs the encoding it is in.
+        let broken = A64_SPEC.replacen(r#"name="sf"><c>1</c>"#, r#"name="sf"><c>q</c>"#, 1);
+        let (instructions, warnings) = parse(&broken);
+        assert_eq!(instructions.iter().map(|i| i.name.as_ref()).collect::<Vec<_>>(), ["ORR_32_log_imm"]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(&*warnings[0].dropped, "ORR_64_log_imm");
+        assert_eq!(&*warnings[0].error.path, "instructionsection/classes/iclass/encoding/box/c");
+        assert_eq!(&*warnings[0].error.message, "unexpected bit `q` in `q`");
+
+        // In the iclass diagram it costs all of them, a box past the diagram as well.
+        let broken = A64_SPEC.replacen(r#"<box hibit="22" name="N""#, r#"<box hibit="32" name="N""#, 1);
+        let (instructions, warnings) = parse(&broken);
+        assert!(instructions.is_empty());
+        assert_eq!(warnings.iter().map(|w| w.dropped.as_ref()).collect::<Vec<_>>(), ["ORR_32_log_imm", "ORR_64_log_imm"]);
+        assert!(warnings[0].error.message.contains("doesn't fit a 32 bit diagram"));
+
+        // Without the end of the file, the structure can't be trusted.
+        let truncated = &A64_SPEC[..A64_SPEC.find("<encoding name=\"ORR_64").unwrap()];
+        let mut reader = XmlReader::new(truncated.as_bytes(), Path::new("orr.xml"));
+        let error = parse_spec(&mut reader).unwrap_err();
+        assert_eq!(&*error.message, "unexpected end of file");
+        assert_eq!(error.position, truncated.len() as u64);
+        assert!(error.to_string().starts_with("orr.xml:"));
+    }
+
+    #[test]
+    fn test_see_redirects() {
+        let region = |label: &str, range| ir::BitRegion { label: Box::from(label), range };
+        let regions = [region("P", 24..25), region("W", 21..22), region("Rn", 16..20), region("imm12", 0..12)];
+        let decode = asl::parse_statements(concat!(
+            "t = UInt(Rt); imm32 = ZeroExtend(imm12, 32);\n",
+            "if P == '0' && W == '1' then SEE \"LDRT\";\n",
+            "if Rn IN {'1111', '1x10'} then SEE \"LDR (literal)\";\n",
+            "if t == 15 then SEE \"nothing the word decides\";\n",
+        )).unwrap();
+
+        let see = see_redirects(&decode, &regions)
+            .into_iter()
+            .map(|s| (s.mask, s.value, s.target.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(see, [
+            (0x0120_0000, 0x0020_0000, "LDRT".to_string()),
+            (0x000F_0000, 0x000F_0000, "LDR (literal)".to_string()),
+            (0x000B_0000, 0x000A_0000, "LDR (literal)".to_string()),
+        ]);
+
+        let index = r#"<encodingindex><table><tbody>
+            <tr class="instructiontable" encname="LDRT_A1" iformid="LDRT"/>
+            <tr class="instructiontable" encname="LDR_l_A1"><td>0</td></tr>
+        </tbody></table></encodingindex>"#;
+        let mut reader = XmlReader::new(index.as_bytes(), Path::new("a32_encindex.xml"));
+        assert_eq!(parse_encoding_index(&mut reader).unwrap(), [Box::from("LDRT_A1"), Box::from("LDR_l_A1")]);
+    }
+
+    #[test]
+    fn test_unpredictable_cases() {
+        let region = |label: &str, range| ir::BitRegion { label: Box::from(label), range };
+        let regions = [region("Rn", 16..20), region("Rt", 12..16)];
+        let decode = asl::parse_statements(concat!(
+            "t = UInt(Rt); n = UInt(Rn);\n",
+            "if t == 15 then UNPREDICTABLE;\n",
+            "if n == 15 || n == t then UNPREDICTABLE;\n",
+            "if Rt<0> == '1' && UInt(Rn) != 0 then UNPREDICTABLE;\n",
+            "if InITBlock() then UNPREDICTABLE;\n",
+        )).unwrap();
+
+        let xml = r#"<instructionsection><constrained_unpredictables encoding="LDRD_A1" ps_block="Decode">
+            <cu_case><cu_cause><pstext mayhavelinks="1">t == 15</pstext></cu_cause>
+              <cu_type constraint="Constraint_UNDEF"/><cu_type constraint="Constraint_NOP"/><cu_type constraint="Constraint_Unknown"/>
+            </cu_case>
+          </constrained_unpredictables></instructionsection>"#;
+        let mut reader = XmlReader::new(xml.as_bytes(), Path::new("ldrd.xml"));
+        while !matches!(reader.next(&mut vec![]).unwrap(), Event::Start(ref e) if e.name().as_ref() == b"constrained_unpredictables") {}
+        let cases = parse_constrained_unpredictables(&mut reader, Some("LDRD_A1".into())).unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!((cases[0].encoding.as_deref(), &*cases[0].cause), (Some("LDRD_A1"), "t == 15"));
+        assert_eq!(cases[0].constraints.len(), 3);
+
+        let mappings = r#"<constraint_text_mappings>
+            <constraint_text_mapping><constraint_name>Constraint_UNDEF</constraint_name><constraint_text>The instruction is
+              <arm-defined-word>UNDEFINED</arm-defined-word>.</constraint_text></constraint_text_mapping>
+            <constraint_text_mapping constraint="Constraint_NOP">The instruction executes as a NOP.</constraint_text_mapping>
+          </constraint_text_mappings>"#;
+        let mut reader = XmlReader::new(mappings.as_bytes(), Path::new("constraint_text_mappings.xml"));
+        let constraints = parse_constraint_mappings(&mut reader).unwrap();
+        assert_eq!(constraints.get("Constraint_UNDEF").map(|t| &**t), Some("The instruction is UNDEFINED."));
+        assert_eq!(constraints.get("Constraint_NOP").map(|t| &**t), Some("The instruction executes as a NOP."));
+
+        // `Constraint_Unknown` isn't mapped, the case keeps the two that are.
+        let known = cases[0].constraints.iter().filter_map(|id| Some(ir::Constraint { id: id.clone(), text: constraints.get(id)?.clone() }));
+        let constrained = [(asl::parse_expression(&cases[0].cause).unwrap(), known.collect())];
+        let cases = unpredictable_cases(&decode, &regions, &constrained)
+            .into_iter()
+            .map(|case| (case.mask, case.value, case.constraints.iter().map(|c| c.id.to_string()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let nonzero = |bit: u32| (0x1000 | 1 << bit, 0x1000 | 1 << bit, vec![]);
+        assert_eq!(cases, [
+            (0x0000_F000, 0x0000_F000, vec!["Constraint_UNDEF".to_string(), "Constraint_NOP".to_string()]),
+            // `n == t` isn't decided by the word, `n == 15` is enough.
+            (0x000F_0000, 0x000F_0000, vec![]),
+            // `Rt<0>` is bit 12, `Rn != 0` holds wherever one of its bits is set.
+            nonzero(16), nonzero(17), nonzero(18), nonzero(19),
+        ]);
+
+        // Once a nested statement may assign to `t` it no longer holds Rt.
+        let reassigned = asl::parse_statements(concat!(
+            "t = UInt(Rt); n = UInt(Rn);\n",
+            "if InITBlock() then t = 15;\n",
+            "for i = 0 to 3\n    (n, -) = Split(i);\n",
+            "if t == 15 || n == 15 then UNPREDICTABLE;\n",
+        )).unwrap();
+        assert!(unpredictable_cases(&reassigned, &regions, &[]).is_empty());
     }
 }
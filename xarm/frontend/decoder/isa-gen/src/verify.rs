@@ -0,0 +1,278 @@
+//! Checks a generated table against the encodings it was built from, word by word.
+//!
+//! The reference is as naive as it gets: every encoding is tried on every word. The table is
+//! only seen through a `decode` function returning the leaf of a word, one of the reserved
+//! `Descriptor` leaves or `Descriptor::RESERVED + n` for the `n`-th implemented encoding, as
+//! `LatencyOptimizedCodeEmitter` numbers them. A word one of its encodings calls UNPREDICTABLE
+//! has to decode as that case, one no encoding matches as UNDEFINED.
+
+use crate::ir;
+use isa_gen_nostd::Descriptor;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// An encoding reduced to what a word has to satisfy.
+struct Matcher<'a> {
+    mask: u32,
+    value: u32,
+    /// `!=` constraints, the word may not have `value` under `mask`.
+    excluded: Box<[(u32, u32)]>,
+    inst: &'a ir::Instruction,
+    implemented: bool
+}
+
+impl Matcher<'_> {
+    fn matches(&self, word: u32) -> bool {
+        word & self.mask == self.value && self.excluded.iter().all(|&(mask, value)| word & mask != value)
+    }
+}
+
+pub struct Reference<'a> {
+    matchers: Vec<Matcher<'a>>,
+    /// Index into `matchers` of every leaf from `Descriptor::RESERVED`.
+    leaves: Vec<usize>
+}
+
+impl<'a> Reference<'a> {
+    /// `all` the table was built over, `implemented` in leaf order. Words matching an encoding
+    /// that isn't implemented may decode as UNDEFINED.
+    pub fn new(all: &[&'a ir::Instruction], implemented: &[&'a ir::Instruction]) -> Self {
+        let matchers = all.iter().map(|inst| {
+            let (mask, value) = inst.fixed_bits();
+            Matcher {
+                mask,
+                value,
+                excluded: inst.field_constraints.iter().map(ir::FieldConstraint::mask).collect(),
+                inst,
+                implemented: implemented.iter().any(|i| std::ptr::eq(*i, *inst))
+            }
+        }).collect::<Vec<_>>();
+
+        let leaves = implemented.iter().map(|inst| {
+            matchers.iter().position(|m| std::ptr::eq(m.inst, *inst)).expect("implemented encodings are part of all")
+        }).collect();
+
+        Self { matchers, leaves }
+    }
+
+    /// The encodings `word` is an instance of.
+    pub fn matching(&self, word: u32) -> impl Iterator<Item = &'a ir::Instruction> + '_ {
+        self.matchers.iter().filter(move |m| m.matches(word)).map(|m| m.inst)
+    }
+
+    fn check(&self, word: u32, leaf: u16, report: &mut Report) {
+        report.checked += 1;
+        let matching = self.matchers.iter().filter(|m| m.matches(word));
+        let agrees = match leaf {
+            // Anything might decode as UNDEFINED but an implemented encoding of its own.
+            Descriptor::UNDEFINED => matching.clone().all(|m| !m.implemented),
+            // One of the implemented encodings the word is an instance of has a case of that kind.
+            leaf if leaf < Descriptor::RESERVED => matching.clone().any(|m| {
+                m.implemented && m.inst.unpredictable_case(word).is_some_and(|case| case.leaf() == leaf)
+            }),
+            leaf => match self.leaves.get((leaf - Descriptor::RESERVED) as usize) {
+                Some(&index) => {
+                    let decoded = &self.matchers[index];
+                    decoded.matches(word) && decoded.inst.unpredictable_case(word).is_none()
+                }
+                None => false
+            }
+        };
+
+        if !agrees {
+            let leaf = match leaf {
+                Descriptor::UNDEFINED => None,
+                leaf if leaf < Descriptor::RESERVED => Descriptor::reserved_name(leaf).map(Box::from),
+                leaf => match self.leaves.get((leaf - Descriptor::RESERVED) as usize) {
+                    Some(&index) => Some(self.matchers[index].inst.name.clone()),
+                    None => Some(format!("leaf {leaf}").into())
+                }
+            };
+            // Encodings calling the word UNPREDICTABLE say so.
+            let matching = matching.map(|m| match m.inst.unpredictable_case(word) {
+                Some(case) => format!("{} ({})", m.inst.name, Descriptor::reserved_name(case.leaf()).unwrap()).into(),
+                None => m.inst.name.clone()
+            });
+            report.disagreements.push(Disagreement { word, leaf, matching: matching.collect() });
+        }
+    }
+}
+
+/// The words to check.
+#[derive(Debug, Clone)]
+pub enum Words {
+    /// Every one of the 2^32.
+    All,
+    /// `per_stratum` random words inside every encoding and under every value of the top byte.
+    Sampled { per_stratum: u32, seed: u64 },
+    /// Part of them, e.g. to split an exhaustive run across machines.
+    Range(Range<u64>)
+}
+
+/// A word the table decodes as something none of its matching encodings is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disagreement {
+    pub word: u32,
+    /// `None` for UNDEFINED.
+    pub leaf: Option<Box<str>>,
+    pub matching: Box<[Box<str>]>
+}
+
+impl std::fmt::Display for Disagreement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#010x}: decodes as {}, ", self.word, self.leaf.as_deref().unwrap_or("UNDEFINED"))?;
+        match self.matching.is_empty() {
+            true => write!(f, "matches nothing"),
+            false => write!(f, "matches {}", self.matching.join(", "))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checked: u64,
+    /// Sorted by word.
+    pub disagreements: Vec<Disagreement>
+}
+
+impl Report {
+    fn merge(&mut self, other: Report) {
+        self.checked += other.checked;
+        self.disagreements.extend(other.disagreements);
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "checked {} words, {} disagree", self.checked, self.disagreements.len())?;
+        for disagreement in &self.disagreements {
+            write!(f, "\n{disagreement}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Words every thread takes at once.
+const CHUNK: u64 = 1 << 20;
+
+/// Fills the don't care bits of `mask`/`value`, xorshift keeps samples the same across runs.
+fn sample(state: &mut u64, mask: u32, value: u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state as u32 & !mask) | value
+}
+
+fn strata(reference: &Reference, per_stratum: u32, seed: u64) -> Vec<u32> {
+    let encodings = reference.matchers.iter().map(|m| (m.mask, m.value));
+    let top_bytes = (0..=0xFFu32).map(|byte| (0xFF00_0000, byte << 24));
+
+    let mut state = seed | 1;
+    encodings
+        .chain(top_bytes)
+        .flat_map(|(mask, value)| (0..per_stratum).map(move |_| (mask, value)))
+        .map(|(mask, value)| sample(&mut state, mask, value))
+        .collect()
+}
+
+/// Runs `decode` over `words` on every core, reporting each word whose leaf is not one of the
+/// encodings matching it.
+pub fn verify(reference: &Reference, words: Words, decode: impl Fn(u32) -> u16 + Sync) -> Report {
+    let (range, sampled) = match words {
+        Words::All => (0..1 << 32, None),
+        Words::Range(range) => (range.start..range.end.min(1 << 32), None),
+        Words::Sampled { per_stratum, seed } => {
+            let sampled = strata(reference, per_stratum, seed);
+            (0..sampled.len() as u64, Some(sampled))
+        }
+    };
+    let word = |i: u64| sampled.as_ref().map_or(i as u32, |s| s[i as usize]);
+
+    let next = AtomicU64::new(range.start);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut report = std::thread::scope(|scope| {
+        let workers = (0..threads).map(|_| scope.spawn(|| {
+            let mut report = Report::default();
+            loop {
+                let start = next.fetch_add(CHUNK, Ordering::Relaxed);
+                if start >= range.end {
+                    break report;
+                }
+                for i in start..(start + CHUNK).min(range.end) {
+                    let word = word(i);
+                    reference.check(word, decode(word), &mut report);
+                }
+            }
+        })).collect::<Vec<_>>();
+
+        workers.into_iter().fold(Report::default(), |mut report, worker| {
+            report.merge(worker.join().unwrap());
+            report
+        })
+    });
+
+    report.disagreements.sort_by_key(|d| d.word);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::strategies::latency::{graph, lut};
+    use crate::ir::fixtures::instruction;
+
+    fn walk(pool: &[isa_gen_nostd::Entry], root: u16, word: u32) -> u16 {
+        isa_gen_nostd::Entry::walk(pool, root, word)
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut instructions = [
+            instruction("CONDITIONAL", "NNNN 0000 xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("UNCONDITIONAL", "1111 0000 xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("GATED", "xxxx 0001 xxxx xxxx xxxx xxxx xxxx xxxx"),
+            instruction("SPECIAL", "xxxx 0010 0000 xxxx xxxx xxxx xxxx xxxx"),
+            instruction("GENERAL", "xxxx 0010 xxxx xxxx xxxx xxxx xxxx xxxx"),
+        ];
+        instructions[4].unpredictable = Box::new([ir::Unpredictable { mask: 0xF, value: 0xF, constraints: Box::new([]) }]);
+        let all = instructions.iter().collect::<Vec<_>>();
+        let implemented = [&instructions[0], &instructions[1], &instructions[3], &instructions[4]];
+        let (pool, root) = lut::build(&implemented, graph::build(&all, &Default::default()).unwrap(), &Default::default());
+
+        let reference = Reference::new(&all, &implemented);
+        assert_eq!(reference.matching(0x1200_0000).map(|i| &*i.name).collect::<Vec<_>>(), ["SPECIAL", "GENERAL"]);
+
+        let report = verify(&reference, Words::Sampled { per_stratum: 64, seed: 1 }, |word| walk(&pool, root, word));
+        assert_eq!(report.checked, 64 * (5 + 256));
+        assert!(report.disagreements.is_empty(), "{report}");
+
+        let report = verify(&reference, Words::Range(0x1000_0000..0x1040_0000), |word| walk(&pool, root, word));
+        assert_eq!(report.checked, 0x0040_0000);
+        assert!(report.disagreements.is_empty(), "{report}");
+
+        // A table mixing up the special case is caught on every word of it.
+        let broken = |word| match walk(&pool, root, word) {
+            leaf if leaf == Descriptor::RESERVED + 2 => Descriptor::RESERVED + 1,
+            leaf => leaf
+        };
+        let report = verify(&reference, Words::Range(0x1200_0000..0x1210_0000), broken);
+        assert_eq!(report.disagreements.len(), 0x0010_0000);
+        assert_eq!(
+            report.disagreements[0].to_string(),
+            "0x12000000: decodes as UNCONDITIONAL, matches SPECIAL, GENERAL"
+        );
+        let undefined = verify(&reference, Words::Range(0x1000_0000..0x1000_0001), |_| 0);
+        assert_eq!(undefined.disagreements[0].to_string(), "0x10000000: decodes as UNDEFINED, matches CONDITIONAL");
+
+        // GENERAL calls words ending in 1111 UNPREDICTABLE, decoding them as GENERAL is caught.
+        let general = 0x1230_000F;
+        assert_eq!(walk(&pool, root, general), Descriptor::UNPREDICTABLE);
+        let words = Words::Range(general as u64..general as u64 + 1);
+        let ignored = verify(&reference, words, |_| Descriptor::RESERVED + 3);
+        assert_eq!(ignored.disagreements[0].to_string(), "0x1230000f: decodes as GENERAL, matches GENERAL (UNPREDICTABLE)");
+
+        // No encoding has 0011 in bits 27..24, decoding such a word as one is caught.
+        let unallocated = verify(&reference, Words::Range(0x0300_0000..0x0300_0001), |_| Descriptor::RESERVED);
+        assert_eq!(unallocated.disagreements[0].to_string(), "0x03000000: decodes as CONDITIONAL, matches nothing");
+    }
+}
@@ -0,0 +1,27 @@
+//! A64 decoding, generated from the AArch64 release.
+
+pub mod _generated {
+    #![allow(non_camel_case_types)]
+
+    core::include!(core::concat!(env!("OUT_DIR"), "/a64.rs"));
+}
+
+pub use _generated::InstructionView;
+
+#[cfg(feature = "semantics")]
+mod _execute {
+    #![allow(non_snake_case, unused, unreachable_code, clippy::all)]
+
+    use super::InstructionView;
+    core::include!(core::concat!(env!("OUT_DIR"), "/a64_execute.rs"));
+}
+
+/// The leaf of the encoding `word` decodes as, see `crate::classify`.
+///
+/// # Safety
+///
+/// As for `crate::scalar_decode`, with the `pext` feature the CPU has to support BMI2.
+#[inline(always)]
+pub unsafe fn scalar_decode(word: u32) -> Result<u16, crate::DecodeError> {
+    unsafe { crate::classify(walk_generated!(word)) }
+}
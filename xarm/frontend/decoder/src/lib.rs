@@ -1,7 +1,37 @@
 //#![cfg_attr(not(test), no_std)]
+// `scalar_decode` stays unsafe for the table walks, the portable `decode` has nothing unchecked.
+#![cfg_attr(not(target_arch = "x86_64"), allow(unused_unsafe))]
+
+use core::hint::black_box;
+#[cfg(target_arch = "x86_64")]
+use core::arch;
+use isa_gen_nostd::Descriptor;
+use isa_gen_nostd::{Entry, Word};
+#[cfg(all(target_arch = "x86_64", feature = "pext"))]
+use isa_gen_nostd::PextNode;
+
+/// Walks the tables of the `_generated` module in scope with the walker of their strategy.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+macro_rules! walk_generated {
+    ($word:expr) => {
+        crate::walk(&_generated::ENTRIES, _generated::ROOT_INDEX, $word)
+    };
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "pext"))]
+macro_rules! walk_generated {
+    ($word:expr) => {
+        crate::walk_pext(&_generated::NODES, &_generated::CHILDREN, _generated::ROOT_INDEX, $word)
+    };
+}
 
-use core::{arch, hint::black_box};
-use isa_gen_nostd::{Descriptor, Entry};
+// Elsewhere the tree is generated as plain Rust, see `PortableCodeEmitter`.
+#[cfg(not(target_arch = "x86_64"))]
+macro_rules! walk_generated {
+    ($word:expr) => {
+        _generated::decode($word)
+    };
+}
 
 pub mod _generated {
     #![allow(non_camel_case_types)]
@@ -9,9 +39,48 @@ pub mod _generated {
     core::include!(core::concat!(env!("OUT_DIR"), "/a32.rs"));
 }
 
+#[cfg(feature = "semantics")]
+mod _execute {
+    #![allow(non_snake_case, unused, unreachable_code, clippy::all)]
+
+    use super::InstructionView;
+    core::include!(core::concat!(env!("OUT_DIR"), "/a32_execute.rs"));
+}
+
+pub mod t32;
+#[cfg(feature = "a64")]
+pub mod a64;
+#[cfg(feature = "semantics")]
+pub mod semantics;
+pub mod tables;
+/// The portable backend built next to the tables, to check one against the other on x86_64.
+#[cfg(all(target_arch = "x86_64", feature = "portable"))]
+pub mod portable {
+    pub mod a32 {
+        #![allow(non_camel_case_types)]
+
+        core::include!(core::concat!(env!("OUT_DIR"), "/a32_portable.rs"));
+    }
+
+    pub mod t32 {
+        #![allow(non_camel_case_types)]
+
+        core::include!(core::concat!(env!("OUT_DIR"), "/t32_portable.rs"));
+    }
+
+    #[cfg(feature = "a64")]
+    pub mod a64 {
+        #![allow(non_camel_case_types)]
+
+        core::include!(core::concat!(env!("OUT_DIR"), "/a64_portable.rs"));
+    }
+}
+
 pub use _generated::InstructionView;
+#[cfg(target_arch = "x86_64")]
 use arch::x86_64::*;
 
+#[cfg(target_arch = "x86_64")]
 #[inline(never)]
 unsafe fn debug_zmm(val: __m512i, label: &str) {
     let bytes: [u8; 64] = unsafe { std::mem::transmute(val) };
@@ -30,6 +99,7 @@ unsafe fn debug_zmm(val: __m512i, label: &str) {
     println!("-------------------------------------------------------\n");
 }
 
+#[cfg(target_arch = "x86_64")]
 #[inline(never)]
 unsafe fn debug_ymm(val: __m256i, label: &str) {
     let bytes: [u8; 32] = unsafe { std::mem::transmute(val) };
@@ -48,6 +118,7 @@ unsafe fn debug_ymm(val: __m256i, label: &str) {
     println!("-------------------------------------------------------\n");
 }
 
+#[cfg(target_arch = "x86_64")]
 #[inline(never)]
 unsafe fn debug_xmm(val: __m128i, label: &str) {
     let bytes: [u8; 16] = unsafe { std::mem::transmute(val) };
@@ -63,8 +134,9 @@ unsafe fn debug_xmm(val: __m128i, label: &str) {
     println!("-------------------------------------------------------\n");
 }
 
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
 macro_rules! emit_lut_lookup {
-    ($reg:expr, $lane:expr, $lane_idx:expr, $ndx:expr) => {
+    ($reg:expr, $lane:expr, $lane_idx:expr, $broadcast:expr, $ndx:expr) => {
         concat!(
             // Each iteration just needs to know its index and word.
             // A lot of the cycles are spent on getting that information.
@@ -74,7 +146,8 @@ macro_rules! emit_lut_lookup {
 
             // values = VAND(word, bitmasks)
             "vextracti32x4 {zmm_temp:x}, {words:z}, ", $lane, "\n",
-            "vpshufd {zmm_temp:x}, {zmm_temp:x}, ", $lane_idx, "\n",
+            // Every dword takes the word of the lane, 0x55 * lane_idx.
+            "vpshufd {zmm_temp:x}, {zmm_temp:x}, ", $broadcast, "\n",
             "vpandd {values:x}, {", $reg, ":x}, {zmm_temp:x}\n",
 
             //"valignd {zmm_temp:y}, {z", $idx, ":y}, {z", $idx, ":y}, 4\n"
@@ -96,7 +169,9 @@ macro_rules! emit_lut_lookup {
     }
 }
 
-#[inline(always)]
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+#[inline]
+#[target_feature(enable = "avx512f,avx512bw,avx512vl")]
 unsafe fn semi_vectorized_step(words: __m512i, indices: __m512i) -> __m256i {
     // words, indices -> u32x16
 
@@ -110,35 +185,36 @@ unsafe fn semi_vectorized_step(words: __m512i, indices: __m512i) -> __m256i {
 
         arch::asm!(
             // this should obviously use a cleaner macro
-            emit_lut_lookup!("cache_line", 0, 0, 0b1),
-            emit_lut_lookup!("cache_line", 0, 1, 0b10),
-            emit_lut_lookup!("cache_line", 0, 2, 0b100),
-            emit_lut_lookup!("cache_line", 0, 3, 0b1000),
+            emit_lut_lookup!("cache_line", 0, 0, 0x00, 0b1),
+            emit_lut_lookup!("cache_line", 0, 1, 0x55, 0b10),
+            emit_lut_lookup!("cache_line", 0, 2, 0xAA, 0b100),
+            emit_lut_lookup!("cache_line", 0, 3, 0xFF, 0b1000),
 
             "valignd {indices}, {indices}, {indices}, 4",
-            emit_lut_lookup!("cache_line", 1, 0, 0b10000),
-            emit_lut_lookup!("cache_line", 1, 1, 0b100000),
-            emit_lut_lookup!("cache_line", 1, 2, 0b1000000),
-            emit_lut_lookup!("cache_line", 1, 3, 0b10000000),
+            emit_lut_lookup!("cache_line", 1, 0, 0x00, 0b10000),
+            emit_lut_lookup!("cache_line", 1, 1, 0x55, 0b100000),
+            emit_lut_lookup!("cache_line", 1, 2, 0xAA, 0b1000000),
+            emit_lut_lookup!("cache_line", 1, 3, 0xFF, 0b10000000),
 
             "valignd {indices}, {indices}, {indices}, 4",
 
-            emit_lut_lookup!("cache_line", 2, 0, 0b100000000),
-            emit_lut_lookup!("cache_line", 2, 1, 0b1000000000),
-            emit_lut_lookup!("cache_line", 2, 2, 0b10000000000),
-            emit_lut_lookup!("cache_line", 2, 3, 0b100000000000),
+            emit_lut_lookup!("cache_line", 2, 0, 0x00, 0b100000000),
+            emit_lut_lookup!("cache_line", 2, 1, 0x55, 0b1000000000),
+            emit_lut_lookup!("cache_line", 2, 2, 0xAA, 0b10000000000),
+            emit_lut_lookup!("cache_line", 2, 3, 0xFF, 0b100000000000),
 
             "valignd {indices}, {indices}, {indices}, 4",
 
-            emit_lut_lookup!("cache_line", 3, 0, 0b1000000000000),
-            emit_lut_lookup!("cache_line", 3, 1, 0b10000000000000),
-            emit_lut_lookup!("cache_line", 3, 2, 0b100000000000000),
-            emit_lut_lookup!("cache_line", 3, 3, 0b1000000000000000),
+            emit_lut_lookup!("cache_line", 3, 0, 0x00, 0b1000000000000),
+            emit_lut_lookup!("cache_line", 3, 1, 0x55, 0b10000000000000),
+            emit_lut_lookup!("cache_line", 3, 2, 0xAA, 0b100000000000000),
+            emit_lut_lookup!("cache_line", 3, 3, 0xFF, 0b1000000000000000),
             
             result = out(zmm_reg) result,
 
             table = in(reg) &_generated::ENTRIES,
-            indices = in(zmm_reg) indices,
+            // Rotated as the lanes are walked.
+            indices = inout(zmm_reg) indices => _,
             words = in(zmm_reg) words,
 
             offset = out(reg) _,
@@ -155,25 +231,38 @@ unsafe fn semi_vectorized_step(words: __m512i, indices: __m512i) -> __m256i {
     }
 }
 
+/// The leaf of `word` in tables of any geometry, monomorphised for the one generated. Every
+/// `Entry` picks its descriptor from all of its compares at once, lookups and branches alike.
+/// Leaves fit a `u16` whatever `W`, `lut::Pool::build` and `Tables::load` reject more.
 #[inline(always)]
-pub unsafe fn scalar_decode(word: u32) -> u16 {
+unsafe fn walk<const MASKS: usize, const WAYS: usize, W: Word>(entries: &[Entry<MASKS, WAYS, W>], root: W, word: u32) -> u16 {
+    unsafe { Entry::walk_unchecked(entries, root, word).to_usize() as u16 }
+}
+
+/// Whether `entries` are the cache lines `simd_decode` and the AVX2 gathers load whole.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+const fn cache_line<const MASKS: usize, const WAYS: usize, W: Word>(_: &[Entry<MASKS, WAYS, W>]) -> bool {
+    Entry::<MASKS, WAYS, W>::CACHE_LINE
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "pext"))]
+#[inline]
+#[target_feature(enable = "bmi2")]
+unsafe fn walk_pext(nodes: &[PextNode], children: &[Descriptor], root: u16, word: u32) -> u16 {
     unsafe {
-        let mut fentry = _generated::ENTRIES.get_unchecked(_generated::ROOT_INDEX as usize);
+        let mut node = nodes.get_unchecked(root as usize);
 
         loop {
-            // This looks dumb but is a relatively good solution to solve handling
-            // branch and lookup cases in a way thats branchless
-
-            // Sweet compiler vectorized it for me
-            let e1 = ((fentry.bitmasks[0] & word == fentry.expected[0]) as u8) << 0;
-            let e2 = ((fentry.bitmasks[1] & word == fentry.expected[1]) as u8) << 1;
-            let e3 = ((fentry.bitmasks[2] & word == fentry.expected[2]) as u8) << 2;
-            let e4 = ((fentry.bitmasks[3] & word == fentry.expected[3]) as u8) << 3;
-            let idx = (e1 | e2 | e3 | e4) as usize;
+            // Branches only pick between two children, lookups between as many as their mask has bits.
+            let index = if node.compare {
+                (word & node.mask == node.value) as u32
+            } else {
+                _pext_u32(word, node.mask)
+            };
 
-            let descriptor = fentry.entries[idx];
+            let descriptor = *children.get_unchecked((node.base + index) as usize);
             if descriptor.0 & Descriptor::TAG_ENTRY == Descriptor::TAG_ENTRY {
-                fentry = _generated::ENTRIES.get_unchecked((descriptor.0 & Descriptor::MASK_DATA) as usize);
+                node = nodes.get_unchecked((descriptor.0 & Descriptor::MASK_DATA) as usize);
             } else {
                 return descriptor.0;
             }
@@ -181,47 +270,331 @@ pub unsafe fn scalar_decode(word: u32) -> u16 {
     }
 }
 
+/// Why a word decodes as none of the encodings, one per reserved leaf of `Descriptor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecodeError {
+    /// Unallocated, or an encoding the target profile leaves out.
+    Undefined,
+    /// The decode pseudocode of its encoding calls the word UNPREDICTABLE.
+    Unpredictable,
+    /// UNPREDICTABLE, with the behaviour constrained to the choices the specification lists.
+    ConstrainedUnpredictable
+}
+
+impl DecodeError {
+    /// The reserved leaf it is decoded from.
+    pub const fn leaf(self) -> u16 {
+        match self {
+            DecodeError::Undefined => Descriptor::UNDEFINED,
+            DecodeError::Unpredictable => Descriptor::UNPREDICTABLE,
+            DecodeError::ConstrainedUnpredictable => Descriptor::CONSTRAINED_UNPREDICTABLE
+        }
+    }
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match Descriptor::reserved_name(self.leaf()) {
+            Some(name) => f.write_str(name),
+            None => unreachable!()
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// `leaf` as returned by `decode_batch` or `tables::Tables::decode`, `Ok` for the leaf of an
+/// encoding. Its `InstructionView` has the same discriminant.
 #[inline(always)]
-pub unsafe fn simd_decode(words: __m512i) -> __m256i {
-    // TODO: down side of the SIMD approach is one long guest instruction can clog the function.
-    // But if we truly optimize the step function we can just introduce pipelining guest instructions in
-    // and handing out window views.
-    //
-    // And after we do all that work, we have complete control over ILP, while if we were to use
-    // the scalar version the CPU would do all that work itself.
+pub const fn classify(leaf: u16) -> Result<u16, DecodeError> {
+    match leaf {
+        Descriptor::UNDEFINED => Err(DecodeError::Undefined),
+        Descriptor::UNPREDICTABLE => Err(DecodeError::Unpredictable),
+        Descriptor::CONSTRAINED_UNPREDICTABLE => Err(DecodeError::ConstrainedUnpredictable),
+        leaf => Ok(leaf)
+    }
+}
 
-    unsafe {
-        todo!("Implement the loop, correctly save the mask's state.");
+#[inline(always)]
+unsafe fn scalar_leaf(word: u32) -> u16 {
+    unsafe { walk_generated!(word) }
+}
+
+/// The leaf of the encoding `word` decodes as, see [`classify`].
+///
+/// # Safety
+///
+/// With the `pext` feature the CPU has to support BMI2, the tables are walked with `PEXT`.
+#[inline(always)]
+pub unsafe fn scalar_decode(word: u32) -> Result<u16, DecodeError> {
+    classify(unsafe { scalar_leaf(word) })
+}
+
+/// Byte offsets of the entries the `pending` lanes of `descriptors` point to, the root for the rest.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+#[inline]
+#[target_feature(enable = "avx512f,avx512bw,avx512vl")]
+fn entry_offsets(descriptors: __m256i, pending: __mmask16, root: __m512i) -> __m512i {
+    let data = _mm256_and_si256(descriptors, _mm256_set1_epi16(Descriptor::MASK_DATA as i16));
+    _mm512_mask_blend_epi32(pending, root, _mm512_slli_epi32::<6>(_mm512_cvtepu16_epi32(data)))
+}
 
-        // First iteration can obviously be optimized.
-        let mut entries = semi_vectorized_step(words, _mm512_set1_epi32(_generated::ROOT_INDEX as i32 * 0x40));
-        // Entry flag.
-        let mask_entry = _mm256_set1_epi16(1 << 15);
+/// Decodes 16 words at once, every lane takes as many steps as the deepest of them.
+/// Lanes at their leaf are masked, the step walks them through the root but keeps their leaf.
+///
+/// # Safety
+///
+/// The CPU has to support AVX512F, AVX512BW and AVX512VL, see `decode_batch` for a checked version.
+///
+/// # Panics
+///
+/// When the tables weren't generated with the default geometry of `Entry`.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+#[inline]
+#[target_feature(enable = "avx512f,avx512bw,avx512vl")]
+pub unsafe fn simd_decode(words: __m512i) -> __m256i {
+    assert!(cache_line(&_generated::ENTRIES), "the SIMD walk loads 64-byte entries of 16 `u16` descriptors");
+    unsafe {
+        let root = _mm512_set1_epi32(_generated::ROOT_INDEX as i32 * 0x40);
+        let tag = _mm256_set1_epi16(Descriptor::TAG_ENTRY as i16);
 
+        let mut descriptors = semi_vectorized_step(words, root);
         loop {
-            let entries_mask = _mm256_test_epi16_mask(entries, mask_entry);
+            let pending = _mm256_test_epi16_mask(descriptors, tag);
+            if pending == 0 {
+                return descriptors;
+            }
 
-            //debug_ymm(entries, "SIMD DECODE");
-            //println!("Mask: {:016b}", mask);
+            let next = semi_vectorized_step(words, entry_offsets(descriptors, pending, root));
+            descriptors = _mm256_mask_blend_epi16(pending, descriptors, next);
         }
+    }
+}
 
-        entries
+/// The lanes set in `mask`, lowest first.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+fn lanes(mask: __mmask16) -> impl Iterator<Item = usize> {
+    (0..16).filter(move |lane| mask & 1 << lane != 0)
+}
+
+/// [`decode_batch`] over 16 lanes. A lane reaching its leaf takes the next word right away,
+/// a deep encoding only holds up its own lane instead of the other 15.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+#[target_feature(enable = "avx512f,avx512bw,avx512vl,bmi2")]
+unsafe fn decode_batch_avx512(words: &[u32], leaves: &mut [u16]) {
+    let root = _mm512_set1_epi32(_generated::ROOT_INDEX as i32 * 0x40);
+    let tag = _mm256_set1_epi16(Descriptor::TAG_ENTRY as i16);
+
+    let mut lane_words = _mm512_setzero_si512();
+    let mut offsets = root;
+    let mut positions = [0; 16];
+    let mut pending: __mmask16 = 0;
+    let mut next = 0;
+    loop {
+        // Lanes that aren't walking, as many of them as there are words left, start on the next words.
+        let free = !pending as u32 & 0xFFFF;
+        let count = (free.count_ones() as usize).min(words.len() - next);
+        let refill = _pdep_u32((1 << count) - 1, free) as __mmask16;
+        lane_words = unsafe { _mm512_mask_expandloadu_epi32(lane_words, refill, words.as_ptr().add(next).cast()) };
+        for (i, lane) in lanes(refill).enumerate() {
+            positions[lane] = next + i;
+        }
+        next += count;
+
+        pending |= refill;
+        if pending == 0 {
+            return;
+        }
+
+        let descriptors = unsafe { semi_vectorized_step(lane_words, offsets) };
+        let walking = _mm256_mask_test_epi16_mask(pending, descriptors, tag);
+        let done = pending & !walking;
+        if done != 0 {
+            let mut found = [0u16; 16];
+            unsafe { _mm256_storeu_si256(found.as_mut_ptr().cast(), descriptors) };
+            for lane in lanes(done) {
+                leaves[positions[lane]] = found[lane];
+            }
+        }
+
+        pending = walking;
+        offsets = entry_offsets(descriptors, walking, root);
+    }
+}
+
+/// Descriptors the entries at `offsets` pick for the words of 8 lanes, `walk` with gathers.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn gathered_step(words: __m256i, offsets: __m256i) -> __m256i {
+    let table = _generated::ENTRIES.as_ptr().cast::<i32>();
+    let one = _mm256_set1_epi32(1);
+
+    let mut index = _mm256_setzero_si256();
+    for i in 0..4 {
+        let (bitmasks, expected) = unsafe {
+            (_mm256_i32gather_epi32::<1>(table.add(i), offsets), _mm256_i32gather_epi32::<1>(table.add(4 + i), offsets))
+        };
+        let hit = _mm256_cmpeq_epi32(_mm256_and_si256(bitmasks, words), expected);
+        index = _mm256_or_si256(index, _mm256_and_si256(hit, _mm256_set1_epi32(1 << i)));
+    }
+
+    // Descriptors are gathered in pairs, a dword at the last descriptor would read past the entry.
+    let pairs = _mm256_add_epi32(offsets, _mm256_slli_epi32::<1>(_mm256_andnot_si256(one, index)));
+    let pair = unsafe { _mm256_i32gather_epi32::<1>(table.add(8), pairs) };
+    let shift = _mm256_slli_epi32::<4>(_mm256_and_si256(index, one));
+    _mm256_and_si256(_mm256_srlv_epi32(pair, shift), _mm256_set1_epi32(0xFFFF))
+}
+
+/// [`decode_batch`] over 8 lanes, refilled the same way as with AVX-512 but one lane at a time.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+#[target_feature(enable = "avx2")]
+unsafe fn decode_batch_avx2(words: &[u32], leaves: &mut [u16]) {
+    let root = _generated::ROOT_INDEX as u32 * 0x40;
+
+    let mut lane_words = [0u32; 8];
+    let mut offsets = [root; 8];
+    let mut positions = [None; 8];
+    let mut next = 0;
+    loop {
+        for lane in 0..8 {
+            if positions[lane].is_none() && next < words.len() {
+                (lane_words[lane], offsets[lane], positions[lane]) = (words[next], root, Some(next));
+                next += 1;
+            }
+        }
+        if positions.iter().all(Option::is_none) {
+            return;
+        }
+
+        let mut descriptors = [0u32; 8];
+        unsafe {
+            let step = gathered_step(
+                _mm256_loadu_si256(lane_words.as_ptr().cast()),
+                _mm256_loadu_si256(offsets.as_ptr().cast())
+            );
+            _mm256_storeu_si256(descriptors.as_mut_ptr().cast(), step);
+        }
+
+        for lane in 0..8 {
+            let Some(position) = positions[lane] else { continue };
+            let descriptor = descriptors[lane] as u16;
+            if descriptor & Descriptor::TAG_ENTRY == Descriptor::TAG_ENTRY {
+                offsets[lane] = (descriptor & Descriptor::MASK_DATA) as u32 * 0x40;
+            } else {
+                leaves[position] = descriptor;
+                positions[lane] = None;
+            }
+        }
     }
 }
 
+/// Whether the CPU runs `simd_decode` and `decode_batch_avx512`.
+#[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+fn avx512() -> bool {
+    is_x86_feature_detected!("avx512f")
+        && is_x86_feature_detected!("avx512bw")
+        && is_x86_feature_detected!("avx512vl")
+        && is_x86_feature_detected!("bmi2")
+}
+
+/// The leaves of A32 `words` as `scalar_decode` walks to them, left for [`classify`]. With
+/// AVX-512 when the CPU has it, AVX2 when it doesn't and one word at a time without either.
+///
+/// The `pext` tables, entries wider than a cache line and other targets are only walked one word
+/// at a time, with `pext` the CPU has to support BMI2 as for `scalar_decode`.
+pub fn decode_batch(words: &[u32]) -> Vec<u16> {
+    let mut leaves = vec![Descriptor::UNDEFINED; words.len()];
+
+    #[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+    if cache_line(&_generated::ENTRIES) {
+        if avx512() {
+            unsafe { decode_batch_avx512(words, &mut leaves) };
+            return leaves;
+        }
+        if is_x86_feature_detected!("avx2") {
+            unsafe { decode_batch_avx2(words, &mut leaves) };
+            return leaves;
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "pext"))]
+    assert!(is_x86_feature_detected!("bmi2"), "the pext tables need BMI2");
+
+    for (leaf, word) in leaves.iter_mut().zip(words) {
+        *leaf = unsafe { scalar_leaf(*word) };
+    }
+    leaves
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_test() {
+    fn test_scalar_decode() {
+        // ADD r0, r0, r1
+        let leaf = unsafe { scalar_decode(0xE080_0001) }.unwrap();
+        let view = unsafe { core::mem::transmute::<u16, _generated::InstructionView>(leaf) };
+        assert!(matches!(view, _generated::InstructionView::ADD_r_A1), "{view:?}");
+    }
 
-        unsafe {
-        //dbg!(core::mem::transmute::<u16, t::InstructionView>(hotpath_decode(0xE5932008)));
-        //dbg!(core::mem::transmute::<u16, t::InstructionView>(hotpath_decode(0b11110001000000010000001000000000)));
-        dbg!(core::mem::transmute::<u16, _generated::InstructionView>(hotpath_decode_1(0b11100001010000000000000001110000)));
+    /// Words spread over the whole space, xorshift keeps them the same across runs.
+    pub(crate) fn words() -> impl Iterator<Item = u32> {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        (0..1 << 20).map(move |_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u32
+        })
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "portable"))]
+    #[test]
+    fn test_portable_equivalence() {
+        for word in words() {
+            unsafe {
+                assert_eq!(scalar_decode(word), classify(portable::a32::decode(word)), "A32 {word:#010x}");
+
+                let (hw1, hw2) = ((word >> 16) as u16, word as u16);
+                let (leaf, size) = t32::scalar_decode(hw1, hw2);
+                let thumb = if size == 4 { word } else { word & 0xFFFF_0000 };
+                assert_eq!(leaf, classify(portable::t32::decode(thumb)), "T32 {thumb:#010x}");
+
+                #[cfg(feature = "a64")]
+                assert_eq!(a64::scalar_decode(word), classify(portable::a64::decode(word)), "A64 {word:#010x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let words = words().collect::<Vec<_>>();
+        let expected = words.iter().map(|word| unsafe { scalar_leaf(*word) }).collect::<Vec<_>>();
+        // Batches the lanes don't divide, refills stop halfway through.
+        for len in [0, 1, 7, 17, 1000, words.len()] {
+            assert_eq!(decode_batch(&words[..len]), expected[..len]);
         }
-        panic!()
 
+        // Every path the CPU has, not only the one dispatched to.
+        #[cfg(all(target_arch = "x86_64", not(feature = "pext")))]
+        unsafe {
+            if !cache_line(&_generated::ENTRIES) {
+                return;
+            }
+            if is_x86_feature_detected!("avx2") {
+                let mut leaves = vec![0; words.len()];
+                decode_batch_avx2(&words, &mut leaves);
+                assert_eq!(leaves, expected);
+            }
+            if avx512() {
+                for (words, expected) in words.chunks_exact(16).zip(expected.chunks_exact(16)).take(1 << 12) {
+                    let mut leaves = [0u16; 16];
+                    _mm256_storeu_si256(leaves.as_mut_ptr().cast(), simd_decode(_mm512_loadu_si512(words.as_ptr().cast())));
+                    assert_eq!(leaves, expected);
+                }
+            }
+        }
     }
 }
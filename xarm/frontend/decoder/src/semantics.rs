@@ -0,0 +1,22 @@
+//! Execution of decoded instructions, generated from the ASL pseudocode of the specification.
+//!
+//! Every `InstructionView` gets an `execute(cpu, word)` that runs the decode and execute
+//! pseudocode of its encoding against a [`Cpu`]. Pseudocode the generator doesn't support
+//! returns [`Exception::Unsupported`], the build script lists it in `semantics_report.txt`.
+
+pub use isa_gen_nostd::semantics::*;
+
+/// The shared pseudocode of the AArch32 release, used by A32 and T32.
+pub mod aarch32 {
+    #![allow(non_snake_case, unused, unreachable_code, clippy::all)]
+
+    core::include!(core::concat!(env!("OUT_DIR"), "/aarch32_library.rs"));
+}
+
+/// The shared pseudocode of the AArch64 release.
+#[cfg(feature = "a64")]
+pub mod aarch64 {
+    #![allow(non_snake_case, unused, unreachable_code, clippy::all)]
+
+    core::include!(core::concat!(env!("OUT_DIR"), "/a64_library.rs"));
+}
@@ -0,0 +1,45 @@
+//! T32 decoding.
+//!
+//! Thumb instructions are either one or two halfwords long, the table is built over
+//! `(hw1 << 16) | hw2` with 16-bit encodings occupying the first halfword only.
+
+pub mod _generated {
+    #![allow(non_camel_case_types)]
+
+    core::include!(core::concat!(env!("OUT_DIR"), "/t32.rs"));
+}
+
+pub use _generated::InstructionView;
+
+#[cfg(feature = "semantics")]
+mod _execute {
+    #![allow(non_snake_case, unused, unreachable_code, clippy::all)]
+
+    use super::InstructionView;
+    core::include!(core::concat!(env!("OUT_DIR"), "/t32_execute.rs"));
+}
+
+/// Whether `hw1` is the first halfword of a 32-bit encoding (`0b11101`, `0b11110`, `0b11111`).
+#[inline(always)]
+pub const fn is_wide(hw1: u16) -> bool {
+    (hw1 >> 11) > 0b11100
+}
+
+/// Decodes the instruction starting at `hw1`, returning the leaf as `crate::scalar_decode` does and
+/// the instruction size in bytes.
+///
+/// `hw2` is ignored for 16-bit encodings.
+///
+/// # Safety
+///
+/// As for `crate::scalar_decode`, with the `pext` feature the CPU has to support BMI2.
+#[inline(always)]
+pub unsafe fn scalar_decode(hw1: u16, hw2: u16) -> (Result<u16, crate::DecodeError>, usize) {
+    let (word, size) = if is_wide(hw1) {
+        (((hw1 as u32) << 16) | hw2 as u32, 4)
+    } else {
+        ((hw1 as u32) << 16, 2)
+    };
+
+    unsafe { (crate::classify(walk_generated!(word)), size) }
+}
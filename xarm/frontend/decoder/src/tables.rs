@@ -0,0 +1,326 @@
+//! Decode tables loaded at runtime from an artifact instead of compiled in, so tools can switch
+//! between releases and profiles without a rebuild.
+//!
+//! `LatencyOptimizedCodeEmitter::emit_artifact` writes them, with the `artifacts` feature the
+//! build script writes one per instruction set next to the generated code. The leaves are those
+//! of the profile the artifact was built for, not of `InstructionView`, the artifact names them.
+
+use std::borrow::Cow;
+use isa_gen_nostd::{Descriptor, Entry, Word};
+use isa_gen_nostd::artifact::{self, Header, Isa};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// Shorter than its header says, or than a header.
+    Truncated { expected: usize, found: usize },
+    /// Not an artifact at all.
+    Magic,
+    Version(u16),
+    Isa(u8),
+    /// Entries of another geometry than the `Tables` loading them, see `Header::is_entry`.
+    Geometry { masks: u8, descriptor: u8 },
+    Root(usize),
+    /// A descriptor of an entry pointing past the pool or the leaves.
+    Descriptor { entry: usize, slot: usize },
+    /// The entries of the pool lead back to `entry`, a walk would never end.
+    Cycle { entry: usize },
+    /// The name table doesn't hold as many UTF-8 names as there are leaves.
+    Names,
+    /// More leaves than a `u16` numbers after the reserved ones.
+    Leaves(usize)
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated { expected, found } => write!(f, "expected {expected} bytes, found {found}"),
+            Self::Magic => write!(f, "not a decode table artifact"),
+            Self::Version(version) => write!(f, "version {version} is not supported, expected {}", artifact::VERSION),
+            Self::Isa(isa) => write!(f, "unknown instruction set {isa}"),
+            Self::Geometry { masks, descriptor } => {
+                write!(f, "entries of {masks} masks and {descriptor} byte descriptors don't match the tables")
+            }
+            Self::Root(root) => write!(f, "root entry {root} is outside the pool"),
+            Self::Descriptor { entry, slot } => write!(f, "descriptor {slot} of entry {entry} is out of range"),
+            Self::Cycle { entry } => write!(f, "entry {entry} is part of a cycle"),
+            Self::Names => write!(f, "malformed name table"),
+            Self::Leaves(leaves) => write!(f, "{leaves} leaves, at most {} fit a u16", u16::MAX - Descriptor::RESERVED)
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A validated pool, walked as the compiled in tables are. The geometry is that of the `Entry`
+/// the pool is made of, the default one unless the artifact was built with a wider budget:
+/// `<Tables>::load(bytes)` or `Tables::<8, 256, u32>::load(bytes)`.
+#[derive(Debug, Clone)]
+pub struct Tables<'a, const MASKS: usize = 4, const WAYS: usize = 16, W: Word = u16> {
+    isa: Isa,
+    entries: Cow<'a, [Entry<MASKS, WAYS, W>]>,
+    root: W,
+    names: Box<[Box<str>]>
+}
+
+impl<'a, const MASKS: usize, const WAYS: usize, W: Word> Tables<'a, MASKS, WAYS, W> {
+    /// Validates `bytes` as an artifact of this geometry. The entries are borrowed when `bytes` is
+    /// aligned to an `Entry` on a little endian host, copied otherwise.
+    pub fn load(bytes: &'a [u8]) -> Result<Self, LoadError> {
+        let truncated = |expected| LoadError::Truncated { expected, found: bytes.len() };
+        let header = bytes.first_chunk::<{ artifact::HEADER_LEN }>().ok_or(truncated(artifact::HEADER_LEN))?;
+        let header = Header::from_bytes(header).ok_or(LoadError::Magic)?;
+        if header.version != artifact::VERSION {
+            return Err(LoadError::Version(header.version));
+        }
+        let isa = Isa::from_u8(header.isa).ok_or(LoadError::Isa(header.isa))?;
+        if !header.is_entry::<MASKS, WAYS, W>() {
+            return Err(LoadError::Geometry { masks: header.masks, descriptor: header.descriptor });
+        }
+        if bytes.len() < header.artifact_len() {
+            return Err(truncated(header.artifact_len()));
+        }
+
+        let pool_len = header.entries as usize * header.entry_len();
+        let pool = &bytes[artifact::HEADER_LEN..][..pool_len];
+        let entries = match pool.as_ptr().align_offset(align_of::<Entry<MASKS, WAYS, W>>()) == 0 && cfg!(target_endian = "little") {
+            // `Entry` is `repr(C)` integers padded to `Entry::LEN`, as the artifact lays it out.
+            // Any bytes are a valid one, the padding is never read.
+            true => Cow::Borrowed(unsafe { std::slice::from_raw_parts(pool.as_ptr().cast(), header.entries as usize) }),
+            false => Cow::Owned(
+                pool.chunks_exact(Entry::<MASKS, WAYS, W>::LEN)
+                    .map(Entry::from_le_bytes)
+                    .collect()
+            )
+        };
+
+        let names = &bytes[artifact::HEADER_LEN + pool_len..][..header.names_len as usize];
+        let names = std::str::from_utf8(names).map_err(|_| LoadError::Names)?;
+        let names = match names.strip_suffix('\0') {
+            Some(names) => names.split('\0').map(Box::from).collect::<Box<[_]>>(),
+            None if names.is_empty() => Box::default(),
+            None => return Err(LoadError::Names)
+        };
+        if names.len() != header.leaves as usize {
+            return Err(LoadError::Names);
+        }
+
+        let root = W::from_usize(header.root as usize).ok_or(LoadError::Root(header.root as usize))?;
+        let tables = Self { isa, entries, root, names };
+        tables.validate()?;
+        Ok(tables)
+    }
+
+    /// Every descriptor in range and no cycles, what `decode` relies on to skip the checks.
+    fn validate(&self) -> Result<(), LoadError> {
+        if self.names.len() > (u16::MAX - Descriptor::RESERVED) as usize {
+            return Err(LoadError::Leaves(self.names.len()));
+        }
+        let root = self.root.to_usize();
+        if root >= self.entries.len() {
+            return Err(LoadError::Root(root));
+        }
+
+        for (entry, e) in self.entries.iter().enumerate() {
+            for (slot, descriptor) in e.entries.iter().enumerate() {
+                let limit = match descriptor.is_entry() {
+                    true => self.entries.len(),
+                    false => self.names.len() + Descriptor::RESERVED as usize
+                };
+                if descriptor.data() >= limit {
+                    return Err(LoadError::Descriptor { entry, slot });
+                }
+            }
+        }
+
+        // Depth first from the root, an entry met again while still on the stack closes a cycle.
+        #[derive(Clone, Copy, PartialEq)]
+        enum State { New, Open, Done }
+        let mut states = vec![State::New; self.entries.len()];
+        let mut stack = vec![(root, 0)];
+        states[root] = State::Open;
+        while let Some((entry, slot)) = stack.pop() {
+            let Some(descriptor) = self.entries[entry].entries.get(slot) else {
+                states[entry] = State::Done;
+                continue;
+            };
+            stack.push((entry, slot + 1));
+            if descriptor.is_entry() {
+                let next = descriptor.data();
+                match states[next] {
+                    State::Open => return Err(LoadError::Cycle { entry: next }),
+                    State::Done => {}
+                    State::New => {
+                        states[next] = State::Open;
+                        stack.push((next, 0));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Tables over borrowed or owned `entries`, e.g. `&_generated::ENTRIES[..]`, leaf `n` being
+    /// named `names[n - Descriptor::RESERVED]`.
+    pub fn from_entries(isa: Isa, entries: impl Into<Cow<'a, [Entry<MASKS, WAYS, W>]>>, root: W, names: &[&str]) -> Result<Self, LoadError> {
+        let names = names.iter().map(|name| Box::from(*name)).collect();
+        let tables = Self { isa, entries: entries.into(), root, names };
+        tables.validate()?;
+        Ok(tables)
+    }
+
+    /// Owns its entries, to outlive the bytes it was loaded from.
+    pub fn into_owned(self) -> Tables<'static, MASKS, WAYS, W> {
+        Tables { isa: self.isa, entries: Cow::Owned(self.entries.into_owned()), root: self.root, names: self.names }
+    }
+
+    pub fn isa(&self) -> Isa {
+        self.isa
+    }
+
+    pub fn entries(&self) -> &[Entry<MASKS, WAYS, W>] {
+        &self.entries
+    }
+
+    /// Leaves besides the reserved ones.
+    pub fn leaves(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The encoding of `leaf`, `None` for the reserved leaves.
+    pub fn name(&self, leaf: u16) -> Option<&str> {
+        let index = (leaf as usize).checked_sub(Descriptor::RESERVED as usize);
+        index.and_then(|index| self.names.get(index)).map(|name| &**name)
+    }
+
+    /// The leaf of `word`, see `crate::classify`. T32 words are laid out as for `t32::scalar_decode`.
+    pub fn decode(&self, word: u32) -> u16 {
+        // Validated on load, every descriptor is in range and every walk reaches a leaf.
+        unsafe { crate::walk(&self.entries, self.root, word) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isa_gen::emitter::strategies::latency::artifact::write;
+    use isa_gen::ir;
+
+    const R: u16 = Descriptor::RESERVED;
+
+    /// A branch on bit 24 into a lookup on bits 0 and 1.
+    fn pool() -> Vec<Entry> {
+        let mut branch = Entry { bitmasks: [1 << 24, 0, 0, 0], expected: [1 << 24, 1, 1, 1], ..Default::default() };
+        branch.entries[0] = Descriptor::new_leaf(R);
+        branch.entries[1] = Descriptor::new_entry(1);
+
+        let mut lookup = Entry { bitmasks: [1, 2, 0, 0], expected: [1, 2, 1, 1], ..Default::default() };
+        lookup.entries[..4].copy_from_slice(&[R + 1, R + 2, 0, R + 2].map(Descriptor::new_leaf));
+        vec![branch, lookup]
+    }
+
+    #[test]
+    fn test_load() {
+        let bytes = write(ir::Isa::A64, &pool(), 0, &["ADD", "SUB", "MUL"]);
+        let tables = <Tables>::load(&bytes).unwrap();
+        assert_eq!(tables.isa(), Isa::A64);
+        assert_eq!(tables.leaves(), 3);
+        assert_eq!([0, 1 << 24, 1 << 24 | 1, 1 << 24 | 2].map(|word| tables.decode(word)), [R, R + 1, R + 2, 0]);
+        assert_eq!((tables.name(0), tables.name(2), tables.name(R + 2), tables.name(R + 3)), (None, None, Some("MUL"), None));
+
+        // Straight from an aligned buffer nothing is copied.
+        let mut aligned = vec![<Entry>::default(); bytes.len().div_ceil(artifact::ENTRY_LEN)];
+        let buffer = unsafe { std::slice::from_raw_parts_mut(aligned.as_mut_ptr().cast::<u8>(), bytes.len()) };
+        buffer.copy_from_slice(&bytes);
+        let borrowed = <Tables>::load(buffer).unwrap();
+        assert!(matches!(borrowed.entries, Cow::Borrowed(_)));
+        assert_eq!(borrowed.decode(1 << 24 | 1), R + 2);
+
+        let mut unaligned = vec![0; bytes.len() + 1];
+        unaligned[1..].copy_from_slice(&bytes);
+        let owned = <Tables>::load(&unaligned[1..]).unwrap().into_owned();
+        assert!(matches!(owned.entries, Cow::Owned(_)));
+        assert_eq!(owned.decode(1 << 24 | 1), R + 2);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let load = |pool: &[Entry], root, names: &[&str]| <Tables>::load(&write(ir::Isa::A32, pool, root, names)).map(|_| ());
+        let bytes = write(ir::Isa::A32, &pool(), 0, &["ADD", "SUB", "MUL"]);
+
+        assert_eq!(<Tables>::load(&bytes[..10]).unwrap_err(), LoadError::Truncated { expected: 64, found: 10 });
+        assert_eq!(<Tables>::load(&bytes[..bytes.len() - 1]).unwrap_err(), LoadError::Truncated { expected: bytes.len(), found: bytes.len() - 1 });
+        assert_eq!(<Tables>::load(&[0; 64]).unwrap_err(), LoadError::Magic);
+        let mut patched = bytes.clone();
+        patched[4] = 7;
+        assert_eq!(<Tables>::load(&patched).unwrap_err(), LoadError::Version(7));
+        patched[4] = artifact::VERSION as u8;
+        patched[6] = 9;
+        assert_eq!(<Tables>::load(&patched).unwrap_err(), LoadError::Isa(9));
+
+        assert_eq!(load(&pool(), 2, &["ADD", "SUB", "MUL"]), Err(LoadError::Root(2)));
+        assert_eq!(load(&pool(), 0, &["ADD", "SUB"]), Err(LoadError::Descriptor { entry: 1, slot: 1 }));
+        let mut cyclic = pool();
+        cyclic[1].entries[3] = Descriptor::new_entry(0);
+        assert_eq!(load(&cyclic, 0, &["ADD", "SUB", "MUL"]), Err(LoadError::Cycle { entry: 0 }));
+        // Shared entries are fine, only a way back isn't.
+        let mut shared = pool();
+        shared[0].entries[0] = Descriptor::new_entry(1);
+        assert_eq!(load(&shared, 0, &["ADD", "SUB", "MUL"]), Ok(()));
+
+        assert_eq!(<Tables>::from_entries(Isa::A32, pool(), 0, &["ADD", "SUB", "MUL"]).unwrap().decode(1 << 24 | 2), 0);
+        assert_eq!(<Tables>::from_entries(Isa::A32, &pool()[..1], 0, &["ADD"]).unwrap_err(), LoadError::Descriptor { entry: 0, slot: 1 });
+        // Leaves are walked to a `u16` whatever the descriptors.
+        let names = vec!["ADD"; (u16::MAX - R) as usize + 1];
+        assert_eq!(<Tables>::from_entries(Isa::A32, pool(), 0, &names).unwrap_err(), LoadError::Leaves(names.len()));
+
+        let mut unnamed = bytes.clone();
+        unnamed.pop();
+        unnamed.push(b'X');
+        assert_eq!(<Tables>::load(&unnamed).unwrap_err(), LoadError::Names);
+    }
+
+    #[test]
+    fn test_geometry() {
+        // The same pool in entries of 8 masks and `u32` descriptors, the masks added never match.
+        let wide = pool().iter().map(|e| {
+            let mut wide = Entry::<8, 256, u32> { expected: [1; 8], ..Default::default() };
+            wide.bitmasks[..4].copy_from_slice(&e.bitmasks);
+            wide.expected[..4].copy_from_slice(&e.expected);
+            for (to, from) in wide.entries.iter_mut().zip(&e.entries) {
+                *to = from.to_width().unwrap();
+            }
+            wide
+        }).collect::<Vec<_>>();
+        let bytes = write(ir::Isa::A32, &wide, 0, &["ADD", "SUB", "MUL"]);
+        let words = [0, 1 << 24, 1 << 24 | 1, 1 << 24 | 2];
+
+        let tables = Tables::<8, 256, u32>::load(&bytes).unwrap();
+        assert_eq!(tables.entries(), &wide[..]);
+        assert_eq!(words.map(|word| tables.decode(word)), [R, R + 1, R + 2, 0]);
+        let mut unaligned = vec![0; bytes.len() + 1];
+        unaligned[1..].copy_from_slice(&bytes);
+        let owned = Tables::<8, 256, u32>::load(&unaligned[1..]).unwrap();
+        assert!(matches!(owned.entries, Cow::Owned(_)));
+        assert_eq!(words.map(|word| owned.decode(word)), [R, R + 1, R + 2, 0]);
+
+        assert_eq!(<Tables>::load(&bytes).unwrap_err(), LoadError::Geometry { masks: 8, descriptor: 4 });
+        let narrow = write(ir::Isa::A32, &pool(), 0, &["ADD", "SUB", "MUL"]);
+        assert_eq!(Tables::<8, 256, u32>::load(&narrow).unwrap_err(), LoadError::Geometry { masks: 4, descriptor: 2 });
+    }
+
+    #[cfg(all(feature = "artifacts", not(feature = "pext")))]
+    #[test]
+    fn test_built_artifact() {
+        let tables = <Tables>::load(include_bytes!(concat!(env!("OUT_DIR"), "/a32.xdt"))).unwrap();
+        assert_eq!(tables.isa(), Isa::A32);
+        for word in crate::tests::words() {
+            let leaf = tables.decode(word);
+            assert_eq!(crate::classify(leaf), unsafe { crate::scalar_decode(word) }, "{word:#010x}");
+            let view = unsafe { core::mem::transmute::<u16, crate::InstructionView>(leaf) };
+            match tables.name(leaf) {
+                Some(name) => assert_eq!(name, format!("{view:?}")),
+                None => assert!(leaf < Descriptor::RESERVED)
+            }
+        }
+    }
+}